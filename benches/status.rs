@@ -0,0 +1,131 @@
+//! Throughput of status exchanges and of status frame coding.
+//!
+//! Run with `cargo bench --bench status`.
+
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use futures::{executor::block_on, future, io::Cursor};
+
+use libp2p::{
+    PeerId,
+    Swarm,
+    Transport,
+    core::{multiaddr::multiaddr, transport::memory::MemoryTransport, upgrade},
+    identity::Keypair,
+    mplex::MplexConfig,
+    secio::SecioConfig,
+};
+
+use rand::{thread_rng, Rng};
+
+use dx::behaviour::{Behaviour, BehaviourConfig};
+use dx::node::{self, DxNode, DxSwarm, NodeEvent};
+use dx::status::{Gossip, StatusConfig, generate_payload, protocol::{self, ResolvedStatus, Status}};
+
+/// Numbers of gossip entries the frame benchmarks are run with
+const GOSSIP: [usize; 4] = [0, 16, 64, 255];
+
+/// Swarm over memory transport that requests statuses as fast as they are answered
+fn swarm() -> DxSwarm {
+    let key = Keypair::generate_ed25519();
+    let id = key.public().into_peer_id();
+
+    let transport = node::boxed_transport(MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(SecioConfig::new(key))
+        .multiplex(MplexConfig::new()));
+
+    let status = StatusConfig::new(generate_payload())
+        .with_keep_alive(true)
+        .with_interval(Duration::new(0, 0))
+        .with_inbound_rate(None);
+    let config = BehaviourConfig::new(status)
+        .with_mdns(false)
+        .with_bootstrap(Vec::new());
+
+    Swarm::new(transport, Behaviour::with_config(id.clone(), config), id)
+}
+
+/// Status frame with the given number of gossip entries
+fn frame(gossip: usize) -> ResolvedStatus {
+    let gossip = (0..gossip)
+        .map(|_| Gossip { peer: PeerId::random(), payload: generate_payload(), age: Duration::from_secs(60) })
+        .collect();
+
+    Status::new(generate_payload())
+        .with_gossip(gossip)
+        .with_name(Some("bench".into()))
+        .with_epoch(Some(1))
+        .resolve()
+}
+
+fn exchange(c: &mut Criterion) {
+    c.bench_function("status exchange", |b| b.iter_custom(|iters| {
+        let mut b = DxNode::new(swarm());
+        let b_id = Swarm::local_peer_id(b.swarm()).clone();
+        let b_addr = multiaddr![Memory(thread_rng().gen::<u64>())];
+        b.listen_on(b_addr.clone()).unwrap();
+
+        let mut a = DxNode::new(swarm());
+        a.swarm().add_peer_with_address(b_id.clone(), b_addr);
+        a.swarm().dial_peer(b_id.clone());
+
+        // Connection setup is part of the measurement, but amortized over many exchanges
+        let start = Instant::now();
+
+        let until_done = a.run(Some(Duration::from_millis(1)), |swarm, event| match event {
+            NodeEvent::Tick => swarm.status_stats(&b_id).map_or(0, |stats| u64::from(stats.received)) < iters,
+            _ => true,
+        });
+
+        block_on(async {
+            let drive_b = b.run(None, |_, _| true);
+            futures::pin_mut!(until_done, drive_b);
+            future::select(until_done, drive_b).await;
+        });
+
+        start.elapsed()
+    }));
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("status encode");
+
+    for gossip in GOSSIP.iter() {
+        let status = frame(*gossip);
+
+        let mut buffer = Cursor::new(Vec::new());
+        block_on(protocol::write_status(&mut buffer, &status)).unwrap();
+        group.throughput(Throughput::Bytes(buffer.get_ref().len() as u64));
+
+        group.bench_with_input(BenchmarkId::from_parameter(gossip), &status, |b, status| b.iter(|| {
+            let mut buffer = Cursor::new(Vec::new());
+            block_on(protocol::write_status(&mut buffer, status)).unwrap();
+            buffer
+        }));
+    }
+
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("status decode");
+
+    for gossip in GOSSIP.iter() {
+        let mut buffer = Cursor::new(Vec::new());
+        block_on(protocol::write_status(&mut buffer, &frame(*gossip))).unwrap();
+        let bytes = buffer.into_inner();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+
+        group.bench_with_input(BenchmarkId::from_parameter(gossip), &bytes, |b, bytes| b.iter(|| {
+            block_on(protocol::read_response(&mut Cursor::new(bytes))).unwrap()
+        }));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, exchange, encode, decode);
+criterion_main!(benches);
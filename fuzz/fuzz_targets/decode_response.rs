@@ -0,0 +1,77 @@
+//! Feeds arbitrary bytes to the status frame decoder, which must neither
+//! panic nor allocate more than a frame field can hold.
+//!
+//! Run with `cargo fuzz run decode_response` from the repository root.
+
+#![no_main]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use libfuzzer_sys::fuzz_target;
+
+use dx::status::protocol::{self, Gossip, Status, MAX_FIELD_LEN};
+
+/// Allocator keeping track of the largest allocation
+struct Tracking;
+
+static LARGEST: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Tracking {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LARGEST.fetch_max(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        LARGEST.fetch_max(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Tracking = Tracking;
+
+/// Largest buffer the decoder may need, either a field or the gossip list
+fn allocation_limit() -> usize {
+    let gossip = u8::max_value() as usize * mem::size_of::<Gossip>();
+    if gossip > MAX_FIELD_LEN { gossip } else { MAX_FIELD_LEN }
+}
+
+fuzz_target!(|data: &[u8]| {
+    LARGEST.store(0, Ordering::Relaxed);
+    let decoded = protocol::decode_response(data);
+    let largest = LARGEST.load(Ordering::Relaxed);
+
+    assert!(largest <= allocation_limit(), "Decoding allocated {} bytes", largest);
+
+    // Whatever decodes must survive being sent again
+    let response = match decoded {
+        Ok(response) => response,
+        Err(_) => return,
+    };
+
+    let status = Status::new(response.payload)
+        .with_gossip(response.gossip.clone())
+        .with_signature(response.signature.clone())
+        .with_name(response.name.clone())
+        .with_epoch(response.epoch)
+        .with_schema(response.schema.clone())
+        .with_capabilities(response.capabilities);
+
+    let mut frame = Vec::new();
+    futures::executor::block_on(protocol::write_status(&mut frame, &status)).unwrap();
+
+    let again = protocol::decode_response(&frame).expect("Re-encoded frame fails to decode");
+    assert_eq!(again.payload, response.payload);
+    assert_eq!(again.gossip.len(), response.gossip.len());
+    assert_eq!(again.name, response.name);
+    assert_eq!(again.epoch, response.epoch);
+    assert_eq!(again.schema, response.schema);
+    assert_eq!(again.capabilities, response.capabilities);
+});
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{fs, io};
+
+use libp2p::{Multiaddr, PeerId};
+
+use serde::{Deserialize, Serialize};
+
+use crate::behaviour::Behaviour;
+use crate::trust::TrustStore;
+
+
+/// On-disk format of the address book
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct RawBook {
+    #[serde(default)]
+    peers: Vec<RawEntry>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct RawEntry {
+    id: String,
+    address: String,
+    /// Seconds since the Unix epoch
+    seen: u64,
+}
+
+/// Addresses of peers learned while running, e.g. via mDNS or Kademlia,
+/// persisted alongside the trust store so that they are known right away
+/// after a restart, e.g.:
+///
+/// ```toml
+/// [[peers]]
+/// id = "QmNnooDu7bfjPFoTZYxMNLWUQJyrVwtbZg5gBMjTezGAJN"
+/// address = "/ip4/192.0.2.1/tcp/4001"
+/// seen = 1583020800
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddressBook {
+    /// Addresses of every peer, with when they were last known to be valid
+    peers: HashMap<PeerId, Vec<(Multiaddr, SystemTime)>>,
+}
+
+/// Seconds since the Unix epoch, rounded down
+fn to_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+impl AddressBook {
+    /// Returns default address book path, next to the trust store
+    pub fn path() -> PathBuf {
+        TrustStore::path().join("addresses.toml")
+    }
+
+    /// Load address book from given path, dropping addresses not seen within
+    /// the TTL. A missing file is an empty address book.
+    pub fn load(path: &Path, ttl: Duration) -> io::Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(AddressBook::default()),
+            Err(error) => return Err(error),
+        };
+
+        let raw: RawBook = toml::from_str(&text)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+        let oldest = to_secs(SystemTime::now()).saturating_sub(ttl.as_secs());
+        let mut book = AddressBook::default();
+
+        // Entries that can not be interpreted are as good as stale
+        for entry in raw.peers.into_iter().filter(|entry| entry.seen >= oldest) {
+            if let (Ok(peer), Ok(address)) = (entry.id.parse(), entry.address.parse()) {
+                book.insert(peer, address, UNIX_EPOCH + Duration::from_secs(entry.seen));
+            }
+        }
+
+        Ok(book)
+    }
+
+    /// Save address book to given path
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut peers: Vec<RawEntry> = self.peers.iter()
+            .flat_map(|(peer, addrs)| addrs.iter().map(move |(address, seen)| RawEntry {
+                id: peer.to_base58(),
+                address: address.to_string(),
+                seen: to_secs(*seen),
+            }))
+            .collect();
+        peers.sort_by(|a, b| (&a.id, &a.address).cmp(&(&b.id, &b.address)));
+
+        let text = toml::to_string(&RawBook { peers })
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+        fs::write(path, text)
+    }
+
+    /// Remember the address of the peer, last seen at the given time
+    pub fn insert(&mut self, peer: PeerId, address: Multiaddr, seen: SystemTime) {
+        let addrs = self.peers.entry(peer).or_insert_with(Vec::new);
+
+        match addrs.iter_mut().find(|(known, _)| *known == address) {
+            Some(entry) => entry.1 = std::cmp::max(entry.1, seen),
+            None => addrs.push((address, seen)),
+        }
+    }
+
+    /// Known addresses of the peer
+    pub fn addresses(&self, peer: &PeerId) -> Vec<&Multiaddr> {
+        self.peers.get(peer).map_or(Vec::new(), |addrs| addrs.iter().map(|(address, _)| address).collect())
+    }
+
+    /// Number of peers with known addresses
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    /// Tell Kademlia about all known addresses, without watching the peers
+    pub fn seed(&self, behaviour: &mut Behaviour) {
+        for (peer, addrs) in self.peers.iter() {
+            for (address, _) in addrs {
+                behaviour.add_address(peer, address.clone());
+            }
+        }
+    }
+
+    /// Remember the addresses currently known for all watched peers
+    pub fn update(&mut self, behaviour: &mut Behaviour) {
+        let now = SystemTime::now();
+
+        for info in behaviour.all_peer_info() {
+            for address in behaviour.addresses_of(info.id()) {
+                self.insert(info.id().clone(), address, now);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AddressBook;
+    use crate::behaviour::{Behaviour, BehaviourConfig};
+    use crate::status::{StatusConfig, generate_payload};
+    use libp2p::{Multiaddr, PeerId, identity::Keypair};
+    use rand::{thread_rng, Rng};
+    use std::{env, fs, time::{Duration, SystemTime}};
+
+    fn behaviour() -> Behaviour {
+        let id = Keypair::generate_ed25519().public().into_peer_id();
+        let config = BehaviourConfig::new(StatusConfig::new(generate_payload()))
+            .with_mdns(false)
+            .with_bootstrap(Vec::new());
+
+        Behaviour::with_config(id, config)
+    }
+
+    #[test]
+    fn persist_addresses() {
+        let dir = env::temp_dir().join(format!("dx-test-{}", thread_rng().gen::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("addresses.toml");
+        let ttl = Duration::from_secs(24 * 60 * 60);
+
+        // Missing file is no error
+        assert!(AddressBook::load(&path, ttl).unwrap().is_empty());
+
+        // Learn addresses of a watched peer while running
+        let fresh = PeerId::random();
+        let fresh_addr: Multiaddr = "/ip4/192.0.2.1/tcp/4001".parse().unwrap();
+        let mut running = behaviour();
+        running.add_peer_with_address(fresh.clone(), fresh_addr.clone());
+
+        let mut book = AddressBook::default();
+        book.update(&mut running);
+        assert_eq!(book.addresses(&fresh), vec![&fresh_addr]);
+
+        let stale = PeerId::random();
+        let stale_addr: Multiaddr = "/ip4/192.0.2.2/tcp/4001".parse().unwrap();
+        book.insert(stale.clone(), stale_addr, SystemTime::now() - 2 * ttl);
+
+        book.save(&path).unwrap();
+
+        // Only addresses seen within the TTL survive a restart
+        let loaded = AddressBook::load(&path, ttl).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded.addresses(&fresh), vec![&fresh_addr]);
+        assert!(loaded.addresses(&stale).is_empty());
+
+        // And are known to Kademlia right away
+        let mut restarted = behaviour();
+        assert!(restarted.addresses_of(&fresh).is_empty());
+        loaded.seed(&mut restarted);
+        assert_eq!(restarted.addresses_of(&fresh), vec![fresh_addr]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,10 +1,22 @@
+use std::cmp;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::num::NonZeroUsize;
 use std::time::{Duration, Instant};
-use std::sync::Mutex;
+use std::task::{Context, Poll};
+
+use futures::prelude::*;
 
 use libp2p::{
+    Multiaddr,
     PeerId,
     NetworkBehaviour,
-    swarm::NetworkBehaviourEventProcess,
+    core::ConnectedPoint,
+    swarm::{
+        NetworkBehaviour as _,
+        NetworkBehaviourAction,
+        NetworkBehaviourEventProcess,
+        toggle::Toggle,
+    },
     kad::{
         Kademlia,
         KademliaConfig,
@@ -14,12 +26,28 @@ use libp2p::{
     mdns::{Mdns, MdnsEvent},
 };
 
+use log::{debug, info, trace, warn};
+
+use rand::thread_rng;
+
+
+use crate::clock::{SharedClock, Timer};
+use crate::connection::{Connections, ConnectionEvent};
+use crate::dht::{Dht, KademliaMode};
+use crate::reconnect::ReconnectConfig;
+use crate::transport::{AddressPreference, RACE_DELAY, SecurityTable};
+use crate::topology::Topology;
 use crate::status::{
+    Capabilities,
+    PendingResults,
     Status,
     StatusConfig,
     StatusEvent,
+    StatusFailure,
     StatusSuccess,
+    StatusStats,
     Payload,
+    VERSION,
 };
 
 
@@ -33,11 +61,242 @@ const BOOTSTRAP_NODES : [(&str, &str); 5] = [
     ("QmNnooDu7bfjPFoTZYxMNLWUQJyrVwtbZg5gBMjTezGAJN", "/ip4/147.75.69.143/tcp/4001"),
 ];
 
-/// Returned events by behavior (unused)
-enum Event {
-    PeerOffline,
-    PeerOnline,
-    PeerStatus,
+/// Application specific check whether the payload received from a peer is
+/// acceptable, see [`Behaviour::set_payload_validator`]
+pub type PayloadValidator = Box<dyn Fn(&PeerId, &Payload) -> bool + Send>;
+
+/// Application specific comparison of two payloads, e.g. only of their
+/// version bytes, see [`Behaviour::set_payload_comparator`]
+pub type PayloadComparator = Box<dyn Fn(&Payload, &Payload) -> Compatibility + Send>;
+
+/// How two payloads relate, as decided by a [`PayloadComparator`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compatibility {
+    /// Payloads are identical
+    Equal,
+    /// Payloads differ, but peers reporting them are considered in agreement
+    Compatible,
+    /// Payloads differ and peers reporting them are not in agreement
+    Incompatible,
+}
+
+impl Compatibility {
+    /// Byte equality of payloads, the default comparison
+    pub fn of(a: &Payload, b: &Payload) -> Self {
+        if a == b { Compatibility::Equal } else { Compatibility::Incompatible }
+    }
+
+    /// Whether peers reporting the payloads are in agreement
+    pub fn matches(self) -> bool {
+        self != Compatibility::Incompatible
+    }
+}
+
+/// Bound of peer scores, so that a peer's past does not outweigh its present
+const MAX_SCORE: i32 = 10;
+
+/// Number of most recent status exchanges the quality of a peer is based on
+const QUALITY_WINDOW: usize = 16;
+
+/// Round trip time up to which a peer is considered perfectly responsive
+const QUALITY_RTT_GOOD: Duration = Duration::from_millis(50);
+
+/// Round trip time from which on a peer is considered unresponsive
+const QUALITY_RTT_BAD: Duration = Duration::from_secs(1);
+
+/// Age from which on a status is considered entirely stale
+const QUALITY_STALE: Duration = Duration::from_secs(5 * 60);
+
+/// Number of addresses remembered per peer to detect address changes
+const KNOWN_ADDRESSES: usize = 16;
+
+/// Time after which a dial the swarm never reported back on, e.g. as it
+/// refused the address right away, no longer counts as outstanding
+const DIAL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Returned events by behaviour
+#[derive(Debug, Clone)]
+pub enum BehaviourEvent {
+    /// A connection to a watched peer has been established
+    PeerOnline { peer: PeerId },
+    /// The connection to a watched peer has been closed
+    PeerOffline { peer: PeerId },
+    /// Dialing an address failed, e.g. because its protocols are not allowed
+    DialFailed { peer: Option<PeerId>, address: Multiaddr, error: String },
+    /// Peer was removed from the watch list after being idle for too long
+    PeerEvicted { peer: PeerId },
+    /// Peer uses a different status frame version than we do
+    VersionMismatch { peer: PeerId, theirs: u8, ours: u8 },
+    /// Status protocol negotiated with a watched peer, reported when it changes
+    ProtocolNegotiated { peer: PeerId, protocol: String },
+    /// Watched peer reported a new status that differs from the reference status
+    StatusDiverged { peer: PeerId, status: Payload, reference: Payload },
+    /// Peer failed too many status exchanges and is no longer used for routing
+    PeerDemoted { peer: PeerId, score: i32 },
+    /// Events were dropped as they were generated faster than consumed
+    EventsDropped { count: usize },
+    /// Connection to a watched peer is secured by the given protocol
+    SecurityNegotiated { peer: PeerId, security: String },
+    /// Watched peer reported a new epoch, i.e. it restarted, even if its
+    /// status did not change
+    PeerRestarted { peer: PeerId },
+    /// Watched peer declared a payload schema different from ours, so its
+    /// status is ignored
+    SchemaMismatch { peer: PeerId, theirs: String, ours: String },
+    /// Watched peer reported a status refused by the payload validator, so
+    /// it is ignored
+    PayloadRejected { peer: PeerId, payload: Payload },
+    /// Untrusted peer requested our status, which was withheld from it
+    StatusWithheld { peer: PeerId },
+    /// Node started shutting down, see [`Behaviour::begin_drain`]
+    Draining,
+    /// Watched peer was dialed at an address never used for it before,
+    /// e.g. after it moved to another network
+    PeerAddressChanged { peer: PeerId, old: Multiaddr, new: Multiaddr },
+    /// Peer entered the Kademlia routing table, possibly replacing the
+    /// evicted one
+    RoutingUpdated { peer: PeerId, evicted: Option<PeerId> },
+    /// Dialing a trusted peer failed at every known address, listed in the
+    /// order they were tried
+    TrustedPeerUnreachable { peer: PeerId, addresses: Vec<Multiaddr> },
+    /// Connection to a watched peer was replaced by a redundant one, e.g.
+    /// as both sides dialed at once, the peer stayed online
+    ConnectionReplaced { peer: PeerId },
+}
+
+impl BehaviourEvent {
+    /// Peer the event is about, if it concerns a single peer
+    pub fn peer(&self) -> Option<&PeerId> {
+        match self {
+            BehaviourEvent::PeerOnline { peer }
+            | BehaviourEvent::PeerOffline { peer }
+            | BehaviourEvent::PeerEvicted { peer }
+            | BehaviourEvent::VersionMismatch { peer, .. }
+            | BehaviourEvent::ProtocolNegotiated { peer, .. }
+            | BehaviourEvent::StatusDiverged { peer, .. }
+            | BehaviourEvent::PeerDemoted { peer, .. }
+            | BehaviourEvent::SecurityNegotiated { peer, .. }
+            | BehaviourEvent::PeerRestarted { peer }
+            | BehaviourEvent::SchemaMismatch { peer, .. }
+            | BehaviourEvent::PayloadRejected { peer, .. }
+            | BehaviourEvent::StatusWithheld { peer }
+            | BehaviourEvent::PeerAddressChanged { peer, .. }
+            | BehaviourEvent::RoutingUpdated { peer, .. }
+            | BehaviourEvent::TrustedPeerUnreachable { peer, .. }
+            | BehaviourEvent::ConnectionReplaced { peer } => Some(peer),
+            BehaviourEvent::DialFailed { peer, .. } => peer.as_ref(),
+            BehaviourEvent::EventsDropped { .. } | BehaviourEvent::Draining => None,
+        }
+    }
+}
+
+/// Queue of events to be returned to the swarm, holding up to a fixed number
+/// of events. Events beyond that are dropped and reported by a single
+/// [`BehaviourEvent::EventsDropped`] once there is room again.
+#[derive(Debug)]
+struct EventQueue {
+    events: VecDeque<BehaviourEvent>,
+    capacity: usize,
+    /// Number of events dropped since the last report
+    dropped: usize,
+    /// Every event pushed, whether dropped or not
+    log: EventLog,
+}
+
+impl EventQueue {
+    fn new(capacity: usize, log: EventLog) -> Self {
+        EventQueue { events: VecDeque::new(), capacity: cmp::max(capacity, 1), dropped: 0, log }
+    }
+
+    fn push(&mut self, event: BehaviourEvent) {
+        self.log.record(&event);
+        self.report_dropped();
+
+        if self.events.len() < self.capacity {
+            self.events.push_back(event);
+        } else {
+            self.dropped += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<BehaviourEvent> {
+        let event = self.events.pop_front();
+        self.report_dropped();
+        event
+    }
+
+    /// Report dropped events, if any, as soon as there is room
+    fn report_dropped(&mut self) {
+        if self.dropped > 0 && self.events.len() < self.capacity {
+            self.events.push_back(BehaviourEvent::EventsDropped { count: self.dropped });
+            self.dropped = 0;
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &BehaviourEvent> {
+        self.events.iter()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+/// Events generated recently, together with when they were, bounded by
+/// number and age, to look back at what happened without scraping logs.
+#[derive(Debug)]
+struct EventLog {
+    entries: VecDeque<(Instant, BehaviourEvent)>,
+    max_len: usize,
+    max_age: Duration,
+    clock: SharedClock,
+}
+
+impl EventLog {
+    fn new(max_len: usize, max_age: Duration, clock: SharedClock) -> Self {
+        EventLog { entries: VecDeque::new(), max_len, max_age, clock }
+    }
+
+    fn record(&mut self, event: &BehaviourEvent) {
+        if self.max_len == 0 {
+            return
+        }
+
+        let now = self.clock.now();
+        self.entries.push_back((now, event.clone()));
+
+        while self.entries.len() > self.max_len {
+            self.entries.pop_front();
+        }
+        self.expire(now);
+    }
+
+    /// Forget events older than the maximum age
+    fn expire(&mut self, now: Instant) {
+        while self.entries.front().map_or(false, |(time, _)| now.saturating_duration_since(*time) >= self.max_age) {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Events still within the maximum age with their age, oldest first
+    fn recent(&self) -> Vec<(Duration, BehaviourEvent)> {
+        let now = self.clock.now();
+
+        self.entries.iter()
+            .map(|(time, event)| (now.saturating_duration_since(*time), event.clone()))
+            .filter(|(age, _)| *age < self.max_age)
+            .collect()
+    }
+}
+
+/// Status all watched peers are expected to agree on
+#[derive(Clone, Debug)]
+pub enum StatusReference {
+    /// Peers are expected to report the given status
+    Expected(Payload),
+    /// Peers are expected to report the status most of them report,
+    /// there is no reference while several statuses are tied
+    Plurality,
 }
 
 /// Internal structure used to track other peers
@@ -46,13 +305,119 @@ pub struct PeerInfo {
     id: PeerId,
     routing: Option<PeerRouting>,
     status: Option<PeerStatus>,
+    rtt: Option<Duration>,
+    /// Name of the status protocol negotiated in the last direct exchange
+    protocol: Option<String>,
+    /// Node name advertised by the peer
+    name: Option<String>,
+    /// Epoch advertised by the peer, which changes whenever it restarts
+    epoch: Option<u64>,
+    /// Payload schema declared in the last direct exchange
+    schema: Option<String>,
+    /// Optional features both the peer and we support, if it announced any
+    capabilities: Option<Capabilities>,
+    /// Security protocol of the current or last connection
+    security: Option<String>,
+    /// Whether the peer withheld its status in the last direct exchange
+    withheld: bool,
+    /// Whether we are currently connected to the peer
+    online: bool,
+    /// Trusted peers are never evicted
+    trusted: bool,
+    /// Last time the peer was connected or its status received
+    last_active: Instant,
+    /// Whether each of the most recent outbound exchanges succeeded, oldest first
+    outcomes: VecDeque<bool>,
+    /// Addresses the peer was successfully dialed at, most recent last
+    addresses: Vec<Multiaddr>,
+    /// Delay of the last re-dial scheduled since the connection was lost
+    backoff: Option<Duration>,
+    /// When the scheduled re-dial is due, if still pending
+    next_retry: Option<Instant>,
 }
 
 #[derive(Clone)]
 struct PeerRouting ( Vec<PeerId>, Instant);
 
+/// Dial to be requested from the swarm
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Dial {
+    /// Dial peer at any of its known addresses, one after another
+    Peer(PeerId),
+    /// Dial a specific address
+    Address(Multiaddr),
+}
+
+/// Dial of a trusted peer going through its known addresses
+#[derive(Debug)]
+struct DialAttempt {
+    /// Addresses dialed by us that did not fail yet, empty if the swarm
+    /// goes through the addresses itself
+    pending: HashSet<Multiaddr>,
+    /// Addresses to dial once all pending ones failed
+    remaining: VecDeque<Multiaddr>,
+    /// Addresses that failed so far
+    failed: Vec<Multiaddr>,
+    /// When the dial started, it is forgotten if the swarm never reports back
+    started: Instant,
+}
+
+/// Second address of a happy eyeballs race, dialed once the first one failed
+/// or the timer fired, unless connected by then
+#[derive(Debug)]
+struct Staggered {
+    /// Address dialed first
+    first: Multiaddr,
+    /// Address of the other family
+    second: Multiaddr,
+    timer: Timer,
+}
+
+/// Reports of peer addresses, each passed on only once within a window
+struct Debounce {
+    window: Duration,
+    /// When each address was last passed on
+    seen: HashMap<(PeerId, Multiaddr), Instant>,
+}
+
+impl Debounce {
+    fn new(window: Duration) -> Self {
+        Debounce { window, seen: HashMap::new() }
+    }
+
+    /// Whether the report should be passed on, i.e. it is not a repetition
+    fn admit(&mut self, peer: &PeerId, addr: &Multiaddr, now: Instant) -> bool {
+        let window = self.window;
+        self.seen.retain(|_, seen| now.saturating_duration_since(*seen) < window);
+
+        let key = (peer.clone(), addr.clone());
+        if self.seen.contains_key(&key) {
+            return false
+        }
+
+        if window > Duration::new(0, 0) {
+            self.seen.insert(key, now);
+        }
+        true
+    }
+}
+
+/// Pending re-dial of a trusted peer
+struct Redial {
+    /// Number of attempts made since the connection was lost
+    attempt: u32,
+    /// Timer until the next attempt, if one is scheduled
+    timer: Option<Timer>,
+}
+
 #[derive(Clone)]
-struct PeerStatus ( Payload, Instant );
+struct PeerStatus {
+    payload: Payload,
+    /// When the status was (originally) received
+    received: Instant,
+    /// Peer the status was gossiped by, if not received directly
+    via: Option<PeerId>,
+}
 
 impl PeerInfo {
     pub fn new(id: &PeerId) -> Self {
@@ -60,7 +425,384 @@ impl PeerInfo {
             id: id.clone(),
             routing: None,
             status: None,
+            rtt: None,
+            protocol: None,
+            name: None,
+            epoch: None,
+            schema: None,
+            capabilities: None,
+            security: None,
+            withheld: false,
+            online: false,
+            trusted: false,
+            last_active: Instant::now(),
+            outcomes: VecDeque::with_capacity(QUALITY_WINDOW),
+            addresses: Vec::new(),
+            backoff: None,
+            next_retry: None,
+        }
+    }
+
+    pub fn id(&self) -> &PeerId {
+        &self.id
+    }
+
+    /// Addresses the peer was successfully dialed at, most recent last.
+    ///
+    /// Inbound connections come from ephemeral ports, so they are not included.
+    pub fn addresses(&self) -> &[Multiaddr] {
+        &self.addresses
+    }
+
+    /// Last known status of peer
+    pub fn status(&self) -> Option<&Payload> {
+        self.status.as_ref().map(|s| &s.payload)
+    }
+
+    /// Peer that gossiped the last known status, `None` if received directly
+    pub fn status_via(&self) -> Option<&PeerId> {
+        self.status.as_ref().and_then(|s| s.via.as_ref())
+    }
+
+    /// Whether we are currently connected to the peer
+    pub fn online(&self) -> bool {
+        self.online
+    }
+
+    /// Whether the peer is in the trust store, i.e. never evicted
+    pub fn trusted(&self) -> bool {
+        self.trusted
+    }
+
+    /// Round trip time of the last direct status exchange
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    /// Peers closest to this one, as found by the last Kademlia lookup
+    pub fn routing(&self) -> Option<&[PeerId]> {
+        self.routing.as_ref().map(|r| r.0.as_slice())
+    }
+
+    /// Status protocol negotiated in the last direct exchange
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_ref().map(String::as_str)
+    }
+
+    /// Human readable node name advertised by the peer, if any
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(String::as_str)
+    }
+
+    /// Payload schema declared by the peer, if any
+    pub fn schema(&self) -> Option<&str> {
+        self.schema.as_ref().map(String::as_str)
+    }
+
+    /// Optional features both the peer and we support, `None` if the peer
+    /// did not announce any, i.e. predates them
+    pub fn capabilities(&self) -> Option<Capabilities> {
+        self.capabilities
+    }
+
+    /// Security protocol of the current or last connection, if known
+    pub fn security(&self) -> Option<&str> {
+        self.security.as_ref().map(String::as_str)
+    }
+
+    /// Whether the peer withholds its status from us, e.g. as it only serves
+    /// peers it trusts
+    pub fn withholds_status(&self) -> bool {
+        self.withheld
+    }
+
+    /// Time between receiving the last status of the peer and `now`, `None`
+    /// if never. Use [`Behaviour::status_age`] to measure against the clock
+    /// the status was received by.
+    pub fn last_status_age(&self, now: Instant) -> Option<Duration> {
+        self.status.as_ref().map(|s| now.saturating_duration_since(s.received))
+    }
+
+    /// Delay of the last re-dial scheduled since the connection to the
+    /// trusted peer was lost, growing with every failed attempt
+    pub fn backoff(&self) -> Option<Duration> {
+        self.backoff
+    }
+
+    /// Time until the peer is re-dialed, `None` unless one is scheduled
+    pub fn next_retry_in(&self) -> Option<Duration> {
+        self.next_retry_in_at(Instant::now())
+    }
+
+    /// Time between `now` and when the peer is re-dialed
+    pub fn next_retry_in_at(&self, now: Instant) -> Option<Duration> {
+        self.next_retry.map(|retry| retry.saturating_duration_since(now))
+    }
+
+    /// Health of the connection to the peer from 0 (unusable) to 100 (perfect)
+    pub fn quality(&self) -> u8 {
+        self.quality_at(Instant::now())
+    }
+
+    /// Health of the connection to the peer as of `now`, i.e. the weighted sum
+    ///
+    /// - 50% reliability: the share of successful exchanges among the last 16,
+    /// - 25% responsiveness: 1 for a round trip time up to 50ms, falling
+    ///   linearly to 0 at 1s,
+    /// - 25% freshness: 1 for a status just received, falling linearly to 0
+    ///   for one that is 5 minutes old,
+    ///
+    /// scaled to 0 to 100. Each part is 0 while unknown.
+    pub fn quality_at(&self, now: Instant) -> u8 {
+        /// Position of value between good and bad, as 1 to 0
+        fn linear(value: Duration, good: Duration, bad: Duration) -> f64 {
+            let value = value.max(good).min(bad);
+            1.0 - (value - good).as_secs_f64() / (bad - good).as_secs_f64()
+        }
+
+        let reliability = match self.outcomes.len() {
+            0 => 0.0,
+            n => self.outcomes.iter().filter(|success| **success).count() as f64 / n as f64,
+        };
+        let responsiveness = self.rtt.map_or(0.0, |rtt| linear(rtt, QUALITY_RTT_GOOD, QUALITY_RTT_BAD));
+        let freshness = self.last_status_age(now)
+            .map_or(0.0, |age| linear(age, Duration::new(0, 0), QUALITY_STALE));
+
+        (100.0 * (0.5 * reliability + 0.25 * responsiveness + 0.25 * freshness)).round() as u8
+    }
+
+    /// Remember whether an outbound exchange succeeded, for [`PeerInfo::quality`]
+    fn record_outcome(&mut self, success: bool) {
+        if self.outcomes.len() >= QUALITY_WINDOW {
+            self.outcomes.pop_front();
         }
+        self.outcomes.push_back(success);
+    }
+
+    /// Update status if it is newer than the current one
+    fn update_status(&mut self, payload: Payload, received: Instant, via: Option<PeerId>) {
+        if self.status.as_ref().map_or(true, |s| s.received <= received) {
+            self.status = Some(PeerStatus { payload, received, via });
+        }
+    }
+}
+
+/// Point-in-time view of a watched peer
+#[derive(Clone, Debug)]
+pub struct PeerSnapshot {
+    pub id: PeerId,
+    /// Whether we are currently connected to the peer
+    pub online: bool,
+    /// Time since the last status of the peer was received
+    pub last_seen: Option<Duration>,
+    pub status: Option<Payload>,
+    pub rtt: Option<Duration>,
+    /// Node name advertised by the peer
+    pub name: Option<String>,
+}
+
+
+/// Configuration of the combined network behaviour.
+#[derive(Clone, Debug)]
+pub struct BehaviourConfig {
+    /// Configuration of the status protocol.
+    status: StatusConfig,
+    /// Whether peers on the local network should be discovered via mDNS.
+    mdns: bool,
+    /// Window within which repeated mDNS reports of the same address are ignored.
+    mdns_debounce: Duration,
+    /// Kademlia nodes to bootstrap from.
+    bootstrap: Vec<(PeerId, Multiaddr)>,
+    /// Duration after which idle untrusted peers are no longer watched.
+    peer_ttl: Option<Duration>,
+    /// Whether to serve the DHT or only query it.
+    kad_mode: KademliaMode,
+    /// Backoff for re-dialing lost trusted peers, if at all.
+    reconnect: Option<ReconnectConfig>,
+    /// Status watched peers are expected to agree on, if any.
+    reference: Option<StatusReference>,
+    /// Score below which peers are withheld from Kademlia, if at all.
+    score_threshold: Option<i32>,
+    /// Maximum number of events queued for the swarm.
+    event_capacity: usize,
+    /// Maximum number and age of events kept for looking back.
+    event_log: (usize, Duration),
+    /// Order in which address families are dialed.
+    address_preference: AddressPreference,
+    /// Security protocols of connections as recorded by the transport, if at all.
+    security: Option<SecurityTable>,
+    /// Maximum number of dials outstanding at once, if limited.
+    max_dials: Option<usize>,
+    /// Maximum number of connections per peer, if limited.
+    max_connections: Option<NonZeroUsize>,
+}
+
+impl BehaviourConfig {
+    /// Creates a new `BehaviourConfig` using the given status configuration,
+    /// with mDNS discovery enabled and bootstrapping from the IPFS network.
+    pub fn new(status: StatusConfig) -> Self {
+        let bootstrap = BOOTSTRAP_NODES.iter()
+            .map(|(id, addr)| (id.parse().unwrap(), addr.parse().unwrap()))
+            .collect();
+
+        Self {
+            status,
+            mdns: true,
+            mdns_debounce: Duration::from_secs(10),
+            bootstrap,
+            peer_ttl: None,
+            kad_mode: KademliaMode::Server,
+            reconnect: Some(ReconnectConfig::default()),
+            reference: None,
+            score_threshold: Some(-5),
+            event_capacity: 1024,
+            event_log: (256, Duration::from_secs(5 * 60)),
+            address_preference: AddressPreference::default(),
+            security: None,
+            max_dials: Some(32),
+            max_connections: NonZeroUsize::new(1),
+        }
+    }
+
+    /// Sets the Kademlia nodes to bootstrap from, replacing the default IPFS nodes.
+    pub fn with_bootstrap(mut self, nodes: Vec<(PeerId, Multiaddr)>) -> Self {
+        self.bootstrap = nodes;
+        self
+    }
+
+    /// Sets whether the node answers inbound Kademlia queries.
+    ///
+    /// Short-lived nodes should use [`KademliaMode::Client`] to not
+    /// advertise themselves as record holders. Small networks with known
+    /// addresses can do without the DHT using [`KademliaMode::Disabled`],
+    /// discovering peers via mDNS and configured addresses only.
+    pub fn with_kademlia_mode(mut self, mode: KademliaMode) -> Self {
+        self.kad_mode = mode;
+        self
+    }
+
+    /// Sets the duration after which a watched peer that has neither been
+    /// connected nor sent a status is evicted from the watch list.
+    ///
+    /// Trusted peers are never evicted. By default, peers are watched forever,
+    /// as they are with a TTL of zero, which would otherwise evict peers as
+    /// soon as added.
+    pub fn with_peer_ttl(mut self, ttl: Duration) -> Self {
+        self.peer_ttl = Some(ttl).filter(|ttl| *ttl > Duration::from_secs(0));
+        self
+    }
+
+    /// Sets the backoff used to re-dial trusted peers after the connection
+    /// was lost or dialing failed, or disables re-dialing with `None`.
+    pub fn with_reconnect(mut self, reconnect: Option<ReconnectConfig>) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// Sets the status watched peers are expected to agree on, reporting
+    /// [`BehaviourEvent::StatusDiverged`] for peers that do not.
+    pub fn with_reference(mut self, reference: StatusReference) -> Self {
+        self.reference = Some(reference);
+        self
+    }
+
+    /// Sets the score below which a peer is withheld from Kademlia routing,
+    /// or disables scoring with `None`.
+    ///
+    /// Every peer starts with a score of 0, which is incremented for each
+    /// successful status exchange and decremented for each failed one, within
+    /// -10 and 10. Peers that do not support the status protocol at all or
+    /// exceed our inbound rate limit are not penalized and trusted peers are
+    /// never withheld. Defaults to -5.
+    pub fn with_score_threshold(mut self, threshold: Option<i32>) -> Self {
+        self.score_threshold = threshold;
+        self
+    }
+
+    /// Sets the order in which IPv4 and IPv6 addresses of peers are dialed,
+    /// in the order they were learned by default.
+    pub fn with_address_preference(mut self, preference: AddressPreference) -> Self {
+        self.address_preference = preference;
+        self
+    }
+
+    /// Sets the table the transport records the security protocol of every
+    /// connection in, see [`crate::transport::RecordSecurity`]. Entries of
+    /// closed connections are removed from it.
+    pub fn with_security_table(mut self, table: SecurityTable) -> Self {
+        self.security = Some(table);
+        self
+    }
+
+    /// Sets the maximum number of events queued for the swarm.
+    ///
+    /// Events generated while the queue is full are dropped, which is
+    /// reported by [`BehaviourEvent::EventsDropped`]. Defaults to 1024.
+    pub fn with_event_capacity(mut self, n: usize) -> Self {
+        self.event_capacity = n;
+        self
+    }
+
+    /// Sets how many of the most recent events are kept, and for how long,
+    /// for [`Behaviour::recent_events`]. A length of 0 keeps none.
+    ///
+    /// Defaults to 256 events from the last 5 minutes.
+    pub fn with_event_log(mut self, len: usize, age: Duration) -> Self {
+        self.event_log = (len, age);
+        self
+    }
+
+    /// Sets the maximum number of dials outstanding at once, or `None` to
+    /// dial all peers right away.
+    ///
+    /// Further dials are queued until earlier ones succeed or fail, so that
+    /// dialing hundreds of peers does not exhaust file descriptors. Dials
+    /// without any outcome after 30 seconds no longer count. Defaults to 32.
+    pub fn with_max_dials(mut self, n: Option<usize>) -> Self {
+        self.max_dials = n;
+        self
+    }
+
+    /// Sets the maximum number of connections per peer, counting the
+    /// established one and those being dialed, or lifts the limit with `None`.
+    /// Defaults to 1, i.e. the first connection is kept.
+    ///
+    /// The swarm never keeps more than one connection per peer, but replaces
+    /// the older one by any newer one. Once the limit is reached, a peer is
+    /// thus not dialed again, e.g. once discovered via mDNS or once it
+    /// connected to us, as that would only replace the connection. Dials in
+    /// flight can not be cancelled though, nor can the peer be kept from
+    /// connecting to us again, which replaces the connection still.
+    pub fn with_max_connections_per_peer(mut self, n: Option<NonZeroUsize>) -> Self {
+        self.max_connections = n;
+        self
+    }
+
+    /// Sets the clock used for all timing of the behaviour and status protocol.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.status = self.status.with_clock(clock);
+        self
+    }
+
+    /// Sets whether mDNS discovery should be used.
+    ///
+    /// Even if enabled, the behaviour continues without mDNS if it
+    /// fails to initialize (e.g. due to missing multicast support).
+    ///
+    /// The mDNS service of libp2p always listens and announces the node on
+    /// all interfaces and can not be limited to some of them. Disable mDNS
+    /// entirely to keep the node from being discovered on untrusted networks.
+    pub fn with_mdns(mut self, b: bool) -> Self {
+        self.mdns = b;
+        self
+    }
+
+    /// Sets the window within which an address discovered or expired again
+    /// via mDNS is ignored, as busy networks report the same peers over and
+    /// over. Zero passes every report on.
+    pub fn with_mdns_debounce(mut self, window: Duration) -> Self {
+        self.mdns_debounce = window;
+        self
     }
 }
 
@@ -68,89 +810,936 @@ impl PeerInfo {
 // We create a custom network behaviour that combines Kademlia with
 // regular status requests.
 #[derive(NetworkBehaviour)]
+#[behaviour(out_event = "BehaviourEvent", poll_method = "poll_events")]
 pub struct Behaviour {
-    kad: Kademlia<MemoryStore>,
-    mdns: Mdns,
+    kad: Dht,
+    mdns: Toggle<Mdns>,
     status: Status,
+    conn: Connections,
+
+    /// All watched peers, owned by the swarm task. Other tasks and threads
+    /// access them through a [`crate::node::NodeHandle`].
+    #[behaviour(ignore)]
+    peers: Vec<PeerInfo>,
+
+    /// Events to be returned to the swarm
+    #[behaviour(ignore)]
+    events: EventQueue,
+
+    /// Time after which idle peers are evicted
+    #[behaviour(ignore)]
+    peer_ttl: Option<Duration>,
+
+    /// Timer to periodically check for idle peers
+    #[behaviour(ignore)]
+    eviction: Option<Timer>,
+
+    /// Source of the current time, shared with the status protocol
+    #[behaviour(ignore)]
+    clock: SharedClock,
+
+    /// Repeated mDNS discoveries and expiries to be ignored
+    #[behaviour(ignore)]
+    discovered: Debounce,
+    #[behaviour(ignore)]
+    expired: Debounce,
+
+    /// Backoff for re-dialing trusted peers
+    #[behaviour(ignore)]
+    reconnect: Option<ReconnectConfig>,
+
+    /// Status watched peers are expected to agree on
+    #[behaviour(ignore)]
+    reference: Option<StatusReference>,
+
+    /// Schema of our payload, payloads in other schemas are ignored
+    #[behaviour(ignore)]
+    schema: Option<String>,
+
+    /// Optional features we offer to peers
+    #[behaviour(ignore)]
+    capabilities: Capabilities,
 
+    /// Score below which peers are withheld from Kademlia
     #[behaviour(ignore)]
-    pub peers: Mutex<Vec<PeerInfo>>,
+    score_threshold: Option<i32>,
+
+    /// Reliability scores of all peers status was exchanged with
+    #[behaviour(ignore)]
+    scores: HashMap<PeerId, i32>,
+
+    /// Trusted peers that have lost their connection
+    #[behaviour(ignore)]
+    redials: HashMap<PeerId, Redial>,
+
+    /// Trusted peers being dialed, until connected or out of addresses
+    #[behaviour(ignore)]
+    attempts: HashMap<PeerId, DialAttempt>,
+
+    /// Addresses of the other family of peers being dialed with happy eyeballs
+    #[behaviour(ignore)]
+    staggered: HashMap<PeerId, Staggered>,
+
+    /// Security protocols of connections recorded by the transport
+    #[behaviour(ignore)]
+    security: Option<SecurityTable>,
+
+    /// Dials to be requested from the swarm
+    #[behaviour(ignore)]
+    dials: VecDeque<Dial>,
+
+    /// Maximum number of dials outstanding at once, if limited
+    #[behaviour(ignore)]
+    max_dials: Option<usize>,
+
+    /// Maximum number of connections per peer, if limited
+    #[behaviour(ignore)]
+    max_connections: Option<NonZeroUsize>,
+
+    /// Whether Kademlia bootstrapped successfully, or had nothing to bootstrap from
+    #[behaviour(ignore)]
+    bootstrapped: bool,
+
+    /// Check of received payloads before they are accepted, if any
+    #[behaviour(ignore)]
+    validator: Option<PayloadValidator>,
+
+    /// Comparison of payloads by divergence and rollout queries, if not byte equality
+    #[behaviour(ignore)]
+    comparator: Option<PayloadComparator>,
+
+    /// Dials requested from the swarm that neither succeeded nor failed yet,
+    /// along with when they were requested, only tracked if limited
+    #[behaviour(ignore)]
+    outstanding: HashMap<Dial, Instant>,
+
+    /// Timer until the oldest outstanding dial expires
+    #[behaviour(ignore)]
+    dial_expiry: Option<Timer>,
+
+    /// Peers our status was withheld from since they connected, reported once
+    #[behaviour(ignore)]
+    withheld: HashSet<PeerId>,
 }
 
 impl Behaviour {
     pub fn new(id: PeerId, state: Payload ) -> Self {
+        let status = StatusConfig::new( state ).with_keep_alive(true);
+
+        Self::with_config(id, BehaviourConfig::new(status))
+    }
+
+    pub fn with_config(id: PeerId, config: BehaviourConfig) -> Self {
         // Config and setup Kademlia
         let mut cfg = KademliaConfig::default();
 
+        // Keep nodes of other networks out of our routing table
+        if let Some(network) = config.status.network() {
+            cfg.set_protocol_name(network.protocol("kad/1.0.0").into_bytes());
+        }
+
         let store = MemoryStore::new(id.clone());
 
-        let mut kad = Kademlia::with_config(id.clone(), store, cfg);
+        let kad = Kademlia::with_config(id.clone(), store, cfg);
+        let mut kad = Dht::new(kad, config.kad_mode).with_preference(config.address_preference);
 
-        // Trigger bootstrap with default IPFS nodes
-        for (id, addr) in BOOTSTRAP_NODES.iter() {
-            kad.add_address(&id.parse().unwrap(), addr.parse().unwrap());
+        // Trigger bootstrap with configured nodes
+        for (id, addr) in config.bootstrap.iter() {
+            kad.add_address(id, addr.clone());
         }
 
         kad.bootstrap();
 
-        // Setup mDNS discovery
-        let mdns = Mdns::new().unwrap();
+        // Setup mDNS discovery, but do not fail without it
+        let mdns = if config.mdns {
+            match Mdns::new() {
+                Ok(mdns) => Some(mdns),
+                Err(error) => {
+                    warn!("Failed to start mDNS, continuing without: {:?}", error);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        // Setup status protocol, sharing its clock
+        let clock = config.status.clock().clone();
+        let schema = config.status.schema().map(str::to_owned);
+        let capabilities = config.status.capabilities();
+        let status = Status::new(config.status.with_local_peer_id(id.clone()));
 
-        // Configure and setup status protocol
-        let status = Status::new(StatusConfig::new( state ).with_keep_alive(true));
+        // Check for idle peers often enough to not exceed TTL by much
+        let eviction = config.peer_ttl.map(|ttl| Timer::new(clock.clone(), ttl / 2));
 
-        Behaviour { kad, mdns, status, peers: Mutex::new(Vec::new()) }
+        Behaviour {
+            kad,
+            mdns: Toggle::from(mdns),
+            discovered: Debounce::new(config.mdns_debounce),
+            expired: Debounce::new(config.mdns_debounce),
+            status,
+            conn: Connections::default(),
+            peers: Vec::new(),
+            events: EventQueue::new(config.event_capacity, EventLog::new(config.event_log.0, config.event_log.1, clock.clone())),
+            peer_ttl: config.peer_ttl,
+            eviction,
+            clock,
+            reconnect: config.reconnect,
+            reference: config.reference,
+            schema,
+            capabilities,
+            score_threshold: config.score_threshold,
+            scores: HashMap::new(),
+            security: config.security,
+            redials: HashMap::new(),
+            attempts: HashMap::new(),
+            staggered: HashMap::new(),
+            dials: VecDeque::new(),
+            max_dials: config.max_dials,
+            max_connections: config.max_connections,
+            outstanding: HashMap::new(),
+            dial_expiry: None,
+            withheld: HashSet::new(),
+            bootstrapped: config.bootstrap.is_empty() || config.kad_mode == KademliaMode::Disabled,
+            validator: None,
+            comparator: None,
+        }
     }
 
-    /// Add peer id to list of watched peers
-    pub fn add_peers(&mut self, id: PeerId) {
-        self.peers.lock().unwrap().push(PeerInfo::new(&id));
+    /// Only accept received payloads, whether received directly or gossiped,
+    /// that pass the given check, e.g. that they decode to a valid build id.
+    ///
+    /// Refused payloads do not update the status of the peer, those received
+    /// directly are reported as [`BehaviourEvent::PayloadRejected`].
+    pub fn set_payload_validator(&mut self, validator: PayloadValidator) {
+        self.validator = Some(validator);
+    }
 
-        self.kad.get_closest_peers(id.clone());
+    /// Decide with the given comparison whether statuses agree, e.g. to accept
+    /// peers on the same release but with different build hashes. Used when
+    /// reporting and detecting divergence and for the revision queries.
+    ///
+    /// The plurality reference counts matching statuses together as well,
+    /// see [`Behaviour::reference_status`].
+    pub fn set_payload_comparator(&mut self, comparator: PayloadComparator) {
+        self.comparator = Some(comparator);
     }
 
-    /// Retrieve current peer status by id
-    pub fn get_peer_info(&self, id: &PeerId) -> Option<PeerInfo> {
-        for peer in self.peers.lock().unwrap().iter() {
-            if &peer.id == id {
-                return Some(peer.clone())
-            }
+    /// How the payloads relate, by default by byte equality
+    pub fn compatibility(&self, a: &Payload, b: &Payload) -> Compatibility {
+        match &self.comparator {
+            Some(comparator) => comparator(a, b),
+            None => Compatibility::of(a, b),
         }
-        None
     }
-}
 
-impl NetworkBehaviourEventProcess<KademliaEvent> for Behaviour {
-    fn inject_event(&mut self, event: KademliaEvent) {
-        match event {
-            KademliaEvent::BootstrapResult(result) => {
-                match result {
-                    Ok(..) => println!("Bootstrap successful!"),
-                    Err(error) => println!("Bootstrap failed: {:#?}", error),
-                }
-            },
-            KademliaEvent::GetClosestPeersResult(result) => {
-                match result {
-                    Ok(closest) => {
-                        if let Ok(id) = PeerId::from_bytes(closest.key) {
+    /// Whether peers reporting the payloads are in agreement
+    fn matches(&self, a: &Payload, b: &Payload) -> bool {
+        self.compatibility(a, b).matches()
+    }
 
-                            if closest.peers.is_empty() {
-                                self.kad.get_closest_peers(id.clone());
-                            } else {
-                                if let Some(info) = self.get_peer_info(&id) {
-                                    //info.routing = Some(PeerRouting(closest.peers, Instant::now()));
-                                    println!("Updated Kademlia Peers of {:#?}: {:#?}", id, closest.peers);
-                                } else {
-                                    println!("Unknown Peer {:#?}: {:#?}", id, closest.peers);
+    /// Events generated recently, including those dropped from the queue, with
+    /// their age, oldest first. Bounded as set by [`BehaviourConfig::with_event_log`].
+    pub fn recent_events(&self) -> Vec<(Duration, BehaviourEvent)> {
+        self.events.log.recent()
+    }
+
+    /// Prepare for shutting down the node: statuses are no longer requested
+    /// or served and peers no longer dialed, while exchanges in flight are
+    /// completed. Connections are released, so they close once idle.
+    ///
+    /// Reports [`BehaviourEvent::Draining`], the node may exit after a grace
+    /// period of e.g. the status timeout.
+    pub fn begin_drain(&mut self) {
+        if self.status.is_draining() {
+            return
+        }
+
+        self.status.drain();
+        self.redials.clear();
+        self.attempts.clear();
+        self.staggered.clear();
+        self.dials.clear();
+        for info in self.peers.iter_mut() {
+            info.next_retry = None;
+        }
+        self.events.push(BehaviourEvent::Draining);
+    }
+
+    /// Whether the node is shutting down, see [`Behaviour::begin_drain`]
+    pub fn is_draining(&self) -> bool {
+        self.status.is_draining()
+    }
+
+    /// Whether Kademlia has bootstrapped, trivially so without any bootstrap nodes
+    pub fn is_bootstrapped(&self) -> bool {
+        self.bootstrapped
+    }
+
+    /// Addresses the node is reachable at, i.e. confirmed external
+    /// addresses followed by the current local listen addresses
+    pub fn listen_addresses(&self) -> Vec<Multiaddr> {
+        self.conn.addresses()
+    }
+
+    /// The Kademlia DHT, e.g. to inspect its mode
+    pub fn dht(&self) -> &Dht {
+        &self.kad
+    }
+
+    /// Whether mDNS discovery is active
+    pub fn mdns_enabled(&self) -> bool {
+        self.mdns.is_enabled()
+    }
+
+    /// Add peer id to list of watched peers
+    pub fn add_peers(&mut self, id: PeerId) {
+        let mut info = PeerInfo::new(&id);
+        info.last_active = self.clock.now();
+        self.peers.push(info);
+
+        self.kad.get_closest_peers(id.clone());
+    }
+
+    /// Add peer to list of watched peers at a known address, so that it can
+    /// be dialed right away without looking it up in the DHT first
+    pub fn add_peer_with_address(&mut self, id: PeerId, addr: Multiaddr) {
+        let mut info = PeerInfo::new(&id);
+        info.last_active = self.clock.now();
+        self.peers.push(info);
+
+        self.kad.add_address(&id, addr);
+    }
+
+    /// Tell Kademlia about an address of the peer, without watching it
+    pub fn add_address(&mut self, id: &PeerId, addr: Multiaddr) {
+        self.kad.add_address(id, addr);
+    }
+
+    /// Addresses of the peer currently known to Kademlia
+    pub fn addresses_of(&mut self, id: &PeerId) -> Vec<Multiaddr> {
+        self.kad.addresses_of_peer(id)
+    }
+
+    /// All peers in the Kademlia routing table with their addresses, sorted
+    /// by peer id. Demoted peers are included, unlike in [`Behaviour::addresses_of`].
+    pub fn routing_table(&mut self) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        let mut peers: Vec<PeerId> = self.kad.kbuckets_entries().cloned().collect();
+        peers.sort_by_key(PeerId::to_base58);
+
+        peers.into_iter()
+            .map(|peer| {
+                let addrs = (*self.kad).addresses_of_peer(&peer);
+                (peer, addrs)
+            })
+            .collect()
+    }
+
+    /// Add peer id to list of watched peers, never to be evicted
+    pub fn add_trusted_peer(&mut self, id: PeerId) {
+        let mut info = PeerInfo::new(&id);
+        info.trusted = true;
+        info.last_active = self.clock.now();
+        self.peers.push(info);
+        self.status.trust(&id);
+        self.withheld.remove(&id);
+
+        self.kad.get_closest_peers(id.clone());
+    }
+
+    /// Stop watching peer, releasing the connection to it if there is one
+    pub fn remove_peer(&mut self, id: &PeerId) {
+        self.peers.retain(|info| &info.id != id);
+        self.redials.remove(id);
+
+        self.status.distrust(id);
+        self.status.shutdown(id);
+    }
+
+    /// Close the connection to the peer, if there is one
+    ///
+    /// Watched peers stay watched, so trusted ones are re-dialed if reconnecting
+    /// is enabled. Remove them first to keep them away.
+    pub fn disconnect(&mut self, id: &PeerId) {
+        self.conn.disconnect(id);
+    }
+
+    /// Peers currently connected to, whether watched or not
+    pub fn connected_peers(&self) -> Vec<PeerId> {
+        self.conn.connected_peers().cloned().collect()
+    }
+
+    /// Dial peer at its known addresses in order of preference. With happy
+    /// eyeballs, the best address of the other family follows shortly after
+    /// the first one, unless connected by then. Addresses that failed before
+    /// are tried last.
+    ///
+    /// Trusted peers are dialed at one address after another until connected,
+    /// [`BehaviourEvent::TrustedPeerUnreachable`] is reported once all failed.
+    pub fn dial_peer(&mut self, id: PeerId) {
+        if self.is_draining() {
+            return
+        }
+
+        let stale = self.attempts.get(&id)
+            .map_or(false, |attempt| self.clock.now().saturating_duration_since(attempt.started) >= DIAL_TIMEOUT);
+        if stale {
+            debug!("Forgetting dial of {:?} after no outcome was reported", id);
+            self.attempts.remove(&id);
+        }
+
+        // Another connection would only replace the first one
+        let connected = self.conn.connected_peers().any(|peer| peer == &id);
+        if self.at_connection_limit(usize::from(connected) + usize::from(self.attempts.contains_key(&id))) {
+            trace!("Not dialing {:?} again, connected or dialing it already", id);
+            return
+        }
+
+        let addrs = self.kad.addresses_of_peer(&id);
+        let race = self.kad.preference().race(&addrs);
+
+        if self.is_trusted(&id) {
+            // Without a race, the swarm goes through the addresses itself
+            let remaining = if race.is_empty() {
+                VecDeque::new()
+            } else {
+                addrs.into_iter().filter(|addr| !race.contains(addr)).collect()
+            };
+            let pending = race.iter().take(1).cloned().collect();
+            let started = self.clock.now();
+            self.attempts.insert(id.clone(), DialAttempt { pending, remaining, failed: Vec::new(), started });
+        }
+
+        let mut race = race.into_iter();
+        match (race.next(), race.next()) {
+            (Some(first), Some(second)) => {
+                let timer = Timer::new(self.clock.clone(), RACE_DELAY);
+                self.dials.push_back(Dial::Address(first.clone()));
+                self.staggered.insert(id, Staggered { first, second, timer });
+            },
+            _ => self.dials.push_back(Dial::Peer(id)),
+        }
+    }
+
+    /// Dial the other family of a happy eyeballs race of peer right away
+    fn dial_staggered(&mut self, peer: &PeerId) {
+        if let Some(staggered) = self.staggered.remove(peer) {
+            if let Some(attempt) = self.attempts.get_mut(peer) {
+                attempt.pending.insert(staggered.second.clone());
+            }
+            self.dials.push_back(Dial::Address(staggered.second));
+        }
+    }
+
+    /// Note a failed dial of address, going on with the next address of the
+    /// trusted peer being dialed at it, if any
+    fn dial_failed(&mut self, peer: Option<PeerId>, address: &Multiaddr) {
+        // Dials of single addresses are not reported with the peer
+        let peer = peer.or_else(|| self.attempts.iter()
+            .find(|(_, attempt)| attempt.pending.contains(address))
+            .map(|(peer, _)| peer.clone()));
+        let peer = match peer {
+            Some(peer) => peer,
+            None => return,
+        };
+
+        self.kad.record_failure(&peer, address);
+
+        let draining = self.is_draining();
+        let attempt = match self.attempts.get_mut(&peer) {
+            Some(attempt) => attempt,
+            None => return,
+        };
+
+        attempt.failed.push(address.clone());
+
+        // Only dials of our own go on here, the swarm reports when it is done
+        if !attempt.pending.remove(address) || !attempt.pending.is_empty() {
+            return
+        }
+
+        match attempt.remaining.pop_front() {
+            Some(next) if !draining => {
+                debug!("Dialing {:?} at {:?} after {:?} failed", peer, next, address);
+                attempt.pending.insert(next.clone());
+                self.dials.push_back(Dial::Address(next));
+            },
+            _ => {
+                self.give_up_dial(&peer);
+                self.schedule_redial(peer);
+            },
+        }
+    }
+
+    /// Whether a peer with the given number of connections, established or
+    /// being dialed, has as many as allowed
+    fn at_connection_limit(&self, connections: usize) -> bool {
+        self.max_connections.map_or(false, |max| connections >= max.get())
+    }
+
+    /// Forget dials of a now connected peer that were not requested from the
+    /// swarm yet, which would only replace the connection
+    fn drop_queued_dials(&mut self, peer: &PeerId) {
+        let mut addrs = self.kad.addresses_of_peer(peer);
+        if let Some(attempt) = self.attempts.get(peer) {
+            addrs.extend(attempt.remaining.iter().cloned());
+        }
+
+        self.dials.retain(|dial| match dial {
+            Dial::Peer(id) => id != peer,
+            Dial::Address(addr) => !addrs.contains(addr),
+        });
+    }
+
+    /// Report a trusted peer unreachable after all its addresses failed
+    fn give_up_dial(&mut self, peer: &PeerId) {
+        if let Some(attempt) = self.attempts.remove(peer) {
+            self.events.push(BehaviourEvent::TrustedPeerUnreachable {
+                peer: peer.clone(),
+                addresses: attempt.failed,
+            });
+        }
+    }
+
+    /// Add a peer discovered via mDNS to Kademlia, unless it was just
+    /// discovered at the same address. Returns whether it was added.
+    fn mdns_discovered(&mut self, peer: PeerId, addr: Multiaddr) -> bool {
+        if !self.discovered.admit(&peer, &addr, self.clock.now()) {
+            return false
+        }
+
+        debug!("Discovered {:?} via {:?}", peer, addr);
+        self.kad.add_address(&peer, addr);
+        true
+    }
+
+    /// Note a peer address expired via mDNS, unless it just did
+    fn mdns_expired(&mut self, peer: PeerId, addr: Multiaddr) {
+        if self.expired.admit(&peer, &addr, self.clock.now()) {
+            debug!("Expired {:?} via {:?}", peer, addr);
+        }
+    }
+
+    fn is_trusted(&self, id: &PeerId) -> bool {
+        self.peers.iter().any(|info| &info.id == id && info.trusted)
+    }
+
+    /// Schedule next attempt to re-dial a lost trusted peer
+    fn schedule_redial(&mut self, id: PeerId) {
+        let reconnect = match &self.reconnect {
+            Some(reconnect) => reconnect,
+            None => return,
+        };
+
+        if !self.is_trusted(&id) {
+            return
+        }
+
+        let redial = self.redials.entry(id.clone()).or_insert(Redial { attempt: 0, timer: None });
+        let delay = reconnect.delay(redial.attempt, &mut thread_rng());
+
+        redial.attempt = redial.attempt.saturating_add(1);
+        redial.timer = Some(Timer::new(self.clock.clone(), delay));
+
+        let retry = self.clock.now() + delay;
+        for info in self.peers.iter_mut().filter(|info| info.id == id) {
+            info.backoff = Some(delay);
+            info.next_retry = Some(retry);
+        }
+    }
+
+    /// Dial a trusted peer right away if the failure hints at a broken
+    /// connection. Dialing is a no-op if the connection turns out to be fine.
+    fn redial_on_failure(&mut self, id: &PeerId, failure: &StatusFailure) {
+        let wanted = self.reconnect.as_ref().map_or(false, |reconnect| reconnect.policy().applies(failure));
+
+        if wanted && self.is_trusted(id) {
+            debug!("Re-dialing {:?} after status failure: {}", id, failure);
+            self.dial_peer(id.clone());
+        }
+    }
+
+    /// Release the slot of an outstanding dial once it succeeded or failed
+    fn complete_dials(&mut self, event: &ConnectionEvent) {
+        if self.outstanding.is_empty() {
+            return
+        }
+
+        match event {
+            ConnectionEvent::Established { peer, endpoint }
+            | ConnectionEvent::Replaced { peer, new: endpoint, .. } => {
+                self.outstanding.remove(&Dial::Peer(peer.clone()));
+
+                // Racing dials to other addresses of the peer are moot now
+                let mut addrs = self.kad.addresses_of_peer(peer);
+                if let ConnectedPoint::Dialer { address } = endpoint {
+                    addrs.push(address.clone());
+                }
+                for address in addrs {
+                    self.outstanding.remove(&Dial::Address(address));
+                }
+            },
+            ConnectionEvent::Unreachable { address, .. } => {
+                self.outstanding.remove(&Dial::Address(address.clone()));
+            },
+            ConnectionEvent::DialFailure { peer } => {
+                self.outstanding.remove(&Dial::Peer(peer.clone()));
+            },
+            ConnectionEvent::Closed { .. } => (),
+        }
+    }
+
+    /// Reliability score of peer, if status was exchanged with it
+    pub fn score(&self, id: &PeerId) -> Option<i32> {
+        self.scores.get(id).cloned()
+    }
+
+    /// Adjust score of peer after a status exchange, withholding its
+    /// addresses from Kademlia while it is below the threshold
+    fn update_score(&mut self, id: &PeerId, success: bool) {
+        let threshold = match self.score_threshold {
+            Some(threshold) => threshold,
+            None => return,
+        };
+
+        if self.is_trusted(id) {
+            return
+        }
+
+        let score = self.scores.entry(id.clone()).or_insert(0);
+        *score = if success { *score + 1 } else { *score - 1 }.max(-MAX_SCORE).min(MAX_SCORE);
+        let score = *score;
+
+        if score >= threshold {
+            self.kad.restore(id);
+        } else if self.kad.demote(id.clone()) {
+            self.events.push(BehaviourEvent::PeerDemoted { peer: id.clone(), score });
+        }
+    }
+
+    /// Remove untrusted peers that have been idle for longer than the TTL
+    fn evict_idle(&mut self) {
+        let ttl = match self.peer_ttl {
+            Some(ttl) => ttl,
+            None => return,
+        };
+
+        let events = &mut self.events;
+        let now = self.clock.now();
+
+        self.peers.retain(|info| {
+            // Being connected counts as activity
+            if info.trusted || info.online {
+                return true
+            }
+
+            if now.saturating_duration_since(info.last_active) < ttl {
+                true
+            } else {
+                events.push(BehaviourEvent::PeerEvicted { peer: info.id.clone() });
+                false
+            }
+        });
+    }
+
+    fn poll_events<T>(&mut self, cx: &mut Context) -> Poll<NetworkBehaviourAction<T, BehaviourEvent>> {
+        if let (Some(eviction), Some(ttl)) = (self.eviction.as_mut(), self.peer_ttl) {
+            if let Poll::Ready(()) = eviction.poll_unpin(cx) {
+                eviction.reset(ttl / 2);
+                self.evict_idle();
+            }
+        }
+
+        let mut due = Vec::new();
+        for (id, staggered) in self.staggered.iter_mut() {
+            if let Poll::Ready(_) = staggered.timer.poll_unpin(cx) {
+                due.push(id.clone());
+            }
+        }
+        for id in due {
+            self.dial_staggered(&id);
+        }
+
+        let mut due = Vec::new();
+        for (id, redial) in self.redials.iter_mut() {
+            if let Some(timer) = redial.timer.as_mut() {
+                if let Poll::Ready(_) = timer.poll_unpin(cx) {
+                    redial.timer = None;
+                    due.push(id.clone());
+                }
+            }
+        }
+        for id in due {
+            for info in self.peers.iter_mut().filter(|info| info.id == id) {
+                info.next_retry = None;
+            }
+            self.dial_peer(id);
+        }
+
+        if let Some(expiry) = self.dial_expiry.as_mut() {
+            if let Poll::Ready(()) = expiry.poll_unpin(cx) {
+                let now = self.clock.now();
+                self.outstanding.retain(|dial, requested| {
+                    let expired = now.saturating_duration_since(*requested) >= DIAL_TIMEOUT;
+                    if expired {
+                        debug!("Giving up on {:?} after no outcome was reported", dial);
+                    }
+                    !expired
+                });
+                self.dial_expiry = self.outstanding.values().min().map(|oldest| {
+                    Timer::new(self.clock.clone(), DIAL_TIMEOUT - now.saturating_duration_since(*oldest))
+                });
+            }
+        }
+
+        while self.max_dials.map_or(true, |max| self.outstanding.len() < max) {
+            let dial = match self.dials.pop_front() {
+                Some(dial) => dial,
+                None => break,
+            };
+
+            // The swarm reports nothing when dialing connected peers
+            if let Dial::Peer(id) = &dial {
+                if self.conn.connected_peers().any(|peer| peer == id) {
+                    continue
+                }
+            }
+
+            if self.max_dials.is_some() {
+                self.outstanding.insert(dial.clone(), self.clock.now());
+                if self.dial_expiry.is_none() {
+                    self.dial_expiry = Some(Timer::new(self.clock.clone(), DIAL_TIMEOUT));
+                }
+            }
+
+            return Poll::Ready(match dial {
+                Dial::Peer(peer_id) => NetworkBehaviourAction::DialPeer { peer_id },
+                Dial::Address(address) => NetworkBehaviourAction::DialAddress { address },
+            })
+        }
+
+        match self.events.pop() {
+            Some(event) => Poll::Ready(NetworkBehaviourAction::GenerateEvent(event)),
+            None => Poll::Pending,
+        }
+    }
+
+    /// Stop sending status requests to a peer without disconnecting it
+    pub fn pause_status(&mut self, id: &PeerId) {
+        self.status.pause(id);
+    }
+
+    /// Resume sending status requests to a previously paused peer
+    pub fn resume_status(&mut self, id: &PeerId) {
+        self.status.resume(id);
+    }
+
+    /// Request the status of a connected peer right away instead of waiting
+    /// for the next interval, unless its status requests are paused
+    pub fn request_status(&mut self, id: &PeerId) {
+        self.status.request(id);
+    }
+
+    /// Retrieve statistics of the status exchange with a connected peer
+    pub fn status_stats(&self, id: &PeerId) -> Option<StatusStats> {
+        self.status.stats(id).cloned()
+    }
+
+    /// Number of status results of all connections waiting to be processed
+    pub fn pending_status_results(&self) -> &PendingResults {
+        self.status.pending_results()
+    }
+
+    /// Take a snapshot of the current state of all watched peers
+    pub fn snapshot(&self) -> Vec<PeerSnapshot> {
+        let now = self.clock.now();
+
+        self.peers.iter()
+            .map(|info| PeerSnapshot {
+                id: info.id.clone(),
+                online: info.online,
+                last_seen: info.last_status_age(now),
+                status: info.status().cloned(),
+                rtt: info.rtt,
+                name: info.name.clone(),
+            })
+            .collect()
+    }
+
+    /// Time since the last status of the peer was received, `None` if never
+    /// or if the peer is not watched
+    pub fn status_age(&self, peer: &PeerId) -> Option<Duration> {
+        let now = self.clock.now();
+        self.peers.iter().find(|info| &info.id == peer).and_then(|info| info.last_status_age(now))
+    }
+
+    /// Peer whose last status is the oldest, with the age of that status.
+    ///
+    /// Peers we never received a status from are not considered.
+    pub fn stalest_peer(&self) -> Option<(PeerId, Duration)> {
+        let now = self.clock.now();
+
+        self.peers.iter()
+            .filter_map(|info| info.last_status_age(now).map(|age| (info.id.clone(), age)))
+            .max_by_key(|(_, age)| *age)
+    }
+
+    /// Retrieve a copy of the current info of all watched peers
+    pub fn all_peer_info(&self) -> Vec<PeerInfo> {
+        self.peers.clone()
+    }
+
+    /// Retrieve the latest status of all watched peers with a known status
+    pub fn statuses(&self) -> HashMap<PeerId, Payload> {
+        self.peers.iter()
+            .filter_map(|info| info.status().map(|status| (info.id.clone(), *status)))
+            .collect()
+    }
+
+    /// Watched peers matching the predicate
+    pub fn filter_peers(&self, pred: impl Fn(&PeerInfo) -> bool) -> Vec<PeerId> {
+        self.peers.iter()
+            .filter(|info| pred(info))
+            .map(|info| info.id.clone())
+            .collect()
+    }
+
+    /// Watched peers whose latest status matches the expected revision
+    pub fn peers_on_revision(&self, expected: &Payload) -> Vec<PeerId> {
+        self.filter_peers(|info| info.status().map_or(false, |status| self.matches(status, expected)))
+    }
+
+    /// Watched peers whose latest status differs from the expected revision,
+    /// peers without a known status are not included
+    pub fn peers_off_revision(&self, expected: &Payload) -> Vec<PeerId> {
+        self.filter_peers(|info| info.status().map_or(false, |status| !self.matches(status, expected)))
+    }
+
+    /// Watched peers not known to be on the expected revision, including
+    /// those without a known status
+    pub fn peers_missing_revision(&self, expected: &Payload) -> Vec<PeerId> {
+        self.filter_peers(|info| info.status().map_or(true, |status| !self.matches(status, expected)))
+    }
+
+    /// Watched peers we are currently not connected to
+    pub fn offline_peers(&self) -> Vec<PeerId> {
+        self.filter_peers(|info| !info.online())
+    }
+
+    /// Status watched peers are expected to agree on, by default the plurality
+    ///
+    /// Statuses are grouped by the first one of each group they match, in the
+    /// order peers are watched, so the plurality is the status matched by the
+    /// most peers, e.g. the first of several builds of the same release.
+    pub fn reference_status(&self) -> Option<Payload> {
+        if let Some(StatusReference::Expected(payload)) = &self.reference {
+            return Some(*payload)
+        }
+
+        let mut counts: Vec<(Payload, usize)> = Vec::new();
+        for status in self.peers.iter().filter_map(PeerInfo::status) {
+            match counts.iter_mut().find(|(first, _)| self.matches(status, first)) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((*status, 1)),
+            }
+        }
+
+        let max = counts.iter().map(|(_, count)| *count).max()?;
+        let mut leaders = counts.into_iter().filter(|(_, count)| *count == max);
+
+        match (leaders.next(), leaders.next()) {
+            (Some((payload, _)), None) => Some(payload),
+            _ => None,
+        }
+    }
+
+    /// Find a watched peer that reports another status than the reference
+    /// status, or than another watched peer if there is no reference, e.g. to
+    /// alert on diverging deployments
+    ///
+    /// Returns a peer on the status diverged from, if any reports it, the
+    /// diverging peer, the status diverged from and the diverging status.
+    pub fn detect_divergence(&self) -> Option<(Option<PeerId>, PeerId, Payload, Payload)> {
+        let statuses = self.statuses();
+
+        let (agreeing, expected) = match self.reference_status() {
+            Some(reference) => {
+                let agreeing = statuses.iter().find(|(_, status)| self.matches(status, &reference));
+                (agreeing.map(|(peer, _)| peer.clone()), reference)
+            },
+            None => statuses.iter().next().map(|(peer, status)| (Some(peer.clone()), *status))?,
+        };
+
+        statuses.iter()
+            .find(|(_, status)| !self.matches(status, &expected))
+            .map(|(diverging, status)| (agreeing, diverging.clone(), expected, *status))
+    }
+
+    /// Graph of the watched peers and the peers closest to each of them,
+    /// for those a Kademlia lookup has completed for
+    pub fn topology(&self) -> Topology {
+        self.peers.iter()
+            .filter_map(|info| info.routing().map(|closest| (info.id.clone(), closest.to_vec())))
+            .collect()
+    }
+
+    /// Retrieve current peer status by id
+    pub fn get_peer_info(&self, id: &PeerId) -> Option<PeerInfo> {
+        for peer in self.peers.iter() {
+            if &peer.id == id {
+                return Some(peer.clone())
+            }
+        }
+        None
+    }
+}
+
+impl NetworkBehaviourEventProcess<KademliaEvent> for Behaviour {
+    fn inject_event(&mut self, event: KademliaEvent) {
+        match event {
+            KademliaEvent::BootstrapResult(result) => {
+                match result {
+                    Ok(..) => {
+                        info!("Bootstrap successful");
+                        self.bootstrapped = true;
+                    },
+                    Err(error) => warn!("Bootstrap failed: {:?}", error),
+                }
+            },
+            KademliaEvent::GetClosestPeersResult(result) => {
+                match result {
+                    Ok(closest) => {
+                        if let Ok(id) = PeerId::from_bytes(closest.key) {
+
+                            if closest.peers.is_empty() {
+                                self.kad.get_closest_peers(id.clone());
+                            } else {
+                                let now = self.clock.now();
+                                if let Some(info) = self.peers.iter_mut().find(|info| info.id == id) {
+                                    debug!("Updated Kademlia peers of {:?}: {:?}", id, closest.peers);
+                                    info.routing = Some(PeerRouting(closest.peers, now));
+                                } else {
+                                    debug!("Unknown peer {:?}: {:?}", id, closest.peers);
                                 }
                             }
                         }
                     },
                     Err(error) => {
-                        println!("Failed to look up peer: {:#?}", error);
+                        debug!("Failed to look up peer: {:?}", error);
                     },
                 }
             },
+            KademliaEvent::RoutingUpdated { peer, old_peer, .. } => {
+                debug!("Added {:?} to routing table, replacing {:?}", peer, old_peer);
+
+                // The DHT just learned how to reach a trusted peer we lost or never had
+                let offline = self.peers.iter().any(|info| info.id == peer && info.trusted && !info.online);
+                if offline {
+                    self.dial_peer(peer.clone());
+                }
+
+                self.events.push(BehaviourEvent::RoutingUpdated { peer, evicted: old_peer });
+            },
             _ => (),
         }
     }
@@ -161,25 +1750,1503 @@ impl NetworkBehaviourEventProcess<MdnsEvent> for Behaviour {
         match event {
             MdnsEvent::Discovered(list) => {
                 for (peer, addr) in list {
-                    // Add discovered nodes to kademlia
-                    self.kad.add_address(&peer, addr.clone());
-
-                    println!("Discovered {:#?} via {:#?}", peer, addr);
+                    self.mdns_discovered(peer, addr);
                 }
             },
             MdnsEvent::Expired(list) => {
                 for (peer, addr) in list {
-                    println!("Expired {:#?} via {:#?}", peer, addr);
+                    self.mdns_expired(peer, addr);
                 }
             }
         }
     }
 }
 
-impl NetworkBehaviourEventProcess<StatusEvent> for Behaviour {
-    fn inject_event(&mut self, event: StatusEvent) {
-        if let Ok(StatusSuccess::Received(status)) = event.result {
-            println!("Received status '{:#?}' from {:?}", status, event.peer);
-        }
+impl NetworkBehaviourEventProcess<ConnectionEvent> for Behaviour {
+    fn inject_event(&mut self, event: ConnectionEvent) {
+        self.complete_dials(&event);
+
+        let (peer, online, address) = match event {
+            ConnectionEvent::Established { peer, endpoint } => {
+                self.redials.remove(&peer);
+                self.staggered.remove(&peer);
+                if self.at_connection_limit(1) {
+                    self.drop_queued_dials(&peer);
+                }
+                self.attempts.remove(&peer);
+                if let ConnectedPoint::Dialer { address } = &endpoint {
+                    self.kad.record_success(&peer, address);
+                }
+                match endpoint {
+                    ConnectedPoint::Dialer { address } => (peer, true, Some(address)),
+                    ConnectedPoint::Listener { .. } => (peer, true, None),
+                }
+            },
+            ConnectionEvent::Closed { peer, .. } => {
+                if let Some(table) = &self.security {
+                    table.lock().unwrap().remove(&peer);
+                }
+                self.withheld.remove(&peer);
+                self.schedule_redial(peer.clone());
+                (peer, false, None)
+            },
+            ConnectionEvent::Replaced { peer, new, .. } => {
+                debug!("Connection to {:?} replaced by one via {:?}", peer, new);
+                self.staggered.remove(&peer);
+                if self.at_connection_limit(1) {
+                    self.drop_queued_dials(&peer);
+                }
+                self.attempts.remove(&peer);
+                if let ConnectedPoint::Dialer { address } = &new {
+                    self.kad.record_success(&peer, address);
+                }
+                if self.peers.iter().any(|info| info.id == peer) {
+                    self.events.push(BehaviourEvent::ConnectionReplaced { peer });
+                }
+                return
+            },
+            ConnectionEvent::DialFailure { peer } => {
+                self.give_up_dial(&peer);
+                self.schedule_redial(peer);
+                return
+            },
+            ConnectionEvent::Unreachable { peer, address, error } => {
+                self.events.push(BehaviourEvent::DialFailed { peer: peer.clone(), address: address.clone(), error });
+                // No need to wait for the other family once the first failed
+                let racing = self.staggered.iter()
+                    .find(|(_, staggered)| staggered.first == address)
+                    .map(|(peer, _)| peer.clone());
+                if let Some(racing) = racing {
+                    self.dial_staggered(&racing);
+                }
+                self.dial_failed(peer, &address);
+                return
+            },
+        };
+
+        let security = match (&self.security, online) {
+            (Some(table), true) => table.lock().unwrap().get(&peer).cloned(),
+            _ => None,
+        };
+
+        for info in self.peers.iter_mut() {
+            if info.id == peer {
+                info.online = online;
+                info.last_active = self.clock.now();
+                if online {
+                    info.backoff = None;
+                    info.next_retry = None;
+                }
+
+                self.events.push(if online {
+                    BehaviourEvent::PeerOnline { peer: peer.clone() }
+                } else {
+                    BehaviourEvent::PeerOffline { peer: peer.clone() }
+                });
+
+                if let Some(security) = &security {
+                    info.security = Some(security.clone());
+                    self.events.push(BehaviourEvent::SecurityNegotiated {
+                        peer: peer.clone(),
+                        security: security.clone(),
+                    });
+                }
+
+                if let Some(address) = &address {
+                    if let Some(old) = info.addresses.last().filter(|_| !info.addresses.contains(address)) {
+                        self.events.push(BehaviourEvent::PeerAddressChanged {
+                            peer: peer.clone(),
+                            old: old.clone(),
+                            new: address.clone(),
+                        });
+                    }
+
+                    info.addresses.retain(|known| known != address);
+                    if info.addresses.len() >= KNOWN_ADDRESSES {
+                        info.addresses.remove(0);
+                    }
+                    info.addresses.push(address.clone());
+                }
+            }
+        }
+    }
+}
+
+impl NetworkBehaviourEventProcess<StatusEvent> for Behaviour {
+    fn inject_event(&mut self, event: StatusEvent) {
+        match &event.result {
+            Ok(_) => self.update_score(&event.peer, true),
+            // Neither not speaking the protocol nor flooding us with requests
+            // makes a peer unreliable as a route
+            Err(StatusFailure::Unsupported) | Err(StatusFailure::Throttled) => (),
+            Err(_) => self.update_score(&event.peer, false),
+        }
+
+        if let Err(failure) = &event.result {
+            self.redial_on_failure(&event.peer, failure);
+        }
+
+        if let Ok(StatusSuccess::Withheld) = &event.result {
+            if self.withheld.insert(event.peer.clone()) {
+                self.events.push(BehaviourEvent::StatusWithheld { peer: event.peer.clone() });
+            }
+        }
+
+        // Only our own requests tell how the connection to the peer is doing
+        let outcome = match &event.result {
+            Ok(StatusSuccess::Received { .. }) => Some(true),
+            Ok(StatusSuccess::Requested) | Ok(StatusSuccess::Withheld) | Err(StatusFailure::Unsupported) | Err(StatusFailure::Throttled) => None,
+            Err(_) => Some(false),
+        };
+        if let Some(success) = outcome {
+            for info in self.peers.iter_mut().filter(|info| info.id == event.peer) {
+                info.record_outcome(success);
+            }
+        }
+
+        if let Ok(StatusSuccess::Received { response, rtt, protocol }) = event.result {
+            trace!("Received status {:?} from {:?}", response.payload, event.peer);
+
+            if response.version != VERSION {
+                self.events.push(BehaviourEvent::VersionMismatch {
+                    peer: event.peer.clone(),
+                    theirs: response.version,
+                    ours: VERSION,
+                });
+            }
+
+            // Do not interpret payloads in a format we do not understand
+            let understood = response.schema_matches(self.schema.as_ref().map(String::as_str));
+
+            // Alert only once per schema declared by the peer
+            let declared = self.peers.iter().any(|info| info.id == event.peer && info.schema == response.schema);
+            if !understood && !declared {
+                if let (Some(ours), Some(theirs)) = (&self.schema, &response.schema) {
+                    self.events.push(BehaviourEvent::SchemaMismatch {
+                        peer: event.peer.clone(),
+                        theirs: theirs.clone(),
+                        ours: ours.clone(),
+                    });
+                }
+            }
+
+            // Nor those the application considers invalid, while withheld ones
+            // are no status at all
+            let validator = &self.validator;
+            let valid = |peer: &PeerId, payload: &Payload| validator.as_ref().map_or(true, |valid| valid(peer, payload));
+
+            let accepted = understood && !response.withheld && valid(&event.peer, &response.payload);
+            if understood && !response.withheld && !accepted {
+                self.events.push(BehaviourEvent::PayloadRejected {
+                    peer: event.peer.clone(),
+                    payload: response.payload,
+                });
+            }
+
+            let now = self.clock.now();
+            let mut changed = false;
+            for info in self.peers.iter_mut() {
+                if info.id == event.peer {
+                    if accepted {
+                        changed = info.status() != Some(&response.payload);
+                        info.update_status(response.payload, now, None);
+                    }
+                    info.rtt = Some(rtt);
+                    info.last_active = now;
+                    info.withheld = response.withheld;
+
+                    if !response.withheld {
+                        info.name = response.name.clone();
+                        info.schema = response.schema.clone();
+
+                        if let Some(theirs) = response.capabilities {
+                            info.capabilities = Some(self.capabilities.intersection(theirs));
+                        }
+                    }
+
+                    if let Some(epoch) = response.epoch {
+                        if info.epoch.map_or(false, |known| known != epoch) {
+                            self.events.push(BehaviourEvent::PeerRestarted { peer: event.peer.clone() });
+                        }
+                        info.epoch = Some(epoch);
+                    }
+
+                    if info.protocol.as_ref() != Some(&protocol) {
+                        info.protocol = Some(protocol.clone());
+                        self.events.push(BehaviourEvent::ProtocolNegotiated {
+                            peer: event.peer.clone(),
+                            protocol: protocol.clone(),
+                        });
+                    }
+                } else if let Some(gossip) = response.gossip.iter().find(|g| understood && g.peer == info.id && valid(&g.peer, &g.payload)) {
+                    if let Some(received) = now.checked_sub(gossip.age) {
+                        info.update_status(gossip.payload, received, Some(event.peer.clone()));
+                    }
+                }
+            }
+
+            // Alert only once per new status
+            if changed && self.reference.is_some() {
+                if let Some(reference) = self.reference_status() {
+                    if !self.matches(&response.payload, &reference) {
+                        self.events.push(BehaviourEvent::StatusDiverged {
+                            peer: event.peer.clone(),
+                            status: response.payload,
+                            reference,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Behaviour, BehaviourConfig, BehaviourEvent, Compatibility, DIAL_TIMEOUT, Dial, StatusReference};
+    use crate::reconnect::{ReconnectConfig, RedialPolicy};
+    use crate::transport::{AddressPreference, RACE_DELAY, SecurityTable};
+    use crate::status::{
+        Capabilities,
+        Gossip,
+        StatusConfig,
+        StatusEvent,
+        StatusFailure,
+        StatusSuccess,
+        Payload,
+        Response,
+        VERSION,
+        generate_payload,
+        protocol::WITHHELD,
+    };
+    use crate::connection::ConnectionEvent;
+    use libp2p::{
+        Multiaddr,
+        PeerId,
+        core::ConnectedPoint,
+        identity::Keypair,
+        kad::{Addresses, KademliaEvent},
+        swarm::{NetworkBehaviour, NetworkBehaviourAction, NetworkBehaviourEventProcess},
+    };
+    use std::task::{Context, Poll};
+    use crate::clock::{Clock, ManualClock};
+    use std::{sync::Arc, time::Duration};
+
+    /// Behaviour without mDNS for local testing
+    fn behaviour(config: impl FnOnce(BehaviourConfig) -> BehaviourConfig) -> Behaviour {
+        let id = Keypair::generate_ed25519().public().into_peer_id();
+        let base = BehaviourConfig::new(StatusConfig::new(generate_payload())).with_mdns(false);
+
+        Behaviour::with_config(id, config(base))
+    }
+
+    /// Status event as produced by a successful exchange
+    fn received(peer: &PeerId, response: Response) -> StatusEvent {
+        StatusEvent {
+            peer: peer.clone(),
+            result: Ok(StatusSuccess::Received {
+                response,
+                rtt: Duration::from_millis(1),
+                protocol: String::from("/dx/status/0.1.0"),
+            }),
+        }
+    }
+
+    #[test]
+    fn behaviour_without_mdns() {
+        let behaviour = behaviour(|c| c);
+
+        assert!(!behaviour.mdns_enabled());
+    }
+
+    #[test]
+    fn debounce_mdns_discoveries() {
+        let clock = ManualClock::new();
+        let window = Duration::from_secs(10);
+        let mut eager = behaviour(|c| c.with_mdns_debounce(Duration::new(0, 0)));
+        let mut behaviour = behaviour(|c| c.with_mdns_debounce(window).with_clock(Arc::new(clock.clone())));
+        let peer = PeerId::random();
+        let (addr, other): (Multiaddr, Multiaddr) = ("/memory/1234".parse().unwrap(), "/memory/5678".parse().unwrap());
+
+        // Rapid repetitions amount to a single add, other addresses are added
+        let adds = (0..5).filter(|_| behaviour.mdns_discovered(peer.clone(), addr.clone())).count();
+        assert_eq!(adds, 1);
+        assert!(behaviour.mdns_discovered(peer.clone(), other.clone()));
+        assert!(behaviour.kad.addresses_of_peer(&peer).contains(&addr));
+
+        // Until the window passed
+        clock.advance(window - Duration::from_secs(1));
+        assert!(!behaviour.mdns_discovered(peer.clone(), addr.clone()));
+        clock.advance(Duration::from_secs(1));
+        assert!(behaviour.mdns_discovered(peer.clone(), addr.clone()));
+
+        // Without a window, nothing is held back
+        assert!(eager.mdns_discovered(peer.clone(), addr.clone()));
+        assert!(eager.mdns_discovered(peer, addr));
+    }
+
+    #[test]
+    fn evict_idle_untrusted_peers() {
+        let clock = ManualClock::new();
+        let ttl = Duration::from_secs(60);
+        let mut behaviour = behaviour(|c| c.with_peer_ttl(ttl).with_clock(Arc::new(clock.clone())));
+
+        let stranger = PeerId::random();
+        let friend = PeerId::random();
+        behaviour.add_peers(stranger.clone());
+        behaviour.add_trusted_peer(friend.clone());
+
+        clock.advance(ttl - Duration::from_secs(1));
+        behaviour.evict_idle();
+        assert!(behaviour.get_peer_info(&stranger).is_some());
+
+        clock.advance(Duration::from_secs(1));
+        behaviour.evict_idle();
+
+        assert!(behaviour.get_peer_info(&stranger).is_none());
+        assert!(behaviour.get_peer_info(&friend).is_some());
+
+        match behaviour.events.pop() {
+            Some(BehaviourEvent::PeerEvicted { peer }) => assert_eq!(peer, stranger),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn evict_from_disconnection() {
+        let clock = ManualClock::new();
+        let ttl = Duration::from_secs(60);
+        let mut behaviour = behaviour(|c| c.with_peer_ttl(ttl).with_clock(Arc::new(clock.clone())));
+
+        let peer = PeerId::random();
+        behaviour.add_peers(peer.clone());
+
+        // Connected for longer than the TTL, without any status
+        let endpoint = ConnectedPoint::Dialer { address: "/memory/1234".parse().unwrap() };
+        behaviour.inject_event(ConnectionEvent::Established { peer: peer.clone(), endpoint: endpoint.clone() });
+        clock.advance(ttl * 2);
+        behaviour.evict_idle();
+        assert!(behaviour.get_peer_info(&peer).is_some());
+
+        // Idle time only starts counting once the connection is closed
+        behaviour.inject_event(ConnectionEvent::Closed { peer: peer.clone(), endpoint });
+        clock.advance(ttl - Duration::from_secs(1));
+        behaviour.evict_idle();
+        assert!(behaviour.get_peer_info(&peer).is_some());
+
+        clock.advance(Duration::from_secs(1));
+        behaviour.evict_idle();
+        assert!(behaviour.get_peer_info(&peer).is_none());
+    }
+
+    #[test]
+    fn forget_security_of_closed_connections() {
+        let table = SecurityTable::default();
+        let mut behaviour = behaviour(|c| c.with_security_table(table.clone()));
+        let peer = PeerId::random();
+        behaviour.add_peers(peer.clone());
+
+        // Recorded by the transport while connecting
+        table.lock().unwrap().insert(peer.clone(), String::from("/secio/1.0.0"));
+        let endpoint = ConnectedPoint::Dialer { address: "/memory/1234".parse().unwrap() };
+        behaviour.inject_event(ConnectionEvent::Established { peer: peer.clone(), endpoint: endpoint.clone() });
+        assert_eq!(behaviour.get_peer_info(&peer).unwrap().security(), Some("/secio/1.0.0"));
+
+        behaviour.inject_event(ConnectionEvent::Closed { peer: peer.clone(), endpoint });
+        assert!(table.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn zero_peer_ttl_never_expires() {
+        let clock = ManualClock::new();
+        let mut behaviour = behaviour(|c| c.with_peer_ttl(Duration::from_secs(0)).with_clock(Arc::new(clock.clone())));
+
+        let peer = PeerId::random();
+        behaviour.add_peers(peer.clone());
+        clock.advance(Duration::from_secs(24 * 60 * 60));
+        behaviour.evict_idle();
+        assert!(behaviour.get_peer_info(&peer).is_some());
+    }
+
+    #[test]
+    fn report_version_mismatch() {
+        let mut behaviour = behaviour(|c| c);
+        let peer = PeerId::random();
+
+        let response = Response { version: VERSION + 1, ..Response::new(generate_payload()) };
+        behaviour.inject_event(received(&peer, response));
+
+        match behaviour.events.pop() {
+            Some(BehaviourEvent::VersionMismatch { peer: p, theirs, ours }) => {
+                assert_eq!(p, peer);
+                assert_eq!(theirs, VERSION + 1);
+                assert_eq!(ours, VERSION);
+            },
+            other => panic!("Unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bulk_peer_info() {
+        let mut behaviour = behaviour(|c| c);
+
+        let peers: Vec<PeerId> = (0..3).map(|_| PeerId::random()).collect();
+        for peer in peers.iter() {
+            behaviour.add_peers(peer.clone());
+        }
+
+        // Only the first two peers report a status
+        let payloads = [generate_payload(), generate_payload()];
+        for (peer, payload) in peers.iter().zip(payloads.iter()) {
+            let response = Response::new(*payload);
+            behaviour.inject_event(received(peer, response));
+        }
+
+        let all = behaviour.all_peer_info();
+        assert_eq!(all.len(), 3);
+        for peer in peers.iter() {
+            assert!(all.iter().any(|info| info.id() == peer));
+        }
+
+        let statuses = behaviour.statuses();
+        assert_eq!(statuses.len(), 2);
+        assert!(statuses[&peers[0]] == payloads[0]);
+        assert!(statuses[&peers[1]] == payloads[1]);
+    }
+
+    #[test]
+    fn online_on_connection() {
+        let mut behaviour = behaviour(|c| c);
+        let peer = PeerId::random();
+        behaviour.add_peers(peer.clone());
+
+        let endpoint = ConnectedPoint::Dialer { address: "/memory/1234".parse().unwrap() };
+        behaviour.inject_event(ConnectionEvent::Established { peer: peer.clone(), endpoint: endpoint.clone() });
+
+        // No status has been exchanged yet
+        let info = behaviour.get_peer_info(&peer).unwrap();
+        assert!(info.online());
+        assert!(info.status().is_none());
+
+        behaviour.inject_event(ConnectionEvent::Closed { peer: peer.clone(), endpoint });
+        assert!(!behaviour.get_peer_info(&peer).unwrap().online());
+    }
+
+    #[test]
+    fn dial_peers_entering_routing() {
+        let mut behaviour = behaviour(|c| c);
+        let (friend, stranger) = (PeerId::random(), PeerId::random());
+        behaviour.add_trusted_peer(friend.clone());
+        behaviour.add_peers(stranger.clone());
+
+        let routed = |peer: &PeerId| KademliaEvent::RoutingUpdated {
+            peer: peer.clone(),
+            addresses: Addresses::new("/memory/1234".parse().unwrap()),
+            old_peer: None,
+        };
+
+        // Only offline trusted peers are dialed, but every update is reported
+        behaviour.inject_event(routed(&stranger));
+        assert!(behaviour.dials.is_empty());
+
+        behaviour.inject_event(routed(&friend));
+        assert_eq!(behaviour.dials.drain(..).collect::<Vec<_>>(), vec![Dial::Peer(friend.clone())]);
+
+        let endpoint = ConnectedPoint::Dialer { address: "/memory/1234".parse().unwrap() };
+        behaviour.inject_event(ConnectionEvent::Established { peer: friend.clone(), endpoint });
+        behaviour.inject_event(routed(&friend));
+        assert!(behaviour.dials.is_empty());
+
+        let updates: Vec<PeerId> = std::iter::from_fn(|| behaviour.events.pop())
+            .filter_map(|event| match event {
+                BehaviourEvent::RoutingUpdated { peer, evicted: None } => Some(peer),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(updates, vec![stranger, friend.clone(), friend]);
+    }
+
+    #[test]
+    fn detect_address_change() {
+        let mut behaviour = behaviour(|c| c);
+        let peer = PeerId::random();
+        behaviour.add_peers(peer.clone());
+
+        let connect = |behaviour: &mut Behaviour, address: &str| {
+            let endpoint = ConnectedPoint::Dialer { address: address.parse().unwrap() };
+            behaviour.inject_event(ConnectionEvent::Established { peer: peer.clone(), endpoint: endpoint.clone() });
+            behaviour.inject_event(ConnectionEvent::Closed { peer: peer.clone(), endpoint });
+
+            let mut changes = Vec::new();
+            while let Some(event) = behaviour.events.pop() {
+                if let BehaviourEvent::PeerAddressChanged { old, new, .. } = event {
+                    changes.push((old.to_string(), new.to_string()));
+                }
+            }
+            changes
+        };
+
+        // The first address is no change, nor is reconnecting to it
+        assert!(connect(&mut behaviour, "/memory/1234").is_empty());
+        assert!(connect(&mut behaviour, "/memory/1234").is_empty());
+
+        assert_eq!(connect(&mut behaviour, "/memory/5678"), vec![("/memory/1234".to_owned(), "/memory/5678".to_owned())]);
+
+        // Moving back to a known address is no news either
+        assert!(connect(&mut behaviour, "/memory/1234").is_empty());
+
+        let info = behaviour.get_peer_info(&peer).unwrap();
+        let addresses: Vec<String> = info.addresses().iter().map(|a| a.to_string()).collect();
+        assert_eq!(addresses, vec!["/memory/5678", "/memory/1234"]);
+
+        // Inbound connections are not taken into account
+        let endpoint = ConnectedPoint::Listener {
+            local_addr: "/memory/1".parse().unwrap(),
+            send_back_addr: "/memory/9999".parse().unwrap(),
+        };
+        behaviour.inject_event(ConnectionEvent::Established { peer: peer.clone(), endpoint });
+        assert_eq!(behaviour.get_peer_info(&peer).unwrap().addresses().len(), 2);
+    }
+
+    #[test]
+    fn peers_by_revision() {
+        let mut behaviour = behaviour(|c| c);
+
+        let peers: Vec<PeerId> = (0..3).map(|_| PeerId::random()).collect();
+        for peer in peers.iter() {
+            behaviour.add_peers(peer.clone());
+        }
+
+        // First peer is up to date, second one lags behind, third is unknown
+        let expected = generate_payload();
+        for (peer, payload) in peers.iter().zip([expected, generate_payload()].iter()) {
+            let response = Response::new(*payload);
+            behaviour.inject_event(received(peer, response));
+        }
+
+        assert_eq!(behaviour.peers_on_revision(&expected), vec![peers[0].clone()]);
+        assert_eq!(behaviour.peers_off_revision(&expected), vec![peers[1].clone()]);
+    }
+
+    #[test]
+    fn store_negotiated_protocol() {
+        let mut behaviour = behaviour(|c| c);
+        let peer = PeerId::random();
+        behaviour.add_peers(peer.clone());
+
+        // Reported only once as long as it does not change
+        for _ in 0..2 {
+            let response = Response::new(generate_payload());
+            behaviour.inject_event(received(&peer, response));
+        }
+
+        let info = behaviour.get_peer_info(&peer).unwrap();
+        assert_eq!(info.protocol(), Some("/dx/status/0.1.0"));
+
+        match behaviour.events.pop() {
+            Some(BehaviourEvent::ProtocolNegotiated { peer: p, protocol }) => {
+                assert_eq!(p, peer);
+                assert_eq!(protocol, "/dx/status/0.1.0");
+            },
+            other => panic!("Unexpected event: {:?}", other),
+        }
+        assert!(behaviour.events.is_empty());
+    }
+
+    #[test]
+    fn report_divergence() {
+        let mut behaviour = behaviour(|c| c.with_reference(StatusReference::Plurality));
+
+        let peers: Vec<PeerId> = (0..3).map(|_| PeerId::random()).collect();
+        for peer in peers.iter() {
+            behaviour.add_peers(peer.clone());
+        }
+
+        // Third peer lags behind the other two
+        let current = generate_payload();
+        let old = generate_payload();
+        for (peer, payload) in peers.iter().zip([current, current, old].iter()) {
+            let response = Response::new(*payload);
+            behaviour.inject_event(received(peer, response));
+        }
+
+        let diverged = behaviour.events.iter().filter_map(|event| match event {
+            BehaviourEvent::StatusDiverged { peer, status, reference } => Some((peer, status, reference)),
+            _ => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(diverged.len(), 1);
+        assert_eq!(diverged[0].0, &peers[2]);
+        assert!(*diverged[0].1 == old && *diverged[0].2 == current);
+
+        let (agreeing, diverging, reference, status) = behaviour.detect_divergence().unwrap();
+        assert!(agreeing == Some(peers[0].clone()) || agreeing == Some(peers[1].clone()));
+        assert_eq!(diverging, peers[2]);
+        assert!(reference == current && status == old);
+    }
+
+    #[test]
+    fn report_divergence_from_expected() {
+        let expected = generate_payload();
+        let mut behaviour = behaviour(|c| c.with_reference(StatusReference::Expected(expected)));
+
+        let peer = PeerId::random();
+        let old = generate_payload();
+        behaviour.add_peers(peer.clone());
+        let response = Response::new(old);
+        behaviour.inject_event(received(&peer, response));
+
+        // Nobody is on the expected status yet, which is still a divergence
+        let (agreeing, diverging, reference, status) = behaviour.detect_divergence().unwrap();
+        assert_eq!(agreeing, None);
+        assert_eq!(diverging, peer);
+        assert!(reference == expected && status == old);
+    }
+
+    #[test]
+    fn single_connection_per_peer() {
+        let clock = ManualClock::new();
+        let mut eager = behaviour(|c| c.with_max_connections_per_peer(None));
+        let mut behaviour = behaviour(|c| c.with_clock(Arc::new(clock.clone())));
+        let friend = PeerId::random();
+        let (v6, v4): (Multiaddr, Multiaddr) = ("/ip6/2001:db8::1/tcp/4001".parse().unwrap(), "/ip4/192.0.2.1/tcp/4001".parse().unwrap());
+        let inbound = ConnectedPoint::Listener {
+            local_addr: "/ip4/192.0.2.2/tcp/4001".parse().unwrap(),
+            send_back_addr: "/ip4/192.0.2.1/tcp/50000".parse().unwrap(),
+        };
+
+        for behaviour in [&mut behaviour, &mut eager].iter_mut() {
+            behaviour.add_trusted_peer(friend.clone());
+            behaviour.kad.add_address(&friend, v6.clone());
+            behaviour.kad.add_address(&friend, v4.clone());
+
+            // Dialed at startup and again once discovered, then it connects to us
+            behaviour.dial_peer(friend.clone());
+            behaviour.dial_peer(friend.clone());
+            NetworkBehaviour::inject_connected(&mut behaviour.conn, friend.clone(), inbound.clone());
+            behaviour.inject_event(ConnectionEvent::Established { peer: friend.clone(), endpoint: inbound.clone() });
+        }
+
+        // Dials not yet requested from the swarm are moot once connected
+        assert!(behaviour.dials.is_empty());
+        assert_eq!(eager.dials.len(), 2);
+
+        // Nor is the first connection replaced by dialing again
+        behaviour.dial_peer(friend.clone());
+        eager.dial_peer(friend.clone());
+        assert!(behaviour.dials.is_empty());
+        assert_eq!(eager.dials.len(), 3);
+
+        // A dial the swarm never reports back on only holds off others for a while
+        let lost = PeerId::random();
+        behaviour.add_trusted_peer(lost.clone());
+        behaviour.dial_peer(lost.clone());
+        behaviour.dials.clear();
+
+        clock.advance(DIAL_TIMEOUT - Duration::from_secs(1));
+        behaviour.dial_peer(lost.clone());
+        assert!(behaviour.dials.is_empty());
+
+        clock.advance(Duration::from_secs(1));
+        behaviour.dial_peer(lost.clone());
+        assert_eq!(behaviour.dials.len(), 1);
+    }
+
+    #[test]
+    fn compare_payloads() {
+        let mut strict = behaviour(|c| c);
+        let mut behaviour = behaviour(|c| c.with_reference(StatusReference::Plurality));
+
+        // Only the first four bytes hold the release, the rest is the build
+        behaviour.set_payload_comparator(Box::new(|a: &Payload, b: &Payload| match (a == b, a[..4] == b[..4]) {
+            (true, _) => Compatibility::Equal,
+            (false, true) => Compatibility::Compatible,
+            (false, false) => Compatibility::Incompatible,
+        }));
+
+        let release = generate_payload();
+        let mut rebuilt = generate_payload();
+        rebuilt[..4].copy_from_slice(&release[..4]);
+        let mut other = generate_payload();
+        other[0] = !release[0];
+
+        let peers: Vec<PeerId> = (0..4).map(|_| PeerId::random()).collect();
+        for (peer, payload) in peers.iter().zip([release, release, rebuilt, other].iter()) {
+            let response = Response::new(*payload);
+            for behaviour in [&mut behaviour, &mut strict].iter_mut() {
+                behaviour.add_peers(peer.clone());
+                behaviour.inject_event(received(peer, response.clone()));
+            }
+        }
+
+        assert_eq!(behaviour.compatibility(&release, &release), Compatibility::Equal);
+        assert_eq!(behaviour.compatibility(&release, &rebuilt), Compatibility::Compatible);
+        assert_eq!(strict.compatibility(&release, &rebuilt), Compatibility::Incompatible);
+
+        // A different build of the same release is no divergence
+        let diverged: Vec<&PeerId> = behaviour.events.iter().filter_map(|event| match event {
+            BehaviourEvent::StatusDiverged { peer, .. } => Some(peer),
+            _ => None,
+        }).collect();
+        assert_eq!(diverged, vec![&peers[3]]);
+        assert_eq!(behaviour.detect_divergence().map(|(_, diverging, _, _)| diverging), Some(peers[3].clone()));
+
+        assert_eq!(behaviour.peers_on_revision(&release), peers[..3].to_vec());
+        assert_eq!(behaviour.peers_off_revision(&release), vec![peers[3].clone()]);
+        assert_eq!(strict.peers_on_revision(&release), peers[..2].to_vec());
+        assert_eq!(strict.peers_off_revision(&release), peers[2..].to_vec());
+    }
+
+    #[test]
+    fn plurality_of_compatible_statuses() {
+        let mut behaviour = behaviour(|c| c.with_reference(StatusReference::Plurality));
+        behaviour.set_payload_comparator(Box::new(|a: &Payload, b: &Payload| match (a == b, a[..4] == b[..4]) {
+            (true, _) => Compatibility::Equal,
+            (false, true) => Compatibility::Compatible,
+            (false, false) => Compatibility::Incompatible,
+        }));
+
+        // Three builds of one release, each reported once, and another release twice
+        let release = generate_payload();
+        let builds: Vec<Payload> = (0..3).map(|_| {
+            let mut build = generate_payload();
+            build[..4].copy_from_slice(&release[..4]);
+            build
+        }).collect();
+        let mut other = generate_payload();
+        other[0] = !release[0];
+
+        let peers: Vec<PeerId> = (0..5).map(|_| PeerId::random()).collect();
+        for (peer, payload) in peers.iter().zip(builds.iter().chain([other, other].iter())) {
+            behaviour.add_peers(peer.clone());
+            behaviour.inject_event(received(peer, Response::new(*payload)));
+        }
+
+        // The builds together outnumber the other release
+        assert_eq!(behaviour.reference_status(), Some(builds[0]));
+        assert_eq!(behaviour.detect_divergence().map(|(_, diverging, _, _)| diverging), Some(peers[3].clone()));
+        assert_eq!(behaviour.peers_off_revision(&builds[0]), peers[3..].to_vec());
+    }
+
+    #[test]
+    fn demote_flaky_peers() {
+        let mut behaviour = behaviour(|c| c.with_score_threshold(Some(-2)));
+        let flaky = PeerId::random();
+        let friend = PeerId::random();
+        behaviour.add_trusted_peer(friend.clone());
+
+        for peer in [&flaky, &friend].iter() {
+            behaviour.kad.add_address(peer, "/memory/1234".parse().unwrap());
+            behaviour.kad.add_address(peer, "/memory/5678".parse().unwrap());
+        }
+
+        let fail = |peer: &PeerId| StatusEvent { peer: peer.clone(), result: Err(StatusFailure::Timeout) };
+
+        for _ in 0..2 {
+            behaviour.inject_event(fail(&flaky));
+            behaviour.inject_event(fail(&friend));
+        }
+        assert_eq!(behaviour.score(&flaky), Some(-2));
+
+        // Being throttled says nothing about the peer as a route
+        for _ in 0..5 {
+            behaviour.inject_event(StatusEvent { peer: flaky.clone(), result: Err(StatusFailure::Throttled) });
+        }
+        assert_eq!(behaviour.score(&flaky), Some(-2));
+        assert!(!behaviour.kad.addresses_of_peer(&flaky).is_empty());
+
+        // Crossing the threshold withholds its addresses, trusted peers are kept
+        behaviour.inject_event(fail(&flaky));
+        behaviour.inject_event(fail(&friend));
+
+        assert!(behaviour.kad.addresses_of_peer(&flaky).is_empty());
+        assert_eq!(NetworkBehaviour::addresses_of_peer(&mut *behaviour.kad, &flaky).len(), 2);
+        assert!(!behaviour.kad.addresses_of_peer(&friend).is_empty());
+        assert_eq!(NetworkBehaviour::addresses_of_peer(&mut *behaviour.kad, &friend).len(), 2);
+        assert_eq!(behaviour.score(&friend), None);
+
+        match behaviour.events.pop() {
+            Some(BehaviourEvent::PeerDemoted { peer, score }) => {
+                assert_eq!(peer, flaky);
+                assert_eq!(score, -3);
+            },
+            other => panic!("Unexpected event: {:?}", other),
+        }
+
+        // Recovering restores them
+        let response = Response::new(generate_payload());
+        behaviour.inject_event(received(&flaky, response));
+        assert!(!behaviour.kad.addresses_of_peer(&flaky).is_empty());
+    }
+
+    #[test]
+    fn drain_before_shutdown() {
+        let mut behaviour = behaviour(|c| c.with_bootstrap(Vec::new()));
+        let friend = PeerId::random();
+        behaviour.add_trusted_peer(friend.clone());
+        behaviour.kad.add_address(&friend, "/memory/1234".parse().unwrap());
+        behaviour.dials.clear();
+
+        behaviour.begin_drain();
+        assert!(behaviour.is_draining());
+        match behaviour.events.pop() {
+            Some(BehaviourEvent::Draining) => (),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+
+        // Reported only once, and nobody is dialed anymore
+        behaviour.begin_drain();
+        behaviour.dial_peer(friend);
+        assert!(behaviour.events.pop().is_none());
+        assert!(behaviour.dials.is_empty());
+    }
+
+    #[test]
+    fn report_redial_backoff() {
+        let clock = ManualClock::new();
+        let reconnect = ReconnectConfig::default()
+            .with_base(Duration::from_secs(2))
+            .with_max(Duration::from_secs(60))
+            .with_jitter(0.0);
+        let mut behaviour = behaviour(|c| c.with_reconnect(Some(reconnect)).with_clock(Arc::new(clock.clone())));
+        let friend = PeerId::random();
+        behaviour.add_trusted_peer(friend.clone());
+
+        let retry = |behaviour: &Behaviour| behaviour.get_peer_info(&friend).unwrap().next_retry_in_at(clock.now());
+        assert_eq!(retry(&behaviour), None);
+
+        // Losing the connection is retried right away, failed dials ever later
+        let endpoint = ConnectedPoint::Dialer { address: "/memory/1234".parse().unwrap() };
+        behaviour.inject_event(ConnectionEvent::Established { peer: friend.clone(), endpoint: endpoint.clone() });
+        behaviour.inject_event(ConnectionEvent::Closed { peer: friend.clone(), endpoint: endpoint.clone() });
+        assert_eq!(retry(&behaviour), Some(Duration::new(0, 0)));
+
+        for secs in [2, 4, 8, 16, 32, 60, 60].iter() {
+            behaviour.inject_event(ConnectionEvent::DialFailure { peer: friend.clone() });
+            assert_eq!(retry(&behaviour), Some(Duration::from_secs(*secs)));
+        }
+        assert_eq!(behaviour.get_peer_info(&friend).unwrap().backoff(), Some(Duration::from_secs(60)));
+
+        // Counting down as time passes
+        clock.advance(Duration::from_secs(45));
+        assert_eq!(retry(&behaviour), Some(Duration::from_secs(15)));
+
+        // And gone once connected again
+        behaviour.inject_event(ConnectionEvent::Established { peer: friend.clone(), endpoint });
+        assert_eq!(retry(&behaviour), None);
+        assert_eq!(behaviour.get_peer_info(&friend).unwrap().backoff(), None);
+    }
+
+    #[test]
+    fn redial_on_failure() {
+        let policy = RedialPolicy { timeout: false, ..RedialPolicy::default() };
+        let mut patient = behaviour(|c| c.with_reconnect(Some(ReconnectConfig::default().with_policy(policy))));
+        let mut behaviour = behaviour(|c| c.with_bootstrap(Vec::new()));
+        let friend = PeerId::random();
+        let stranger = PeerId::random();
+        behaviour.add_trusted_peer(friend.clone());
+        behaviour.add_peers(stranger.clone());
+
+        let addr: Multiaddr = "/memory/1234".parse().unwrap();
+        behaviour.kad.add_address(&friend, addr);
+        behaviour.kad.add_address(&stranger, "/memory/5678".parse().unwrap());
+        behaviour.dials.clear();
+
+        let fail = |peer: &PeerId, failure| StatusEvent { peer: peer.clone(), result: Err(failure) };
+
+        // Negotiation failures and untrusted peers never trigger a re-dial
+        behaviour.inject_event(fail(&friend, StatusFailure::Unsupported));
+        behaviour.inject_event(fail(&stranger, StatusFailure::ConnectionClosed));
+        assert!(behaviour.dials.is_empty());
+
+        // A closed connection to a trusted peer does, right away
+        behaviour.inject_event(fail(&friend, StatusFailure::ConnectionClosed));
+        assert_eq!(behaviour.dials.drain(..).collect::<Vec<_>>(), vec![Dial::Peer(friend.clone())]);
+
+        // Unless disabled by the policy
+        patient.add_trusted_peer(friend.clone());
+        patient.dials.clear();
+        patient.inject_event(fail(&friend, StatusFailure::Timeout));
+        assert!(patient.dials.is_empty());
+    }
+
+    #[test]
+    fn agree_on_capabilities() {
+        let status = StatusConfig::new(generate_payload()).with_capabilities(Capabilities::all());
+        let id = Keypair::generate_ed25519().public().into_peer_id();
+        let mut behaviour = Behaviour::with_config(id, BehaviourConfig::new(status).with_mdns(false));
+        let (legacy, newer) = (PeerId::random(), PeerId::random());
+        behaviour.add_peers(legacy.clone());
+        behaviour.add_peers(newer.clone());
+
+        let response = |capabilities| Response { epoch: Some(1), capabilities, ..Response::new(generate_payload()) };
+
+        // Peers predating capabilities do not announce any
+        behaviour.inject_event(received(&legacy, response(None)));
+        assert_eq!(behaviour.get_peer_info(&legacy).unwrap().capabilities(), None);
+
+        // Newer peers lacking gossip but with features unknown to us agree on the rest
+        let theirs = Capabilities::SIGNATURES | Capabilities::from_bits(1 << 7);
+        behaviour.inject_event(received(&newer, response(Some(theirs))));
+        assert_eq!(behaviour.get_peer_info(&newer).unwrap().capabilities(), Some(Capabilities::SIGNATURES));
+    }
+
+    #[test]
+    fn ignore_foreign_schema() {
+        let status = StatusConfig::new(generate_payload()).with_schema("git-sha1");
+        let id = Keypair::generate_ed25519().public().into_peer_id();
+        let mut behaviour = Behaviour::with_config(id, BehaviourConfig::new(status).with_mdns(false));
+        let peer = PeerId::random();
+        behaviour.add_peers(peer.clone());
+
+        let response = |schema: Option<&str>| Response { schema: schema.map(str::to_owned), ..Response::new(generate_payload()) };
+
+        // Payload in another format is reported and not interpreted
+        behaviour.inject_event(received(&peer, response(Some("semver"))));
+        match behaviour.events.pop() {
+            Some(BehaviourEvent::SchemaMismatch { theirs, ours, .. }) => {
+                assert_eq!(theirs, "semver");
+                assert_eq!(ours, "git-sha1");
+            },
+            other => panic!("Unexpected event: {:?}", other),
+        }
+        assert!(behaviour.get_peer_info(&peer).unwrap().status().is_none());
+        assert_eq!(behaviour.get_peer_info(&peer).unwrap().schema(), Some("semver"));
+
+        // Only reported again once the peer declares another schema
+        let mismatches = |behaviour: &mut Behaviour| {
+            let mut theirs = Vec::new();
+            while let Some(event) = behaviour.events.pop() {
+                if let BehaviourEvent::SchemaMismatch { theirs: schema, .. } = event {
+                    theirs.push(schema);
+                }
+            }
+            theirs
+        };
+        mismatches(&mut behaviour);
+        behaviour.inject_event(received(&peer, response(Some("semver"))));
+        assert!(mismatches(&mut behaviour).is_empty());
+        behaviour.inject_event(received(&peer, response(Some("git-sha256"))));
+        assert_eq!(mismatches(&mut behaviour), vec![String::from("git-sha256")]);
+
+        // Same or no schema declared is fine
+        for schema in [Some("git-sha1"), None].iter() {
+            let response = response(*schema);
+            let payload = response.payload;
+            behaviour.inject_event(received(&peer, response));
+            assert_eq!(behaviour.get_peer_info(&peer).unwrap().status(), Some(&payload));
+        }
+    }
+
+    #[test]
+    fn withheld_status() {
+        let mut behaviour = behaviour(|c| c);
+        let peer = PeerId::random();
+        behaviour.add_peers(peer.clone());
+
+        // Their status is withheld from us, which is no status at all
+        let response = Response { withheld: true, ..Response::new(WITHHELD) };
+        behaviour.inject_event(received(&peer, response));
+        let info = behaviour.get_peer_info(&peer).unwrap();
+        assert!(info.status().is_none());
+        assert!(info.withholds_status());
+
+        let payload = generate_payload();
+        behaviour.inject_event(received(&peer, Response::new(payload)));
+        let info = behaviour.get_peer_info(&peer).unwrap();
+        assert_eq!(info.status(), Some(&payload));
+        assert!(!info.withholds_status());
+
+        // Ours is withheld from them, which is reported once per connection
+        let reported = |behaviour: &mut Behaviour| {
+            let mut count = 0;
+            while let Some(event) = behaviour.events.pop() {
+                if let BehaviourEvent::StatusWithheld { .. } = event {
+                    count += 1;
+                }
+            }
+            count
+        };
+        let withheld = || StatusEvent { peer: peer.clone(), result: Ok(StatusSuccess::Withheld) };
+
+        behaviour.inject_event(withheld());
+        behaviour.inject_event(withheld());
+        assert_eq!(reported(&mut behaviour), 1);
+
+        let endpoint = ConnectedPoint::Dialer { address: "/memory/1234".parse().unwrap() };
+        behaviour.inject_event(ConnectionEvent::Closed { peer: peer.clone(), endpoint });
+        behaviour.inject_event(withheld());
+        assert_eq!(reported(&mut behaviour), 1);
+    }
+
+    #[test]
+    fn store_node_name() {
+        let mut behaviour = behaviour(|c| c);
+        let peer = PeerId::random();
+        behaviour.add_peers(peer.clone());
+
+        let response = Response { name: Some("build-server".into()), ..Response::new(generate_payload()) };
+        behaviour.inject_event(received(&peer, response));
+
+        assert_eq!(behaviour.get_peer_info(&peer).unwrap().name(), Some("build-server"));
+        assert_eq!(behaviour.snapshot()[0].name.as_ref().map(String::as_str), Some("build-server"));
+    }
+
+    #[test]
+    fn detect_restart() {
+        let mut behaviour = behaviour(|c| c);
+        let peer = PeerId::random();
+        behaviour.add_peers(peer.clone());
+
+        let payload = generate_payload();
+        let response = |epoch| Response { epoch: Some(epoch), ..Response::new(payload) };
+        let restarted = |behaviour: &mut Behaviour| {
+            let mut restarted = false;
+            while let Some(event) = behaviour.events.pop() {
+                if let BehaviourEvent::PeerRestarted { .. } = event {
+                    restarted = true;
+                }
+            }
+            restarted
+        };
+
+        // First epoch seen is no restart, neither is seeing it again
+        behaviour.inject_event(received(&peer, response(1)));
+        assert!(!restarted(&mut behaviour));
+        behaviour.inject_event(received(&peer, response(1)));
+        assert!(!restarted(&mut behaviour));
+
+        // Same status, but new epoch
+        behaviour.inject_event(received(&peer, response(2)));
+        assert!(restarted(&mut behaviour));
+        assert_eq!(behaviour.get_peer_info(&peer).unwrap().status(), Some(&payload));
+    }
+
+    #[test]
+    fn connection_quality() {
+        let clock = ManualClock::new();
+        let mut behaviour = behaviour(|c| c.with_clock(Arc::new(clock.clone())));
+        let good = PeerId::random();
+        let bad = PeerId::random();
+        behaviour.add_peers(good.clone());
+        behaviour.add_peers(bad.clone());
+
+        let quality = |behaviour: &Behaviour, peer| behaviour.get_peer_info(peer).unwrap().quality_at(clock.now());
+        let response = || Response::new(generate_payload());
+        let fail = |peer: &PeerId| StatusEvent { peer: peer.clone(), result: Err(StatusFailure::Timeout) };
+
+        // Nothing known is as bad as it gets
+        assert_eq!(quality(&behaviour, &good), 0);
+
+        // Fast, reliable and fresh is perfect
+        for _ in 0..10 {
+            behaviour.inject_event(received(&good, response()));
+        }
+        assert_eq!(quality(&behaviour, &good), 100);
+
+        // Failures and staleness each lower the score
+        for _ in 0..6 {
+            behaviour.inject_event(fail(&good));
+        }
+        let unreliable = quality(&behaviour, &good);
+        assert_eq!(unreliable, 81);
+
+        clock.advance(Duration::from_secs(150));
+        let stale = quality(&behaviour, &good);
+        assert!(stale < unreliable);
+
+        // Recovering raises it again, while a failing peer stays at the bottom
+        for _ in 0..16 {
+            behaviour.inject_event(received(&good, response()));
+            behaviour.inject_event(fail(&bad));
+        }
+        assert_eq!(quality(&behaviour, &good), 100);
+        assert_eq!(quality(&behaviour, &bad), 0);
+
+        // Refusals say nothing about the connection
+        behaviour.inject_event(StatusEvent { peer: good.clone(), result: Err(StatusFailure::Unsupported) });
+        assert_eq!(quality(&behaviour, &good), 100);
+    }
+
+    #[test]
+    fn stalest_peer() {
+        let clock = ManualClock::new();
+        let mut behaviour = behaviour(|c| c.with_clock(Arc::new(clock.clone())));
+
+        let peers: Vec<PeerId> = (0..3).map(|_| PeerId::random()).collect();
+        for peer in peers.iter() {
+            behaviour.add_peers(peer.clone());
+        }
+        assert_eq!(behaviour.stalest_peer(), None);
+
+        // First two peers report 30 seconds apart, the third stays silent
+        let response = || Response::new(generate_payload());
+        behaviour.inject_event(received(&peers[0], response()));
+        clock.advance(Duration::from_secs(30));
+        behaviour.inject_event(received(&peers[1], response()));
+        clock.advance(Duration::from_secs(10));
+
+        assert_eq!(behaviour.status_age(&peers[0]), Some(Duration::from_secs(40)));
+        assert_eq!(behaviour.status_age(&peers[1]), Some(Duration::from_secs(10)));
+        assert_eq!(behaviour.status_age(&peers[2]), None);
+        assert_eq!(behaviour.status_age(&PeerId::random()), None);
+
+        assert_eq!(behaviour.stalest_peer(), Some((peers[0].clone(), Duration::from_secs(40))));
+
+        // Hearing from the stalest peer again makes the other one the stalest
+        behaviour.inject_event(received(&peers[0], response()));
+        assert_eq!(behaviour.stalest_peer(), Some((peers[1].clone(), Duration::from_secs(10))));
+    }
+
+    #[test]
+    fn report_dropped_events() {
+        let mut behaviour = behaviour(|c| c.with_event_capacity(4));
+
+        let unreachable = || ConnectionEvent::Unreachable {
+            peer: None,
+            address: "/memory/1234".parse().unwrap(),
+            error: String::from("Connection refused"),
+        };
+
+        for _ in 0..10 {
+            behaviour.inject_event(unreachable());
+        }
+
+        // Queue is full, the rest is counted once there is room again
+        for _ in 0..4 {
+            match behaviour.events.pop() {
+                Some(BehaviourEvent::DialFailed { .. }) => (),
+                other => panic!("Unexpected event: {:?}", other),
+            }
+        }
+        match behaviour.events.pop() {
+            Some(BehaviourEvent::EventsDropped { count }) => assert_eq!(count, 6),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+        assert!(behaviour.events.is_empty());
+    }
+
+    #[test]
+    fn validate_payloads() {
+        let mut behaviour = behaviour(|c| c);
+        let peer = PeerId::random();
+        let other = PeerId::random();
+        behaviour.add_peers(peer.clone());
+        behaviour.add_peers(other.clone());
+
+        let bogus = [0xba; 20];
+        behaviour.set_payload_validator(Box::new(move |_, payload| payload != &bogus));
+
+        let response = |payload, gossip| Response { gossip, ..Response::new(payload) };
+
+        // Refused payload is reported, but not stored, neither directly nor gossiped
+        let gossip = vec![Gossip { peer: other.clone(), payload: bogus, age: Duration::from_secs(1) }];
+        behaviour.inject_event(received(&peer, response(bogus, gossip)));
+        assert_eq!(behaviour.get_peer_info(&peer).unwrap().status(), None);
+        assert_eq!(behaviour.get_peer_info(&other).unwrap().status(), None);
+
+        match behaviour.events.pop() {
+            Some(BehaviourEvent::PayloadRejected { peer: rejected, payload }) => {
+                assert_eq!(rejected, peer);
+                assert_eq!(payload, bogus);
+            },
+            other => panic!("Unexpected event: {:?}", other),
+        }
+
+        // Others are accepted as usual
+        let valid = generate_payload();
+        behaviour.inject_event(received(&peer, response(valid, Vec::new())));
+        assert_eq!(behaviour.get_peer_info(&peer).unwrap().status(), Some(&valid));
+    }
+
+    #[test]
+    fn log_recent_events() {
+        let clock = ManualClock::new();
+        let mut behaviour = behaviour(|c| c
+            .with_clock(Arc::new(clock.clone()))
+            .with_event_log(3, Duration::from_secs(60)));
+
+        let unreachable = |n: u8| ConnectionEvent::Unreachable {
+            peer: None,
+            address: format!("/memory/{}", n).parse().unwrap(),
+            error: String::from("Connection refused"),
+        };
+        let logged = |behaviour: &Behaviour| -> Vec<(u64, String)> {
+            behaviour.recent_events().into_iter()
+                .map(|(age, event)| match event {
+                    BehaviourEvent::DialFailed { address, .. } => (age.as_secs(), address.to_string()),
+                    other => panic!("Unexpected event: {:?}", other),
+                })
+                .collect()
+        };
+
+        behaviour.inject_event(unreachable(1));
+        behaviour.inject_event(unreachable(2));
+        clock.advance(Duration::from_secs(40));
+        behaviour.inject_event(unreachable(3));
+        behaviour.inject_event(unreachable(4));
+
+        // Only the most recent ones are kept, whether delivered or not
+        while behaviour.events.pop().is_some() {}
+        assert_eq!(logged(&behaviour), vec![
+            (40, "/memory/2".to_owned()),
+            (0, "/memory/3".to_owned()),
+            (0, "/memory/4".to_owned()),
+        ]);
+
+        // And only for a while
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(logged(&behaviour), vec![
+            (30, "/memory/3".to_owned()),
+            (30, "/memory/4".to_owned()),
+        ]);
+    }
+
+    #[test]
+    fn dial_preferred_family_first() {
+        let clock = ManualClock::new();
+        let peer = PeerId::random();
+        let v4: libp2p::Multiaddr = "/ip4/192.0.2.1/tcp/4001".parse().unwrap();
+        let v6: libp2p::Multiaddr = "/ip6/2001:db8::1/tcp/4001".parse().unwrap();
+
+        let setup = |preference| {
+            let mut behaviour = behaviour(|c| c.with_address_preference(preference).with_clock(Arc::new(clock.clone())));
+            behaviour.kad.add_address(&peer, v4.clone());
+            behaviour.kad.add_address(&peer, v6.clone());
+            behaviour.dial_peer(peer.clone());
+            behaviour
+        };
+        let dials = |preference| {
+            let mut behaviour = setup(preference);
+            (behaviour.kad.addresses_of_peer(&peer), behaviour.dials.drain(..).collect::<Vec<_>>())
+        };
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let mut dialed = |behaviour: &mut Behaviour| {
+            let mut dialed = Vec::new();
+            while let Poll::Ready(action) = behaviour.poll_events::<()>(&mut cx) {
+                if let NetworkBehaviourAction::DialAddress { address } = action {
+                    dialed.push(address);
+                }
+            }
+            dialed
+        };
+
+        // Addresses are tried one after another in the order they were learned by default
+        let (addrs, queued) = dials(AddressPreference::Any);
+        assert_eq!(addrs, vec![v4.clone(), v6.clone()]);
+        assert_eq!(queued, vec![Dial::Peer(peer.clone())]);
+
+        // Or in order of preference
+        let (addrs, queued) = dials(AddressPreference::Ipv4);
+        assert_eq!(addrs, vec![v4.clone(), v6.clone()]);
+        assert_eq!(queued, vec![Dial::Peer(peer.clone())]);
+
+        let (addrs, queued) = dials(AddressPreference::Ipv6);
+        assert_eq!(addrs, vec![v6.clone(), v4.clone()]);
+        assert_eq!(queued, vec![Dial::Peer(peer.clone())]);
+
+        // The other family is only dialed if not connected shortly after
+        let mut racing = setup(AddressPreference::HappyEyeballs);
+        assert_eq!(racing.dials.drain(..).collect::<Vec<_>>(), vec![Dial::Address(v6.clone())]);
+        assert!(dialed(&mut racing).is_empty());
+        clock.advance(RACE_DELAY);
+        assert_eq!(dialed(&mut racing), vec![v4.clone()]);
+
+        let mut racing = setup(AddressPreference::HappyEyeballs);
+        let endpoint = ConnectedPoint::Dialer { address: v6.clone() };
+        racing.inject_event(ConnectionEvent::Established { peer: peer.clone(), endpoint });
+        racing.dials.clear();
+        clock.advance(RACE_DELAY);
+        assert!(dialed(&mut racing).is_empty());
+    }
+
+    #[test]
+    fn dial_alternate_addresses() {
+        let mut racing = behaviour(|c| c.with_address_preference(AddressPreference::HappyEyeballs));
+        let mut behaviour = behaviour(|c| c.with_address_preference(AddressPreference::Ipv4));
+        let friend = PeerId::random();
+        let (dead, alive): (Multiaddr, Multiaddr) = ("/ip4/192.0.2.1/tcp/4001".parse().unwrap(), "/ip4/192.0.2.2/tcp/4001".parse().unwrap());
+        let unreachable = |peer: Option<&PeerId>, address: &Multiaddr| ConnectionEvent::Unreachable {
+            peer: peer.cloned(),
+            address: address.clone(),
+            error: String::from("Connection refused"),
+        };
+        let given_up = |behaviour: &mut Behaviour| std::iter::from_fn(|| behaviour.events.pop())
+            .find_map(|event| match event {
+                BehaviourEvent::TrustedPeerUnreachable { peer, addresses } => Some((peer, addresses)),
+                _ => None,
+            });
+
+        behaviour.add_trusted_peer(friend.clone());
+        behaviour.kad.add_address(&friend, dead.clone());
+        behaviour.kad.add_address(&friend, alive.clone());
+
+        // The first address fails, connecting at the second one is no failure
+        behaviour.dial_peer(friend.clone());
+        behaviour.inject_event(unreachable(Some(&friend), &dead));
+        let endpoint = ConnectedPoint::Dialer { address: alive.clone() };
+        behaviour.inject_event(ConnectionEvent::Established { peer: friend.clone(), endpoint: endpoint.clone() });
+        assert!(behaviour.get_peer_info(&friend).unwrap().online());
+        assert_eq!(given_up(&mut behaviour), None);
+
+        // The dead address is tried last from now on
+        assert_eq!(behaviour.kad.failures(&friend, &dead), 1);
+        assert_eq!(behaviour.kad.addresses_of_peer(&friend), vec![alive.clone(), dead.clone()]);
+
+        // Unreachable is only reported once every address failed
+        behaviour.inject_event(ConnectionEvent::Closed { peer: friend.clone(), endpoint });
+        behaviour.dial_peer(friend.clone());
+        behaviour.inject_event(unreachable(Some(&friend), &alive));
+        assert_eq!(given_up(&mut behaviour), None);
+        behaviour.inject_event(unreachable(Some(&friend), &dead));
+        behaviour.inject_event(ConnectionEvent::DialFailure { peer: friend.clone() });
+        assert_eq!(given_up(&mut behaviour), Some((friend.clone(), vec![alive.clone(), dead.clone()])));
+
+        // After racing both families, the other addresses follow one by one
+        let (v6, v4): (Multiaddr, Multiaddr) = ("/ip6/2001:db8::1/tcp/4001".parse().unwrap(), "/ip4/192.0.2.3/tcp/4001".parse().unwrap());
+        racing.add_trusted_peer(friend.clone());
+        for addr in &[&v6, &v4, &alive] {
+            racing.kad.add_address(&friend, (*addr).clone());
+        }
+
+        racing.dial_peer(friend.clone());
+        assert_eq!(racing.dials.drain(..).collect::<Vec<_>>(), vec![Dial::Address(v6.clone())]);
+        racing.inject_event(unreachable(None, &v6));
+        assert_eq!(racing.dials.drain(..).collect::<Vec<_>>(), vec![Dial::Address(v4.clone())]);
+        racing.inject_event(unreachable(None, &v4));
+        assert_eq!(racing.dials.drain(..).collect::<Vec<_>>(), vec![Dial::Address(alive.clone())]);
+        racing.inject_event(unreachable(None, &alive));
+        assert_eq!(given_up(&mut racing), Some((friend, vec![v6, v4, alive])));
+    }
+
+    #[test]
+    fn limit_outstanding_dials() {
+        let mut behaviour = behaviour(|c| c.with_max_dials(Some(2)));
+
+        let peers: Vec<PeerId> = (0..5).map(|_| PeerId::random()).collect();
+        for peer in peers.iter() {
+            behaviour.dial_peer(peer.clone());
+        }
+
+        // Dials requested from the swarm until it has to wait
+        let dial = |behaviour: &mut Behaviour| {
+            let mut dialed = Vec::new();
+            let waker = futures::task::noop_waker();
+            let mut cx = Context::from_waker(&waker);
+            while let Poll::Ready(action) = behaviour.poll_events::<()>(&mut cx) {
+                match action {
+                    NetworkBehaviourAction::DialPeer { peer_id } => dialed.push(peer_id),
+                    NetworkBehaviourAction::GenerateEvent(_) => (),
+                    _ => panic!("Unexpected action"),
+                }
+            }
+            assert!(behaviour.outstanding.len() <= 2);
+            dialed
+        };
+
+        assert_eq!(dial(&mut behaviour), peers[..2].to_vec());
+
+        // Each completed dial, successful or not, makes room for another one
+        behaviour.inject_event(ConnectionEvent::DialFailure { peer: peers[0].clone() });
+        assert_eq!(dial(&mut behaviour), peers[2..3].to_vec());
+
+        let endpoint = ConnectedPoint::Dialer { address: "/memory/1234".parse().unwrap() };
+        behaviour.inject_event(ConnectionEvent::Established { peer: peers[1].clone(), endpoint });
+        assert_eq!(dial(&mut behaviour), peers[3..4].to_vec());
+
+        behaviour.inject_event(ConnectionEvent::DialFailure { peer: peers[2].clone() });
+        behaviour.inject_event(ConnectionEvent::DialFailure { peer: peers[3].clone() });
+        assert_eq!(dial(&mut behaviour), peers[4..].to_vec());
+        assert!(dial(&mut behaviour).is_empty());
+    }
+
+    #[test]
+    fn release_address_dials() {
+        let clock = ManualClock::new();
+        let mut behaviour = behaviour(|c| c.with_max_dials(Some(1)).with_clock(Arc::new(clock.clone())));
+        let addrs: Vec<Multiaddr> = ["/memory/1234", "/memory/5678", "/memory/9012"].iter().map(|a| a.parse().unwrap()).collect();
+        behaviour.dials.extend(addrs.iter().cloned().map(Dial::Address));
+
+        let dial = |behaviour: &mut Behaviour| {
+            let mut dialed = Vec::new();
+            let waker = futures::task::noop_waker();
+            let mut cx = Context::from_waker(&waker);
+            while let Poll::Ready(action) = behaviour.poll_events::<()>(&mut cx) {
+                match action {
+                    NetworkBehaviourAction::DialAddress { address } => dialed.push(address),
+                    NetworkBehaviourAction::GenerateEvent(_) => (),
+                    _ => panic!("Unexpected action"),
+                }
+            }
+            dialed
+        };
+
+        assert_eq!(dial(&mut behaviour), addrs[..1].to_vec());
+
+        // An unreachable address makes room for the next one
+        behaviour.inject_event(ConnectionEvent::Unreachable {
+            peer: None,
+            address: addrs[0].clone(),
+            error: String::from("Connection refused"),
+        });
+        assert_eq!(dial(&mut behaviour), addrs[1..2].to_vec());
+
+        // So does one never reported back on, once expired
+        clock.advance(DIAL_TIMEOUT - Duration::from_secs(1));
+        assert!(dial(&mut behaviour).is_empty());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(dial(&mut behaviour), addrs[2..].to_vec());
+        assert!(behaviour.outstanding.contains_key(&Dial::Address(addrs[2].clone())));
+    }
+
+    #[test]
+    fn filter_peers() {
+        let mut behaviour = behaviour(|c| c);
+
+        let peers: Vec<PeerId> = (0..3).map(|_| PeerId::random()).collect();
+        for peer in peers.iter() {
+            behaviour.add_peers(peer.clone());
+        }
+
+        // First peer is up to date and online, second one lags behind, third is unknown
+        let expected = generate_payload();
+        for (peer, payload) in peers.iter().zip([expected, generate_payload()].iter()) {
+            let response = Response::new(*payload);
+            behaviour.inject_event(received(peer, response));
+        }
+
+        let endpoint = ConnectedPoint::Dialer { address: "/memory/1234".parse().unwrap() };
+        behaviour.inject_event(ConnectionEvent::Established { peer: peers[0].clone(), endpoint });
+
+        let with_rtt = behaviour.filter_peers(|info| info.rtt().is_some());
+        assert_eq!(with_rtt, vec![peers[0].clone(), peers[1].clone()]);
+
+        assert_eq!(behaviour.peers_missing_revision(&expected), vec![peers[1].clone(), peers[2].clone()]);
+        assert_eq!(behaviour.offline_peers(), vec![peers[1].clone(), peers[2].clone()]);
+    }
+
+    #[test]
+    fn export_routing_table() {
+        let mut behaviour = behaviour(|c| c.with_bootstrap(Vec::new()));
+        assert!(behaviour.routing_table().is_empty());
+
+        let peers: Vec<(PeerId, Multiaddr)> = (1..4)
+            .map(|n| (PeerId::random(), format!("/ip4/192.0.2.{}/tcp/4001", n).parse().unwrap()))
+            .collect();
+        for (peer, addr) in peers.iter() {
+            behaviour.add_address(peer, addr.clone());
+        }
+
+        let table = behaviour.routing_table();
+        assert_eq!(table.len(), peers.len());
+        for (peer, addr) in peers.iter() {
+            assert!(table.contains(&(peer.clone(), vec![addr.clone()])));
+        }
+
+        // Withheld addresses are still part of the table
+        behaviour.kad.demote(peers[0].0.clone());
+        assert!(behaviour.addresses_of(&peers[0].0).is_empty());
+        assert_eq!(behaviour.routing_table(), table);
     }
 }
@@ -1,86 +1,613 @@
+use dx::addresses::AddressBook;
 use dx::behaviour::Behaviour;
+use dx::config::Config;
 use dx::trust::{
+    StoreChange,
+    StoreWatcher,
     TrustStore,
     TrustedIdentity,
 };
-use dx::status::generate_payload;
+use dx::status::{Payload, StatusProfile, generate_payload, payload_from_hex, payload_to_hex, read_payload};
+use dx::node::{self, NodeEvent};
+use dx::transport::SecurityTable;
+use dx::{logging, topology, watch};
 
-use async_std::{io, task};
-use futures::{prelude::*, future};
-use libp2p::Swarm;
+use async_std::task;
+use libp2p::{Multiaddr, PeerId, Swarm, swarm::SwarmEvent};
 
-use std::{error::Error, task::{Context, Poll}};
+use std::collections::HashMap;
+use std::{env, fs, io};
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use std::time::{Duration, Instant};
 
-use std::env;
+/// Addresses not seen for this long are forgotten
+const ADDRESS_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How often the address book is saved while running
+const ADDRESS_SAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
 
 fn help() {
-    println!("usage: dxstatus <name>
-    Run dx status node for supplied identity.");
+    println!("usage: dxstatus [watch | topology [--duration <secs>] [--json] | routing [--duration <secs>]] [--config <file>] [--listen <addr>]... [--status <hex> | --status-file <file>]
+                [--profile <default|aggressive|relaxed>] [--interval <secs>] [--timeout <secs>] [--max-failures <n>]
+                [--reload] [--remember] [--dry-run] [--ephemeral | --identity-file <file>] {} [<name>]
+    Run dx status node for supplied identity.
+    Status requests are sent every --interval seconds and fail after --timeout
+    seconds, the connection is closed after --max-failures consecutive failures.
+    Settings not given default to those of the --profile: requests every 15s with
+    20s timeout closing on the first failure by default, every 5s with 5s timeout
+    when aggressive, every 60s with 60s timeout closing after 5 failures within
+    an hour when relaxed.
+    In watch mode a live table of the watched peers is shown instead of raw events.
+    With --reload, identities added to or removed from the trust store are
+    picked up while running.
+    With --remember, addresses of peers are saved to ~/.dx/addresses.toml
+    every few minutes and on Ctrl-C, and tried first on the next start.
+    With --dry-run, the config, identity, addresses and trust store are checked
+    and summarized without starting the node, exiting non-zero on any problem.
+    With --ephemeral, the node runs as a new identity outside the trust store,
+    named <name> or ephemeral. With --identity-file, that identity is loaded
+    from <file>, or saved there if missing, and can be imported with dxtrust.
+    The topology command looks up peers for --duration seconds (default 30), then
+    prints which peers know which as DOT graph, or as JSON adjacency list.
+    The routing command runs as long, then prints the Kademlia routing table.
+    The advertised status is given as 40 character hex string, directly or
+    read from <file> (- for stdin), otherwise a random one is used.
+    Settings are read from <file> or ~/.dx/dx.toml, if present, and overridden by flags.
+    Logs are shown at info, debug or trace level with -v, -vv or -vvv, or at the
+    given --log-level, otherwise as configured by RUST_LOG.", logging::USAGE);
+}
+
+/// Settings supplied on the command line
+#[derive(Default)]
+struct Options {
+    watching: bool,
+    topology: bool,
+    routing: bool,
+    duration: Option<Duration>,
+    json: bool,
+    config: Option<PathBuf>,
+    /// Listen addresses as given, parsed once the config is known, see [`listen_addrs`]
+    listen: Vec<String>,
+    status: Option<Payload>,
+    status_file: Option<PathBuf>,
+    profile: Option<StatusProfile>,
+    interval: Option<Duration>,
+    timeout: Option<Duration>,
+    max_failures: Option<NonZeroU32>,
+    reload: bool,
+    remember: bool,
+    dry_run: bool,
+    /// Whether to run as an identity outside the trust store
+    ephemeral: bool,
+    /// File the ephemeral identity is kept in, if any
+    identity_file: Option<PathBuf>,
+    name: Option<String>,
+}
+
+/// Parse non-zero number of seconds
+fn parse_secs(arg: &str) -> Option<Duration> {
+    arg.parse().ok().filter(|secs| *secs > 0).map(Duration::from_secs)
+}
+
+fn parse_args(args: &[String]) -> Option<Options> {
+    let mut options = Options::default();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "watch" => options.watching = true,
+            "topology" => options.topology = true,
+            "routing" => options.routing = true,
+            "--duration" => options.duration = Some(parse_secs(args.next()?)?),
+            "--json" => options.json = true,
+            "--config" => options.config = Some(args.next()?.into()),
+            "--listen" => options.listen.push(args.next()?.clone()),
+            "--status" => options.status = Some(payload_from_hex(args.next()?)?),
+            "--status-file" => options.status_file = Some(args.next()?.into()),
+            "--profile" => options.profile = Some(StatusProfile::from_name(args.next()?)?),
+            "--interval" => options.interval = Some(parse_secs(args.next()?)?),
+            "--timeout" => options.timeout = Some(parse_secs(args.next()?)?),
+            "--max-failures" => options.max_failures = Some(args.next()?.parse().ok()?),
+            "--reload" => options.reload = true,
+            "--remember" => options.remember = true,
+            "--dry-run" => options.dry_run = true,
+            "--ephemeral" => options.ephemeral = true,
+            "--identity-file" => {
+                options.ephemeral = true;
+                options.identity_file = Some(args.next()?.into());
+            },
+            _ if options.name.is_none() && !arg.starts_with("--") => options.name = Some(arg.clone()),
+            _ => return None,
+        }
+    }
+
+    // Status can only be given once
+    if options.status.is_some() && options.status_file.is_some() {
+        return None
+    }
+
+    // Only one mode at a time, duration only for the modes dumping once
+    if [options.watching, options.topology, options.routing].iter().filter(|mode| **mode).count() > 1 {
+        return None
+    }
+    if !(options.topology || options.routing) && options.duration.is_some() {
+        return None
+    }
+    if !options.topology && options.json {
+        return None
+    }
+
+    Some(options)
+}
+
+/// Read status from file, or stdin if `-`
+fn read_status(path: &Path) -> io::Result<Payload> {
+    if path == Path::new("-") {
+        read_payload(io::stdin())
+    } else {
+        read_payload(fs::File::open(path)?)
+    }
+}
+
+/// Addresses to listen on, those given on the command line taking precedence
+/// over the configured ones, by default all interfaces and a random port
+fn listen_addrs(options: &Options, config: &Config) -> Result<Vec<Multiaddr>, String> {
+    if !options.listen.is_empty() {
+        options.listen.iter()
+            .map(|addr| addr.parse().map_err(|error| format!("Invalid listen address '{}': {}", addr, error)))
+            .collect()
+    } else if !config.listen.is_empty() {
+        Ok(config.listen.clone())
+    } else {
+        Ok(vec!["/ip4/0.0.0.0/tcp/0".parse().unwrap()])
+    }
+}
+
+/// Check everything the node needs to start for the identity of the given
+/// name, using the trust store at path. Returns a summary of what the node
+/// would run with, or every problem found.
+fn dry_run(options: &Options, config: &Config, path: &Path, name: &str) -> Result<String, Vec<String>> {
+    let mut errors = Vec::new();
+    let mut summary = Vec::new();
+
+    for issue in TrustStore::verify_integrity_at(path) {
+        errors.push(format!("Trust store: {}", issue));
+    }
+
+    // Loading a broken store fails, so identities are only checked in an intact one
+    let store = if errors.is_empty() { Some(TrustStore::load_at(path)) } else { None };
+    if options.ephemeral {
+        // A missing identity file would be written, so it is only read if present
+        match options.identity_file.as_ref().filter(|file| file.exists()) {
+            Some(file) => match TrustedIdentity::ephemeral(name.to_owned(), Some(file)) {
+                Ok(identity) => summary.push(format!("Local peer id: {}", identity.id())),
+                Err(error) => errors.push(format!("Failed to load identity from {}: {}", file.display(), error)),
+            },
+            None => summary.push("Local peer id: generated on start".to_owned()),
+        }
+        if store.as_ref().map_or(false, |store| store.find(name).is_some()) {
+            errors.push(format!("Identity '{}' is in the trust store, pick another name", name));
+        }
+    } else if let Some(store) = &store {
+        match store.local(name).and_then(TrustedIdentity::key) {
+            Ok(keypair) => summary.push(format!("Local peer id: {}", keypair.public().into_peer_id())),
+            Err(error) => errors.push(error.to_string()),
+        }
+    }
+
+    match listen_addrs(options, config) {
+        Ok(addrs) => {
+            let addrs: Vec<String> = addrs.iter().map(Multiaddr::to_string).collect();
+            summary.push(format!("Listen addresses: {}", addrs.join(", ")));
+        },
+        Err(error) => errors.push(error),
+    }
+
+    // Reading from stdin would block, it can not be checked up front
+    if let Some(path) = options.status_file.as_ref().filter(|path| *path != Path::new("-")) {
+        if let Err(error) = read_status(path) {
+            errors.push(format!("Failed to read status from {}: {}", path.display(), error));
+        }
+    }
+
+    if options.remember {
+        if let Err(error) = AddressBook::load(&AddressBook::path(), ADDRESS_TTL) {
+            errors.push(format!("Failed to load address book: {}", error));
+        }
+    }
+
+    if let Some(store) = &store {
+        let trusted = store.ids.iter().filter(|other| other.name != name).count();
+        summary.push(format!("Trusted peers: {}", trusted));
+        for duplicate in store.duplicates() {
+            summary.push(format!("WARNING: Trusted peer {}, remove all but one of them", duplicate));
+        }
+    }
+    summary.push(format!("Bootstrap nodes: {}", config.bootstrap.len()));
+
+    if errors.is_empty() {
+        Ok(summary.join("\n"))
+    } else {
+        Err(errors)
+    }
+}
+
+/// Add the currently known addresses of peers to the address book and save it
+fn remember(book: &mut AddressBook, behaviour: &mut Behaviour) {
+    book.update(behaviour);
+
+    if let Err(error) = book.save(&AddressBook::path()) {
+        println!("Failed to save address book: {}", error);
+    }
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() != 2 {
-        return help();
+    let (level, args) = match logging::parse_flags(&args[1..]) {
+        Some(flags) => flags,
+        None => return help(),
+    };
+    let options = match parse_args(&args) {
+        Some(options) => options,
+        None => return help(),
+    };
+
+    // Load config file, explicitly requested or default one if present
+    let default_path = TrustStore::path().join("dx.toml");
+    let config = match &options.config {
+        Some(path) => Config::from_path(path),
+        None if default_path.exists() => Config::from_path(&default_path),
+        None => Ok(Config::default()),
+    };
+
+    let mut config = match config {
+        Ok(config) => config,
+        Err(error) => {
+            println!("{}", error);
+            std::process::exit(1);
+        }
+    };
+
+    // Command line flags take precedence over the config file
+    config.profile = options.profile.unwrap_or(config.profile);
+    config.interval = options.interval.or(config.interval);
+    config.timeout = options.timeout.or(config.timeout);
+    config.max_failures = options.max_failures.or(config.max_failures);
+
+    let name = match options.name.as_ref().or(config.identity.as_ref()) {
+        Some(name) => name.clone(),
+        None if options.ephemeral => "ephemeral".to_owned(),
+        None => return help(),
+    };
+    let name = &name;
+
+    if options.dry_run {
+        match dry_run(&options, &config, &TrustStore::path(), name) {
+            Ok(summary) => {
+                println!("{}", summary);
+                return
+            },
+            Err(errors) => {
+                for error in errors {
+                    println!("{}", error);
+                }
+                std::process::exit(1);
+            }
+        }
     }
 
-    let name = &args[1];
+    let listen = match listen_addrs(&options, &config) {
+        Ok(listen) => listen,
+        Err(error) => {
+            println!("{}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let watching = options.watching;
 
     println!("Starting status node for identity '{}'", name);
 
-    //env_logger::init();
+    logging::init(level);
 
-    let store = TrustStore::load();
+    let mut store = TrustStore::load();
 
-    // Determine peer id
-    let key = store.find(name).expect("Name not in trust store");
-    println!("Local peer id: {:?}", key.id());
+    // Run as an identity of our own, only known to this run of the node
+    if options.ephemeral {
+        if store.find(name).is_some() {
+            println!("Identity '{}' is in the trust store, pick another name", name);
+            std::process::exit(1);
+        }
 
-    // Determine status
-    let status = generate_payload();
+        match TrustedIdentity::ephemeral(name.clone(), options.identity_file.as_ref().map(PathBuf::as_path)) {
+            Ok(identity) => store.ids.push(identity),
+            Err(error) => {
+                println!("Failed to load identity: {}", error);
+                std::process::exit(1);
+            }
+        }
+    }
 
-    // Set up swarm
-    let transport = libp2p::build_development_transport(key.key()).unwrap();
-    let mut behaviour = Behaviour::new(key.id(), status);
+    // Peers are tracked by id, so only one name of a duplicated id is used
+    for duplicate in store.duplicates() {
+        println!("WARNING: Trusted peer {}, remove all but one of them", duplicate);
+    }
 
-    for other in store.ids.iter() {
-        if &other.name != name {
-            behaviour.add_peers(other.id())
+    // Determine peer id, we need to hold the private key of the identity
+    let keypair = match store.local(name).and_then(TrustedIdentity::key) {
+        Ok(keypair) => keypair,
+        Err(error) => {
+            println!("{}", error);
+            std::process::exit(1);
         }
+    };
+    println!("Local peer id: {:?}", keypair.public().into_peer_id());
+
+    // Determine status
+    let status = match (options.status, &options.status_file) {
+        (Some(status), _) => status,
+        (None, Some(path)) => match read_status(path) {
+            Ok(status) => status,
+            Err(error) => {
+                println!("Failed to read status from {}: {}", path.display(), error);
+                std::process::exit(1);
+            }
+        },
+        (None, None) => generate_payload(),
+    };
+    println!("Advertising status {}", payload_to_hex(&status));
+
+    // Start out with the addresses remembered from last time, if wanted
+    let book = if options.remember {
+        match AddressBook::load(&AddressBook::path(), ADDRESS_TTL) {
+            Ok(book) => Some(book),
+            Err(error) => {
+                println!("Failed to load address book: {}", error);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    // Set up swarm, connecting to trusted peers
+    let security = SecurityTable::default();
+    let transport = node::development_transport(keypair, !config.disable_dns, security.clone()).unwrap();
+    let mut swarm = node::status_swarm(transport, security, &store, name, status, &config, book.as_ref().unwrap_or(&AddressBook::default())).unwrap();
+
+    // Tell the swarm to listen on the configured addresses, by default on
+    // all interfaces and a random, OS-assigned port.
+    for addr in listen {
+        Swarm::listen_on(&mut swarm, addr).unwrap();
     }
 
-    let mut swarm = Swarm::new(transport, behaviour, key.id());
+    // Names of peers shown in watch mode
+    let mut names: HashMap<PeerId, String> = store.ids.iter()
+        .map(|other| (other.id(), other.name.clone()))
+        .collect();
+
+    // Pick up identities generated while running, once their files are written
+    let mut reload = if options.reload {
+        match StoreWatcher::new(&TrustStore::path(), Duration::from_secs(1)) {
+            Ok(watcher) => Some(watcher),
+            Err(error) => {
+                println!("Failed to watch trust store: {:?}", error);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut book = book.map(|book| (book, Instant::now()));
+
+    let tty = atty::is(atty::Stream::Stdout);
+    let refresh = Some(Duration::from_secs(1)).filter(|_| watching || reload.is_some() || book.is_some());
+    let name = name.clone();
+
+    // Dump graph or routing table once lookups had time to complete
+    if options.topology || options.routing {
+        let duration = options.duration.unwrap_or(Duration::from_secs(30));
+        let (json, routing) = (options.json, options.routing);
 
-    // Tell the swarm to listen on all interfaces and a random, OS-assigned port.
-    Swarm::listen_on(&mut swarm, "/ip4/0.0.0.0/tcp/0".parse().unwrap()).unwrap();
+        task::block_on(node::run(&mut swarm, Some(duration), move |swarm, event| {
+            match event {
+                NodeEvent::Tick if routing => {
+                    for (peer, addrs) in swarm.routing_table() {
+                        let addrs: Vec<String> = addrs.iter().map(Multiaddr::to_string).collect();
+                        println!("{} {}", names.get(&peer).map_or_else(|| peer.to_base58(), Clone::clone), addrs.join(" "));
+                    }
+                    false
+                },
+                NodeEvent::Tick => {
+                    let graph = swarm.topology();
+                    if json {
+                        print!("{}", topology::render_json(&graph, &names));
+                    } else {
+                        print!("{}", topology::render_dot(&graph, &names));
+                    }
+                    false
+                },
+                _ => true,
+            }
+        }));
 
-    // Try to connect to trusted peers
-    for other in store.ids.iter() {
-        if &other.name != name {
-            Swarm::dial(&mut swarm, other.id());
+        if let Some((book, _)) = book.as_mut() {
+            remember(book, &mut swarm);
         }
+        return
     }
 
+    // Save addresses on Ctrl-C, as the node runs until interrupted
+    let interrupted = Arc::new(AtomicBool::new(false));
+    if book.is_some() {
+        let interrupted = interrupted.clone();
+        ctrlc::set_handler(move || interrupted.store(true, Ordering::SeqCst)).unwrap();
+    }
+
+    task::block_on(node::run(&mut swarm, refresh, move |swarm, event| {
+        match event {
+            NodeEvent::Swarm(SwarmEvent::NewListenAddr(addr)) => println!("Listening on {:?}", addr),
+            NodeEvent::Swarm(SwarmEvent::Behaviour(e)) => if !watching { println!("{:?}", e) },
+            NodeEvent::Swarm(_) => (),
+            NodeEvent::Tick => {
+                let stop = interrupted.load(Ordering::SeqCst);
+                if let Some((book, saved)) = book.as_mut() {
+                    if stop || saved.elapsed() >= ADDRESS_SAVE_INTERVAL {
+                        remember(book, swarm);
+                        *saved = Instant::now();
+                    }
+                }
+                if stop {
+                    return false
+                }
+
+                let changes = reload.as_mut().map(StoreWatcher::changes).unwrap_or_default();
+                for change in changes {
+                    match change {
+                        StoreChange::Added(other) if other.name != name => {
+                            println!("Trusting new identity {}", other);
+                            names.insert(other.id(), other.name.clone());
+                            swarm.add_trusted_peer(other.id());
+                            swarm.dial_peer(other.id());
+                        },
+                        StoreChange::Added(_) => (),
+                        StoreChange::Removed { name, id } => {
+                            println!("No longer trusting identity {} ({})", name, id);
+                            names.remove(&id);
+                            swarm.remove_peer(&id);
+                        },
+                    }
+                }
+
+                if !watching {
+                    return true
+                }
+
+                let mut peers = swarm.snapshot();
+                watch::sort_peers(&mut peers, &names);
 
-    // Use tokio to drive the `Swarm`.
-    let mut listening = false;
-    task::block_on(future::poll_fn(move |cx: &mut Context| -> Poll<()> {
-        loop {
-            match swarm.poll_next_unpin(cx) {
-                Poll::Ready(Some(e)) => println!("{:?}", e),
-                Poll::Ready(None) => return Poll::Ready(()),
-                Poll::Pending => {
-                    if !listening {
-                        for addr in Swarm::listeners(&swarm) {
-                            println!("Listening on {:?}", addr);
-                            listening = true;
-                        }
+                if tty {
+                    print!("{}{}", watch::CLEAR, watch::render_table(&peers, &names));
+                } else {
+                    for peer in peers.iter() {
+                        println!("{}", watch::render_line(peer, &names));
                     }
-                    break
                 }
             }
         }
-        Poll::Pending
+
+        true
     }));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_status_timing() {
+        let options = parse_args(&args("--interval 30 --timeout 5 --max-failures 3 alice")).unwrap();
+        assert_eq!(options.name.as_ref().map(String::as_str), Some("alice"));
+
+        let config = Config {
+            interval: options.interval,
+            timeout: options.timeout,
+            max_failures: options.max_failures,
+            ..Config::default()
+        };
+        let status = config.status_config(generate_payload());
+
+        assert_eq!(status.interval(), Duration::from_secs(30));
+        assert_eq!(status.timeout(), Duration::from_secs(5));
+        assert_eq!(status.max_failures().get(), 3);
+
+        // Zero interval, timeout or failures are rejected
+        assert!(parse_args(&args("--interval 0 alice")).is_none());
+        assert!(parse_args(&args("--timeout 0 alice")).is_none());
+        assert!(parse_args(&args("--max-failures 0 alice")).is_none());
+        assert!(parse_args(&args("--interval soon alice")).is_none());
+    }
+
+    #[test]
+    fn parse_modes() {
+        assert!(parse_args(&args("routing --duration 10 alice")).unwrap().routing);
+        assert!(parse_args(&args("topology --duration 10 --json alice")).unwrap().topology);
+
+        assert!(parse_args(&args("routing topology alice")).is_none());
+        assert!(parse_args(&args("watch routing alice")).is_none());
+        assert!(parse_args(&args("routing --json alice")).is_none());
+        assert!(parse_args(&args("watch --duration 10 alice")).is_none());
+    }
+
+    #[test]
+    fn dry_run_checks() {
+        let dir = std::env::temp_dir().join(format!("dx-test-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let alice = TrustedIdentity::new("alice".to_owned(), &dir);
+        TrustedIdentity::new("bob".to_owned(), &dir);
+
+        let options = parse_args(&args("--dry-run --listen /memory/1234 alice")).unwrap();
+        assert!(options.dry_run);
+        let summary = dry_run(&options, &Config::default(), &dir, "alice").unwrap();
+        assert!(summary.contains(&alice.id().to_string()));
+        assert!(summary.contains("/memory/1234"));
+        assert!(summary.contains("Trusted peers: 1"));
+
+        // Bad listen addresses and unknown identities are all reported
+        let options = parse_args(&args("--dry-run --listen /nowhere/1234 carol")).unwrap();
+        let errors = dry_run(&options, &Config::default(), &dir, "carol").unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().any(|error| error.contains("Invalid listen address '/nowhere/1234'")));
+        assert!(errors.iter().any(|error| error.contains("Unknown identity 'carol'")));
+
+        // As are broken key files
+        fs::write(dir.join("bob.pub"), b"garbage").unwrap();
+        let options = parse_args(&args("--dry-run alice")).unwrap();
+        let errors = dry_run(&options, &Config::default(), &dir, "alice").unwrap_err();
+        assert_eq!(errors, vec!["Trust store: bob: corrupt public key".to_owned()]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parse_ephemeral() {
+        let options = parse_args(&args("--identity-file /tmp/node.key")).unwrap();
+        assert!(options.ephemeral && options.name.is_none());
+        assert_eq!(options.identity_file, Some(PathBuf::from("/tmp/node.key")));
+
+        let options = parse_args(&args("--ephemeral scratch")).unwrap();
+        assert!(options.ephemeral && options.identity_file.is_none());
+        assert!(parse_args(&args("--identity-file")).is_none());
+
+        // The identity file is only read, it is written when the node starts
+        let dir = std::env::temp_dir().join(format!("dx-test-{}", rand::random::<u64>()));
+        let store = dir.join("store");
+        fs::create_dir_all(&store).unwrap();
+        let file = dir.join("node.key");
+        let options = parse_args(&args(&format!("--dry-run --identity-file {}", file.display()))).unwrap();
+        let summary = dry_run(&options, &Config::default(), &store, "ephemeral").unwrap();
+        assert!(summary.contains("generated on start"));
+        assert!(!file.exists());
+
+        let identity = TrustedIdentity::ephemeral("ephemeral".to_owned(), Some(&file)).unwrap();
+        let summary = dry_run(&options, &Config::default(), &store, "ephemeral").unwrap();
+        assert!(summary.contains(&identity.id().to_string()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parse_profile() {
+        assert_eq!(parse_args(&args("--profile aggressive alice")).unwrap().profile, Some(StatusProfile::Aggressive));
+        assert_eq!(parse_args(&args("alice")).unwrap().profile, None);
+        assert!(parse_args(&args("--profile lazy alice")).is_none());
+    }
+}
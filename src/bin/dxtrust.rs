@@ -1,45 +1,275 @@
-use std::env;
+use std::{env, fs};
+use std::path::{Path, PathBuf};
 
+use serde::Serialize;
+
+use dx::logging;
 use dx::trust::{
     TrustStore,
     TrustedIdentity,
 };
 
 fn help() {
-    println!("usage:
+    println!("usage: dxtrust {} [--json] <command>
 dxtrust list
     List keys currently in trusted peer database.
 dxtrust generate <name>
-    Generate new keypair for given hostname.");
+    Generate new keypair for given hostname.
+dxtrust show <name>
+    Show peer id, fingerprint and key files of given identity.
+dxtrust import <name> <file>
+    Import libp2p private key (protobuf, base64 or PEM) for given hostname.
+dxtrust rotate <name>
+    Replace keypair of given local identity, keeping the old one as backup.
+dxtrust verify
+    Check trusted peer database for corrupt or mismatched keys.
+dxtrust fingerprint <name>
+    Show fingerprint of given identity to compare it out-of-band.
+dxtrust verify <name> <fingerprint>
+    Check fingerprint of given identity against the one told by its owner.
+
+With --json, list, generate and show print JSON instead, for use in scripts.", logging::USAGE);
+}
+
+/// Key files of identity in the trust store at dir, the private one only if present
+fn key_files(id: &TrustedIdentity, dir: &Path) -> (PathBuf, Option<PathBuf>) {
+    let prefix = dir.join(&id.name);
+    let private = prefix.with_extension("key");
+
+    (prefix.with_extension("pub"), Some(private).filter(|file| file.exists()))
+}
+
+/// Identity of the trust store as printed with --json
+#[derive(Serialize)]
+struct IdentityJson {
+    name: String,
+    peer_id: String,
+    fingerprint: String,
+    public_key: String,
+    /// Only present on the host the identity belongs to
+    private_key: Option<String>,
+}
+
+impl IdentityJson {
+    fn new(id: &TrustedIdentity, dir: &Path) -> Self {
+        let (public, private) = key_files(id, dir);
+
+        IdentityJson {
+            name: id.name.clone(),
+            peer_id: id.id().to_base58(),
+            fingerprint: id.fingerprint(),
+            public_key: public.to_string_lossy().into_owned(),
+            private_key: private.map(|file| file.to_string_lossy().into_owned()),
+        }
+    }
+}
+
+/// Render identity of the trust store at dir as JSON object
+fn render_json(id: &TrustedIdentity, dir: &Path) -> String {
+    serde_json::to_string(&IdentityJson::new(id, dir)).expect("Identity is valid JSON")
+}
+
+/// Render identities of the trust store at dir as JSON array
+fn render_json_list(ids: &[&TrustedIdentity], dir: &Path) -> String {
+    let entries: Vec<IdentityJson> = ids.iter().map(|id| IdentityJson::new(id, dir)).collect();
+
+    serde_json::to_string(&entries).expect("Identities are valid JSON")
+}
+
+/// Render identity of the trust store at dir for humans
+fn render_text(id: &TrustedIdentity, dir: &Path) -> String {
+    let (public, private) = key_files(id, dir);
+    let private = private.map_or_else(|| "none".to_owned(), |file| file.display().to_string());
+
+    format!("name: {}\npeer id: {}\nfingerprint: {}\npublic key: {}\nprivate key: {}",
+        id.name, id.id(), id.fingerprint(), public.display(), private)
 }
 
-fn list() {
+fn list(json: bool) {
     let store = TrustStore::load();
 
-    for peer in store.ids {
+    if json {
+        return println!("{}", render_json_list(&store.sorted(), &TrustStore::path()));
+    }
+
+    for peer in store.sorted() {
         println!("{}: {}", peer.name, peer.id());
     }
 }
 
-fn generate(name: String) {
+fn verify() {
+    let issues = TrustStore::verify_integrity();
+
+    for issue in issues.iter() {
+        println!("{}", issue);
+    }
+
+    if issues.is_empty() {
+        println!("No issues found.");
+    } else {
+        std::process::exit(1);
+    }
+}
+
+fn find(store: &TrustStore, name: &str) -> TrustedIdentity {
+    match store.find(name) {
+        Some(id) => id.clone(),
+        None => {
+            println!("Unknown identity '{}'", name);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn show(name: &str, json: bool) {
+    let id = find(&TrustStore::load(), name);
+
+    if json {
+        println!("{}", render_json(&id, &TrustStore::path()));
+    } else {
+        println!("{}", render_text(&id, &TrustStore::path()));
+    }
+}
+
+fn fingerprint(name: &str) {
+    let id = find(&TrustStore::load(), name);
+
+    println!("{}: {}", id.name, id.fingerprint());
+}
+
+fn verify_fingerprint(name: &str, expected: &str) {
+    let id = find(&TrustStore::load(), name);
+
+    if id.fingerprint_matches(expected) {
+        println!("{}: fingerprint verified", id.name);
+    } else {
+        println!("{}: fingerprint MISMATCH, expected {} but have {}", id.name, expected, id.fingerprint());
+        std::process::exit(1);
+    }
+}
+
+fn generate(name: String, json: bool) {
     let id = TrustedIdentity::new(name, &TrustStore::path());
 
-    println!("{}: {}", id.name, id.id());
+    if json {
+        println!("{}", render_json(&id, &TrustStore::path()));
+    } else {
+        println!("{}: {}", id.name, id.id());
+    }
+}
+
+fn import(name: String, file: &str) {
+    let data = match fs::read(file) {
+        Ok(data) => data,
+        Err(e) => {
+            println!("Failed to read '{}': {}", file, e);
+            std::process::exit(1);
+        }
+    };
+
+    match TrustedIdentity::import(name, &data, &TrustStore::path()) {
+        Some(id) => println!("{}: {}", id.name, id.id()),
+        None => {
+            println!("Unsupported key format in '{}'", file);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn rotate(name: &str) {
+    match TrustStore::rotate(name) {
+        Ok((old, new)) => {
+            println!("{}: {}", name, new);
+            println!();
+            println!("WARNING: The peer id of '{}' has changed, it was {}.", name, old);
+            println!("Every peer trusting it needs the new public key in its trust store.");
+        },
+        Err(error) => {
+            println!("{}", error);
+            std::process::exit(1);
+        }
+    }
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let (level, mut args) = match logging::parse_flags(&env::args().collect::<Vec<_>>()) {
+        Some(flags) => flags,
+        None => return help(),
+    };
+    logging::init(level);
+
+    let json = args.iter().any(|arg| arg == "--json");
+    args.retain(|arg| arg != "--json");
+
+    // Only some commands have machine-readable output
+    let command = args.get(1).map(String::as_str);
+    if json && !["list", "generate", "show"].contains(&command.unwrap_or("")) {
+        return help();
+    }
 
     match args.len() {
         1 => help(),
         2 => match args[1].as_str() {
-            "list" => list(),
+            "list" => list(json),
+            "verify" => verify(),
             _ => help(),
         },
         3 => match args[1].as_str() {
-            "generate" => generate(args[2].clone()),
+            "generate" => generate(args[2].clone(), json),
+            "show" => show(&args[2], json),
+            "fingerprint" => fingerprint(&args[2]),
+            "rotate" => rotate(&args[2]),
+            _ => help(),
+        }
+        4 => match args[1].as_str() {
+            "verify" => verify_fingerprint(&args[2], &args[3]),
+            "import" => import(args[2].clone(), &args[3]),
             _ => help(),
         }
         _ => help(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::{json, Value};
+
+    #[test]
+    fn render_identities() {
+        let dir = std::env::temp_dir().join(format!("dx-test-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let alice = TrustedIdentity::new("alice".to_owned(), &dir);
+        let bob = TrustedIdentity::new("bob".to_owned(), &dir);
+        fs::remove_file(dir.join("bob.key")).unwrap();
+
+        // Names may hold anything a file name can
+        let eve = TrustedIdentity::new("eve \"\\\t\n".to_owned(), &dir);
+
+        let parse = |json: String| -> Value { serde_json::from_str(&json).unwrap() };
+        let fields = |id: &TrustedIdentity, private: Value| json!({
+            "name": id.name,
+            "peer_id": id.id().to_base58(),
+            "fingerprint": id.fingerprint(),
+            "public_key": dir.join(&id.name).with_extension("pub").to_str().unwrap(),
+            "private_key": private,
+        });
+
+        let expected = fields(&alice, json!(dir.join("alice.key").to_str().unwrap()));
+        assert_eq!(parse(render_json(&alice, &dir)), expected);
+        assert_eq!(parse(render_json(&eve, &dir))["name"], json!(eve.name));
+
+        // Hosts without the private key report none
+        let other = fields(&bob, Value::Null);
+        assert_eq!(parse(render_json(&bob, &dir)), other);
+        assert_eq!(parse(render_json_list(&[&alice, &bob], &dir)), json!([expected, other]));
+        assert_eq!(parse(render_json_list(&[], &dir)), json!([]));
+
+        let text = render_text(&bob, &dir);
+        assert!(text.contains(&format!("peer id: {}", bob.id())));
+        assert!(text.ends_with("private key: none"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,166 @@
+use std::fmt;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use futures::{future::BoxFuture, prelude::*};
+
+use wasm_timer::Delay;
+
+
+/// Source of the current time and of timers based on it.
+///
+/// All timing of the status handler and the behaviour goes through a clock,
+/// so that tests can use a [`ManualClock`] instead of sleeping.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// The current time
+    fn now(&self) -> Instant;
+
+    /// Future that completes once the given time has been reached
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()>;
+}
+
+/// Clock shared among handlers and behaviours
+pub type SharedClock = Arc<dyn Clock>;
+
+/// Clock following the system time
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RealClock;
+
+impl Clock for RealClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        Delay::new_at(deadline).map(|_| ()).boxed()
+    }
+}
+
+/// Shared real clock, used by default
+pub fn real() -> SharedClock {
+    Arc::new(RealClock)
+}
+
+#[derive(Debug)]
+struct ManualState {
+    now: Instant,
+    /// Tasks waiting for the time to advance
+    wakers: Vec<Waker>,
+}
+
+/// Clock that only advances when told to, for deterministic tests
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    state: Arc<Mutex<ManualState>>,
+}
+
+impl ManualClock {
+    /// Create clock starting at the current system time
+    pub fn new() -> Self {
+        let state = ManualState { now: Instant::now(), wakers: Vec::new() };
+        ManualClock { state: Arc::new(Mutex::new(state)) }
+    }
+
+    /// Move the clock forward, completing all timers that are due
+    pub fn advance(&self, d: Duration) {
+        let wakers = {
+            let mut state = self.state.lock().unwrap();
+            state.now += d;
+            std::mem::replace(&mut state.wakers, Vec::new())
+        };
+
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.state.lock().unwrap().now
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        let state = self.state.clone();
+
+        future::poll_fn(move |cx: &mut Context| {
+            let mut state = state.lock().unwrap();
+            if state.now >= deadline {
+                Poll::Ready(())
+            } else {
+                state.wakers.push(cx.waker().clone());
+                Poll::Pending
+            }
+        }).boxed()
+    }
+}
+
+/// Resettable timer driven by a clock
+pub struct Timer {
+    clock: SharedClock,
+    sleep: BoxFuture<'static, ()>,
+}
+
+impl Timer {
+    /// Create timer that fires after the given delay
+    pub fn new(clock: SharedClock, d: Duration) -> Self {
+        let sleep = clock.sleep_until(clock.now() + d);
+        Timer { clock, sleep }
+    }
+
+    /// Fire after the given delay from now on instead
+    pub fn reset(&mut self, d: Duration) {
+        self.sleep = self.clock.sleep_until(self.clock.now() + d);
+    }
+}
+
+impl fmt::Debug for Timer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Timer").field("clock", &self.clock).finish()
+    }
+}
+
+impl Future for Timer {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        self.sleep.poll_unpin(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ManualClock, Timer};
+    use futures::prelude::*;
+    use std::{sync::Arc, time::Duration};
+
+    #[test]
+    fn manual_interval() {
+        let clock = ManualClock::new();
+        let interval = Duration::from_secs(15);
+        let mut timer = Timer::new(Arc::new(clock.clone()), interval);
+
+        for _ in 0..3 {
+            // Not a moment too soon
+            clock.advance(interval - Duration::from_millis(1));
+            assert!(timer.poll_unpin(&mut noop_context()).is_pending());
+
+            clock.advance(Duration::from_millis(1));
+            assert!(timer.poll_unpin(&mut noop_context()).is_ready());
+
+            timer.reset(interval);
+        }
+    }
+
+    fn noop_context() -> std::task::Context<'static> {
+        std::task::Context::from_waker(futures::task::noop_waker_ref())
+    }
+}
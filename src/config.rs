@@ -0,0 +1,422 @@
+use std::{error::Error, fmt, fs, io, num::NonZeroU32, path::Path, str::FromStr, time::Duration};
+
+use libp2p::{Multiaddr, PeerId};
+
+use serde::Deserialize;
+
+use crate::behaviour::BehaviourConfig;
+use crate::status::{Payload, StatusConfig, StatusProfile};
+use crate::transport::{AddressPreference, ProtocolAllowlist, is_protocol_name};
+
+
+/// On-disk format of the configuration file
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    identity: Option<String>,
+    network: Option<String>,
+    #[serde(default)]
+    listen: Vec<String>,
+    #[serde(default)]
+    bootstrap: Vec<RawPeer>,
+    #[serde(default)]
+    peers: Vec<RawPeer>,
+    dial_protocols: Option<Vec<String>>,
+    address_preference: Option<String>,
+    dns: Option<bool>,
+    max_dials: Option<usize>,
+    #[serde(default)]
+    status: RawStatus,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPeer {
+    id: String,
+    address: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawStatus {
+    profile: Option<String>,
+    /// Interval in seconds
+    interval: Option<u64>,
+    /// Timeout in seconds
+    timeout: Option<u64>,
+    max_failures: Option<u32>,
+}
+
+/// Node settings as read from a `dx.toml` file, e.g.:
+///
+/// ```toml
+/// identity = "alice"
+/// network = "dx"
+/// listen = ["/ip4/0.0.0.0/tcp/4001"]
+///
+/// dial_protocols = ["ip4", "ip6", "tcp", "p2p"]
+/// address_preference = "happy-eyeballs" # or "any", "ipv4", "ipv6"
+/// dns = true # resolve /dns4 and /dns6 addresses
+/// max_dials = 32 # dials outstanding at once
+///
+/// [[bootstrap]]
+/// id = "QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ"
+/// address = "/ip4/104.131.131.82/tcp/4001"
+///
+/// [[peers]]
+/// id = "QmNnooDu7bfjPFoTZYxMNLWUQJyrVwtbZg5gBMjTezGAJN"
+/// address = "/ip4/192.0.2.1/tcp/4001"
+///
+/// [status]
+/// profile = "default" # or "aggressive", "relaxed"
+/// interval = 15
+/// timeout = 20
+/// max_failures = 1
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// Name of the local identity in the trust store
+    pub identity: Option<String>,
+    /// Name of the network to join, which the protocols are scoped to
+    pub network: Option<NetworkName>,
+    /// Addresses to listen on
+    pub listen: Vec<Multiaddr>,
+    /// Bootstrap peers, replacing the default IPFS nodes if not empty
+    pub bootstrap: Vec<(PeerId, Multiaddr)>,
+    /// Peers to watch at known addresses
+    pub peers: Vec<(PeerId, Multiaddr)>,
+    /// Protocols of addresses that may be dialed
+    pub dial_protocols: ProtocolAllowlist,
+    /// Order in which address families of peers are dialed
+    pub address_preference: AddressPreference,
+    /// Whether hostnames are not resolved, e.g. in air-gapped networks
+    pub disable_dns: bool,
+    /// Maximum number of dials outstanding at once
+    pub max_dials: Option<usize>,
+    /// Preset of status settings the others are applied on top of
+    pub profile: StatusProfile,
+    /// Status request interval
+    pub interval: Option<Duration>,
+    /// Status request timeout
+    pub timeout: Option<Duration>,
+    /// Consecutive status request failures before a connection is closed
+    pub max_failures: Option<NonZeroU32>,
+}
+
+/// Failure to load the configuration
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read.
+    Io(io::Error),
+    /// The file is not valid TOML or does not match the expected format.
+    Parse(toml::de::Error),
+    /// A value could not be interpreted.
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "Failed to read config: {}", error),
+            ConfigError::Parse(error) => write!(f, "Malformed config: {}", error),
+            ConfigError::Invalid(msg) => write!(f, "Invalid config: {}", msg),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io(error) => Some(error),
+            ConfigError::Parse(error) => Some(error),
+            ConfigError::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(error: io::Error) -> Self {
+        ConfigError::Io(error)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(error: toml::de::Error) -> Self {
+        ConfigError::Parse(error)
+    }
+}
+
+/// Name of a network, safe to use as segment of a protocol name
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NetworkName(String);
+
+impl NetworkName {
+    /// Name of the given protocol within this network, e.g.
+    /// `/dx/testnet/status/0.1.0` for `status/0.1.0`
+    pub fn protocol(&self, protocol: &str) -> String {
+        format!("/dx/{}/{}", self.0, protocol.trim_start_matches('/'))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for NetworkName {
+    type Err = ConfigError;
+
+    /// Accepts ASCII letters, digits, `-`, `_` and `.`, but not `.` or `..`
+    /// alone, which would read as relative path in the protocol name
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let legal = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.';
+
+        if s.is_empty() || s == "." || s == ".." || !s.chars().all(legal) {
+            return Err(ConfigError::Invalid(format!("bad network name '{}'", s)))
+        }
+
+        Ok(NetworkName(s.to_owned()))
+    }
+}
+
+impl fmt::Display for NetworkName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn parse_addr(addr: &str) -> Result<Multiaddr, ConfigError> {
+    addr.parse().map_err(|_| ConfigError::Invalid(format!("bad multiaddr '{}'", addr)))
+}
+
+fn parse_preference(preference: &str) -> Result<AddressPreference, ConfigError> {
+    match preference {
+        "any" => Ok(AddressPreference::Any),
+        "ipv4" => Ok(AddressPreference::Ipv4),
+        "ipv6" => Ok(AddressPreference::Ipv6),
+        "happy-eyeballs" => Ok(AddressPreference::HappyEyeballs),
+        _ => Err(ConfigError::Invalid(format!("bad address preference '{}'", preference))),
+    }
+}
+
+fn parse_allowlist(protocols: Vec<String>) -> Result<ProtocolAllowlist, ConfigError> {
+    match protocols.iter().find(|name| !is_protocol_name(name)) {
+        Some(name) => Err(ConfigError::Invalid(format!("bad dial protocol '{}'", name))),
+        None => Ok(ProtocolAllowlist::new(protocols)),
+    }
+}
+
+fn parse_profile(profile: &str) -> Result<StatusProfile, ConfigError> {
+    StatusProfile::from_name(profile)
+        .ok_or_else(|| ConfigError::Invalid(format!("bad status profile '{}'", profile)))
+}
+
+fn parse_id(id: &str) -> Result<PeerId, ConfigError> {
+    id.parse().map_err(|_| ConfigError::Invalid(format!("bad peer id '{}'", id)))
+}
+
+impl Config {
+    /// Load configuration from file
+    pub fn from_path(path: &Path) -> Result<Self, ConfigError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Status configuration of the configured profile, with the configured
+    /// interval, timeout and maximum failures applied
+    pub fn status_config(&self, status: Payload) -> StatusConfig {
+        let mut config = self.profile.config(status).with_keep_alive(true);
+
+        if let Some(interval) = self.interval {
+            config = config.with_interval(interval);
+        }
+        if let Some(timeout) = self.timeout {
+            config = config.with_timeout(timeout);
+        }
+        if let Some(max_failures) = self.max_failures {
+            config = config.with_max_failures(max_failures);
+        }
+        if let Some(network) = &self.network {
+            config = config.with_network(network.clone());
+        }
+
+        config
+    }
+
+    /// Behaviour configuration using the given status configuration,
+    /// e.g. from [`Config::status_config`], with the configured settings applied
+    pub fn behaviour_config(&self, status: StatusConfig) -> BehaviourConfig {
+        let mut config = BehaviourConfig::new(status).with_address_preference(self.address_preference);
+
+        if self.max_dials.is_some() {
+            config = config.with_max_dials(self.max_dials);
+        }
+
+        if self.bootstrap.is_empty() {
+            config
+        } else {
+            config.with_bootstrap(self.bootstrap.clone())
+        }
+    }
+}
+
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let raw: RawConfig = toml::from_str(s)?;
+
+        let listen = raw.listen.iter()
+            .map(|addr| parse_addr(addr))
+            .collect::<Result<_, _>>()?;
+
+        let bootstrap = raw.bootstrap.iter()
+            .map(|peer| Ok((parse_id(&peer.id)?, parse_addr(&peer.address)?)))
+            .collect::<Result<_, ConfigError>>()?;
+
+        let peers = raw.peers.iter()
+            .map(|peer| Ok((parse_id(&peer.id)?, parse_addr(&peer.address)?)))
+            .collect::<Result<_, ConfigError>>()?;
+
+        if raw.max_dials == Some(0) {
+            return Err(ConfigError::Invalid("max_dials must be at least 1".to_owned()))
+        }
+        let max_dials = raw.max_dials;
+
+        let max_failures = match raw.status.max_failures {
+            Some(n) => Some(NonZeroU32::new(n)
+                .ok_or_else(|| ConfigError::Invalid("max_failures must be at least 1".to_owned()))?),
+            None => None,
+        };
+
+        // Zero would request or give up on statuses in a busy loop
+        if raw.status.interval == Some(0) {
+            return Err(ConfigError::Invalid("interval must be at least 1 second".to_owned()))
+        }
+        if raw.status.timeout == Some(0) {
+            return Err(ConfigError::Invalid("timeout must be at least 1 second".to_owned()))
+        }
+
+        Ok(Config {
+            identity: raw.identity,
+            network: raw.network.map(|network| network.parse()).transpose()?,
+            listen,
+            bootstrap,
+            peers,
+            dial_protocols: match raw.dial_protocols {
+                Some(protocols) => parse_allowlist(protocols)?,
+                None => ProtocolAllowlist::default(),
+            },
+            address_preference: match raw.address_preference {
+                Some(preference) => parse_preference(&preference)?,
+                None => AddressPreference::default(),
+            },
+            disable_dns: raw.dns == Some(false),
+            max_dials,
+            profile: match raw.status.profile {
+                Some(profile) => parse_profile(&profile)?,
+                None => StatusProfile::default(),
+            },
+            interval: raw.status.interval.map(Duration::from_secs),
+            timeout: raw.status.timeout.map(Duration::from_secs),
+            max_failures,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, ConfigError, NetworkName};
+    use std::time::Duration;
+
+    #[test]
+    fn parse_config() {
+        let config: Config = r#"
+            identity = "alice"
+            network = "testnet"
+            listen = ["/ip4/0.0.0.0/tcp/4001", "/ip6/::/tcp/4001"]
+
+            [[bootstrap]]
+            id = "QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ"
+            address = "/dns4/bootstrap.example.com/tcp/4001"
+
+            [status]
+            profile = "relaxed"
+            interval = 30
+            timeout = 5
+        "#.parse().unwrap();
+
+        assert_eq!(config.identity.as_ref().map(String::as_str), Some("alice"));
+        assert_eq!(config.network.as_ref().map(NetworkName::as_str), Some("testnet"));
+        assert_eq!(config.listen, vec![
+            "/ip4/0.0.0.0/tcp/4001".parse().unwrap(),
+            "/ip6/::/tcp/4001".parse().unwrap(),
+        ]);
+        assert_eq!(config.bootstrap, vec![(
+            "QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ".parse().unwrap(),
+            "/dns4/bootstrap.example.com/tcp/4001".parse().unwrap(),
+        )]);
+        assert!(!config.disable_dns);
+        assert_eq!(config.interval, Some(Duration::from_secs(30)));
+        assert_eq!(config.timeout, Some(Duration::from_secs(5)));
+
+        // Explicit settings take precedence over the profile
+        let status = config.status_config([0; 20]);
+        assert_eq!(status.interval(), Duration::from_secs(30));
+        assert_eq!(status.max_failures().get(), 5);
+    }
+
+    #[test]
+    fn reject_malformed_config() {
+        match "identity = ".parse::<Config>() {
+            Err(ConfigError::Parse(_)) => (),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        match "listen = [\"not an address\"]".parse::<Config>() {
+            Err(ConfigError::Invalid(_)) => (),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        // Misspelled protocols would silently refuse every address
+        match "dial_protocols = [\"ipv4\", \"tcp\"]".parse::<Config>() {
+            Err(ConfigError::Invalid(_)) => (),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!("dial_protocols = [\"ip4\", \"tcp\"]".parse::<Config>().is_ok());
+
+        // Zero durations and limits are refused instead of spinning
+        for zero in ["interval = 0", "timeout = 0", "max_failures = 0"].iter() {
+            match format!("[status]\n{}", zero).parse::<Config>() {
+                Err(ConfigError::Invalid(_)) => (),
+                other => panic!("Unexpected result for '{}': {:?}", zero, other),
+            }
+        }
+        match "max_dials = 0".parse::<Config>() {
+            Err(ConfigError::Invalid(_)) => (),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn network_names() {
+        for name in ["dx", "testnet-2", "staging_eu", "v1.2"].iter() {
+            let network: NetworkName = name.parse().unwrap();
+            assert_eq!(network.to_string(), *name);
+        }
+
+        for name in ["", ".", "..", "test net", "a/b", "dx\n", "caf\u{e9}"].iter() {
+            assert!(name.parse::<NetworkName>().is_err(), "Accepted '{}'", name);
+        }
+
+        let network: NetworkName = "testnet".parse().unwrap();
+        assert_eq!(network.protocol("status/0.1.0"), "/dx/testnet/status/0.1.0");
+        assert_eq!(network.protocol("/kad/1.0.0"), "/dx/testnet/kad/1.0.0");
+
+        let config: Config = "network = \"testnet\"".parse().unwrap();
+        assert_eq!(&*config.status_config([0; 20]).protocol_name(), &b"/dx/testnet/status/0.1.0"[..]);
+
+        match "network = \"my network\"".parse::<Config>() {
+            Err(ConfigError::Invalid(_)) => (),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+}
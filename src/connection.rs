@@ -0,0 +1,216 @@
+use std::collections::{HashSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::task::{Context, Poll};
+
+use libp2p::{
+    Multiaddr,
+    PeerId,
+    core::{ConnectedPoint, upgrade::DeniedUpgrade},
+    swarm::{
+        KeepAlive,
+        NetworkBehaviour,
+        NetworkBehaviourAction,
+        PollParameters,
+        ProtocolsHandler,
+        ProtocolsHandlerEvent,
+        ProtocolsHandlerUpgrErr,
+        SubstreamProtocol,
+    },
+};
+
+use void::Void;
+
+
+/// Connection lifecycle event
+#[derive(Debug, Clone)]
+pub enum ConnectionEvent {
+    /// A connection to the peer has been established
+    Established { peer: PeerId, endpoint: ConnectedPoint },
+    /// The connection to the peer has been closed
+    Closed { peer: PeerId, endpoint: ConnectedPoint },
+    /// The connection to the peer has been replaced by a newer one, as the
+    /// swarm keeps a single connection per peer
+    Replaced { peer: PeerId, closed: ConnectedPoint, new: ConnectedPoint },
+    /// Dialing an address failed or was refused by the transport
+    Unreachable { peer: Option<PeerId>, address: Multiaddr, error: String },
+    /// Dialing the peer failed on all known addresses
+    DialFailure { peer: PeerId },
+}
+
+/// Reason a connection was closed by [`ConnectionHandler`]
+#[derive(Debug)]
+pub struct Disconnected;
+
+impl fmt::Display for Disconnected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Disconnected on request")
+    }
+}
+
+impl Error for Disconnected {}
+
+/// Handler that does not speak any protocol either, but closes the
+/// connection once told to by the behaviour.
+#[derive(Debug, Default)]
+pub struct ConnectionHandler {
+    /// Whether the connection is to be closed.
+    close: bool,
+}
+
+impl ProtocolsHandler for ConnectionHandler {
+    type InEvent = ();
+    type OutEvent = Void;
+    type Error = Disconnected;
+    type InboundProtocol = DeniedUpgrade;
+    type OutboundProtocol = DeniedUpgrade;
+    type OutboundOpenInfo = Void;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<DeniedUpgrade> {
+        SubstreamProtocol::new(DeniedUpgrade)
+    }
+
+    fn inject_fully_negotiated_inbound(&mut self, out: Void) {
+        void::unreachable(out)
+    }
+
+    fn inject_fully_negotiated_outbound(&mut self, out: Void, _: Void) {
+        void::unreachable(out)
+    }
+
+    fn inject_event(&mut self, _: ()) {
+        self.close = true;
+    }
+
+    fn inject_dial_upgrade_error(&mut self, info: Void, _: ProtocolsHandlerUpgrErr<Void>) {
+        void::unreachable(info)
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        KeepAlive::No
+    }
+
+    fn poll(&mut self, _: &mut Context) -> Poll<ProtocolsHandlerEvent<DeniedUpgrade, Void, Void, Disconnected>> {
+        if self.close {
+            Poll::Ready(ProtocolsHandlerEvent::Close(Disconnected))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Behaviour that does not speak any protocol itself, but reports the
+/// connection lifecycle observed by the swarm and tracks our own addresses.
+#[derive(Default)]
+pub struct Connections {
+    /// Queue of events to yield to the swarm.
+    events: VecDeque<ConnectionEvent>,
+    /// Peers we are currently connected to.
+    connected: HashSet<PeerId>,
+    /// Peers whose connection is to be closed.
+    disconnecting: HashSet<PeerId>,
+    /// Addresses we are currently listening on.
+    listen: Vec<Multiaddr>,
+    /// Addresses we are reachable at as observed by others.
+    external: Vec<Multiaddr>,
+}
+
+impl Connections {
+    /// Addresses we are reachable at, confirmed external ones first
+    pub fn addresses(&self) -> Vec<Multiaddr> {
+        let mut addresses = self.external.clone();
+        for addr in self.listen.iter() {
+            if !addresses.contains(addr) {
+                addresses.push(addr.clone());
+            }
+        }
+        addresses
+    }
+
+    /// Peers we are currently connected to
+    pub fn connected_peers(&self) -> impl Iterator<Item = &PeerId> {
+        self.connected.iter()
+    }
+
+    /// Close the connection to the peer, if there is one
+    pub fn disconnect(&mut self, peer: &PeerId) {
+        if self.connected.contains(peer) {
+            self.disconnecting.insert(peer.clone());
+        }
+    }
+}
+
+impl NetworkBehaviour for Connections {
+    type ProtocolsHandler = ConnectionHandler;
+    type OutEvent = ConnectionEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        ConnectionHandler::default()
+    }
+
+    fn addresses_of_peer(&mut self, _peer_id: &PeerId) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn inject_connected(&mut self, peer: PeerId, endpoint: ConnectedPoint) {
+        self.connected.insert(peer.clone());
+        self.events.push_back(ConnectionEvent::Established { peer, endpoint });
+    }
+
+    fn inject_replaced(&mut self, peer: PeerId, closed: ConnectedPoint, new: ConnectedPoint) {
+        self.events.push_back(ConnectionEvent::Replaced { peer, closed, new });
+    }
+
+    fn inject_disconnected(&mut self, peer: &PeerId, endpoint: ConnectedPoint) {
+        self.connected.remove(peer);
+        self.disconnecting.remove(peer);
+        self.events.push_back(ConnectionEvent::Closed { peer: peer.clone(), endpoint });
+    }
+
+    fn inject_addr_reach_failure(&mut self, peer: Option<&PeerId>, address: &Multiaddr, error: &dyn Error) {
+        self.events.push_back(ConnectionEvent::Unreachable {
+            peer: peer.cloned(),
+            address: address.clone(),
+            error: error.to_string(),
+        });
+    }
+
+    fn inject_dial_failure(&mut self, peer: &PeerId) {
+        self.events.push_back(ConnectionEvent::DialFailure { peer: peer.clone() });
+    }
+
+    fn inject_new_listen_addr(&mut self, addr: &Multiaddr) {
+        if !self.listen.contains(addr) {
+            self.listen.push(addr.clone());
+        }
+    }
+
+    fn inject_expired_listen_addr(&mut self, addr: &Multiaddr) {
+        self.listen.retain(|a| a != addr);
+    }
+
+    fn inject_new_external_addr(&mut self, addr: &Multiaddr) {
+        if !self.external.contains(addr) {
+            self.external.push(addr.clone());
+        }
+    }
+
+    fn inject_node_event(&mut self, _: PeerId, event: Void) {
+        void::unreachable(event)
+    }
+
+    fn poll(&mut self, _: &mut Context, _: &mut impl PollParameters)
+            -> Poll<NetworkBehaviourAction<(), ConnectionEvent>>
+    {
+        if let Some(peer) = self.disconnecting.iter().next().cloned() {
+            self.disconnecting.remove(&peer);
+            return Poll::Ready(NetworkBehaviourAction::SendEvent { peer_id: peer, event: () })
+        }
+
+        if let Some(e) = self.events.pop_front() {
+            Poll::Ready(NetworkBehaviourAction::GenerateEvent(e))
+        } else {
+            Poll::Pending
+        }
+    }
+}
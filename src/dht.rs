@@ -0,0 +1,261 @@
+use std::{collections::{HashMap, HashSet}, error, ops::{Deref, DerefMut}, task::{Context, Poll}};
+
+use crate::transport::AddressPreference;
+
+use libp2p::{
+    Multiaddr,
+    PeerId,
+    core::ConnectedPoint,
+    kad::{
+        Kademlia,
+        KademliaEvent,
+        handler::KademliaHandler,
+        record::store::MemoryStore,
+    },
+    swarm::{
+        NetworkBehaviour,
+        NetworkBehaviourAction,
+        PollParameters,
+        ProtocolsHandler,
+    },
+};
+
+
+/// Whether the node participates in the DHT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KademliaMode {
+    /// Answer inbound queries and store records for others
+    Server,
+    /// Only query the DHT, e.g. for short-lived nodes that can not
+    /// honor records stored on them
+    Client,
+    /// Neither query nor answer the DHT, only keep the addresses of peers,
+    /// e.g. on small networks where all addresses are known up front
+    Disabled,
+}
+
+impl Default for KademliaMode {
+    fn default() -> Self {
+        KademliaMode::Server
+    }
+}
+
+type Handler = <Kademlia<MemoryStore> as NetworkBehaviour>::ProtocolsHandler;
+
+/// Kademlia behaviour that can be restricted to client mode.
+///
+/// In client mode the handlers deny all inbound substreams, so that remote
+/// peers can not query or store records on this node.
+///
+/// When disabled, no queries are started either, so that the routing table
+/// only serves as address book.
+///
+/// Unreliable peers can be demoted, withholding their addresses so that they
+/// are no longer dialed for routing. They stay in the routing table, as the
+/// pinned Kademlia offers no way to remove addresses of an entry.
+///
+/// Addresses that failed to be dialed are provided after the others, so
+/// that dead addresses are tried last.
+pub struct Dht {
+    inner: Kademlia<MemoryStore>,
+    mode: KademliaMode,
+    /// Peers whose addresses are withheld
+    demoted: HashSet<PeerId>,
+    /// Order in which addresses of peers are provided
+    preference: AddressPreference,
+    /// Number of queries started so far
+    queries: usize,
+    /// Failed dials of peer addresses since they were last dialed successfully
+    failures: HashMap<(PeerId, Multiaddr), u32>,
+}
+
+impl Dht {
+    pub fn new(inner: Kademlia<MemoryStore>, mode: KademliaMode) -> Self {
+        Dht { inner, mode, demoted: HashSet::new(), preference: AddressPreference::default(), queries: 0, failures: HashMap::new() }
+    }
+
+    /// Bootstrap the routing table, unless disabled
+    pub fn bootstrap(&mut self) {
+        if self.mode != KademliaMode::Disabled {
+            self.queries += 1;
+            self.inner.bootstrap();
+        }
+    }
+
+    /// Look up the peers closest to the given one, unless disabled
+    pub fn get_closest_peers(&mut self, peer: PeerId) {
+        if self.mode != KademliaMode::Disabled {
+            self.queries += 1;
+            self.inner.get_closest_peers(peer);
+        }
+    }
+
+    /// Number of queries started so far
+    pub fn queries(&self) -> usize {
+        self.queries
+    }
+
+    /// Provide addresses of peers in the given order of address families
+    pub fn with_preference(mut self, preference: AddressPreference) -> Self {
+        self.preference = preference;
+        self
+    }
+
+    pub fn preference(&self) -> AddressPreference {
+        self.preference
+    }
+
+    pub fn mode(&self) -> KademliaMode {
+        self.mode
+    }
+
+    /// Withhold the addresses of peer, returns whether it was not demoted yet
+    pub fn demote(&mut self, peer: PeerId) -> bool {
+        self.demoted.insert(peer)
+    }
+
+    /// Provide the addresses of a previously demoted peer again
+    pub fn restore(&mut self, peer: &PeerId) {
+        self.demoted.remove(peer);
+    }
+
+    /// Whether the addresses of peer are withheld
+    pub fn is_demoted(&self, peer: &PeerId) -> bool {
+        self.demoted.contains(peer)
+    }
+
+    /// Note that dialing peer at address failed, deprioritizing the address
+    pub fn record_failure(&mut self, peer: &PeerId, addr: &Multiaddr) {
+        *self.failures.entry((peer.clone(), addr.clone())).or_insert(0) += 1;
+    }
+
+    /// Note that peer was dialed successfully at address, forgetting its failures
+    pub fn record_success(&mut self, peer: &PeerId, addr: &Multiaddr) {
+        self.failures.remove(&(peer.clone(), addr.clone()));
+    }
+
+    /// Failed dials of peer at address since it was last dialed successfully
+    pub fn failures(&self, peer: &PeerId, addr: &Multiaddr) -> u32 {
+        self.failures.get(&(peer.clone(), addr.clone())).cloned().unwrap_or(0)
+    }
+}
+
+impl Deref for Dht {
+    type Target = Kademlia<MemoryStore>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for Dht {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl NetworkBehaviour for Dht {
+    type ProtocolsHandler = Handler;
+    type OutEvent = KademliaEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        match self.mode {
+            KademliaMode::Server => self.inner.new_handler(),
+            // Without queries, nothing is ever dialed either
+            KademliaMode::Client | KademliaMode::Disabled => KademliaHandler::dial_only(),
+        }
+    }
+
+    fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        if self.demoted.contains(peer_id) {
+            return Vec::new()
+        }
+
+        // Keep the preferred order among addresses that failed equally often
+        let mut addrs = self.preference.order(self.inner.addresses_of_peer(peer_id));
+        addrs.sort_by_key(|addr| self.failures(peer_id, addr));
+        addrs
+    }
+
+    fn inject_connected(&mut self, peer_id: PeerId, endpoint: ConnectedPoint) {
+        self.inner.inject_connected(peer_id, endpoint)
+    }
+
+    fn inject_disconnected(&mut self, peer_id: &PeerId, endpoint: ConnectedPoint) {
+        self.inner.inject_disconnected(peer_id, endpoint)
+    }
+
+    fn inject_replaced(&mut self, peer_id: PeerId, closed: ConnectedPoint, new: ConnectedPoint) {
+        self.inner.inject_replaced(peer_id, closed, new)
+    }
+
+    fn inject_node_event(&mut self, peer_id: PeerId, event: <Handler as ProtocolsHandler>::OutEvent) {
+        self.inner.inject_node_event(peer_id, event)
+    }
+
+    fn inject_addr_reach_failure(&mut self, peer_id: Option<&PeerId>, addr: &Multiaddr, error: &dyn error::Error) {
+        self.inner.inject_addr_reach_failure(peer_id, addr, error)
+    }
+
+    fn inject_dial_failure(&mut self, peer_id: &PeerId) {
+        self.inner.inject_dial_failure(peer_id)
+    }
+
+    fn inject_new_listen_addr(&mut self, addr: &Multiaddr) {
+        self.inner.inject_new_listen_addr(addr)
+    }
+
+    fn inject_expired_listen_addr(&mut self, addr: &Multiaddr) {
+        self.inner.inject_expired_listen_addr(addr)
+    }
+
+    fn inject_new_external_addr(&mut self, addr: &Multiaddr) {
+        self.inner.inject_new_external_addr(addr)
+    }
+
+    fn poll(&mut self, cx: &mut Context, params: &mut impl PollParameters)
+        -> Poll<NetworkBehaviourAction<<Handler as ProtocolsHandler>::InEvent, KademliaEvent>>
+    {
+        self.inner.poll(cx, params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Dht, KademliaMode};
+    use libp2p::{
+        PeerId,
+        core::UpgradeInfo,
+        kad::{Kademlia, record::store::MemoryStore},
+        swarm::{NetworkBehaviour, ProtocolsHandler},
+    };
+
+    fn dht(mode: KademliaMode) -> Dht {
+        let id = PeerId::random();
+        Dht::new(Kademlia::new(id.clone(), MemoryStore::new(id)), mode)
+    }
+
+    /// Number of protocols inbound substreams are accepted for
+    fn inbound_protocols(dht: &mut Dht) -> usize {
+        dht.new_handler().listen_protocol().upgrade().protocol_info().into_iter().count()
+    }
+
+    #[test]
+    fn client_denies_inbound() {
+        assert_eq!(inbound_protocols(&mut dht(KademliaMode::Client)), 0);
+        assert_eq!(inbound_protocols(&mut dht(KademliaMode::Disabled)), 0);
+        assert!(inbound_protocols(&mut dht(KademliaMode::Server)) > 0);
+    }
+
+    #[test]
+    fn disabled_never_queries() {
+        let mut disabled = dht(KademliaMode::Disabled);
+        disabled.bootstrap();
+        disabled.get_closest_peers(PeerId::random());
+        assert_eq!(disabled.queries(), 0);
+
+        let mut client = dht(KademliaMode::Client);
+        client.get_closest_peers(PeerId::random());
+        assert_eq!(client.queries(), 1);
+    }
+}
@@ -1,3 +1,14 @@
 pub mod trust;
 pub mod status;
+pub mod transport;
 pub mod behaviour;
+pub mod config;
+pub mod connection;
+pub mod dht;
+pub mod reconnect;
+pub mod clock;
+pub mod watch;
+pub mod topology;
+pub mod node;
+pub mod addresses;
+pub mod logging;
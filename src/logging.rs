@@ -0,0 +1,86 @@
+use log::LevelFilter;
+
+
+/// Usage of the logging flags shared by all binaries
+pub const USAGE: &str = "[-v | -vv | -vvv | --log-level <error|warn|info|debug|trace>]";
+
+/// Log level chosen by the number of `-v` flags or given by name, the
+/// latter taking precedence. `None` if neither is given or the name is
+/// unknown.
+pub fn level(verbosity: usize, name: Option<&str>) -> Option<LevelFilter> {
+    if let Some(name) = name {
+        return name.parse().ok()
+    }
+
+    match verbosity {
+        0 => None,
+        1 => Some(LevelFilter::Info),
+        2 => Some(LevelFilter::Debug),
+        _ => Some(LevelFilter::Trace),
+    }
+}
+
+/// Split the logging flags from the other arguments, returning the chosen
+/// level and the remaining arguments. `None` if the flags are malformed.
+pub fn parse_flags(args: &[String]) -> Option<(Option<LevelFilter>, Vec<String>)> {
+    let mut verbosity = 0;
+    let mut name = None;
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--log-level" => name = Some(args.next()?.clone()),
+            flag if flag.len() > 1 && flag.starts_with('-') && flag[1..].chars().all(|c| c == 'v') =>
+                verbosity += flag.len() - 1,
+            _ => rest.push(arg.clone()),
+        }
+    }
+
+    match (level(verbosity, name.as_ref().map(String::as_str)), name) {
+        (None, Some(_)) => None,
+        (level, _) => Some((level, rest)),
+    }
+}
+
+/// Set up logging at the given level, or as configured by `RUST_LOG` if none
+pub fn init(level: Option<LevelFilter>) {
+    let mut builder = env_logger::Builder::from_default_env();
+
+    if let Some(level) = level {
+        builder.filter_level(level);
+    }
+
+    builder.init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{level, parse_flags};
+    use log::LevelFilter;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn map_flags_to_level() {
+        assert_eq!(level(0, None), None);
+        assert_eq!(level(1, None), Some(LevelFilter::Info));
+        assert_eq!(level(2, None), Some(LevelFilter::Debug));
+        assert_eq!(level(5, None), Some(LevelFilter::Trace));
+        assert_eq!(level(2, Some("warn")), Some(LevelFilter::Warn));
+        assert_eq!(level(0, Some("ERROR")), Some(LevelFilter::Error));
+        assert_eq!(level(0, Some("loud")), None);
+
+        assert_eq!(parse_flags(&args("-v watch -v alice")), Some((Some(LevelFilter::Debug), args("watch alice"))));
+        assert_eq!(parse_flags(&args("list -vvv")), Some((Some(LevelFilter::Trace), args("list"))));
+        assert_eq!(parse_flags(&args("--log-level info alice")), Some((Some(LevelFilter::Info), args("alice"))));
+        assert_eq!(parse_flags(&args("alice")), Some((None, args("alice"))));
+
+        // Unknown or missing level names are rejected, a lone dash is no flag
+        assert_eq!(parse_flags(&args("--log-level loud")), None);
+        assert_eq!(parse_flags(&args("alice --log-level")), None);
+        assert_eq!(parse_flags(&args("-")), Some((None, args("-"))));
+    }
+}
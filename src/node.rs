@@ -0,0 +1,1370 @@
+use std::{cell::RefCell, collections::HashSet, error::Error, fmt, io, path::PathBuf, time::Duration};
+
+use futures::{prelude::*, channel::{mpsc, oneshot}, future::{self, Either}, stream};
+
+use libp2p::{
+    Multiaddr,
+    PeerId,
+    Swarm,
+    Transport,
+    core::{
+        muxing::{StreamMuxer, StreamMuxerBox},
+        nodes::ListenerId,
+        transport::{OptionalTransport, TransportError, boxed::Boxed},
+        upgrade::{self, SelectUpgrade},
+    },
+    dns::DnsConfig,
+    identity::Keypair,
+    mplex::MplexConfig,
+    secio::SecioConfig,
+    swarm::SwarmEvent,
+    tcp::TcpConfig,
+    websocket::WsConfig,
+    yamux,
+};
+
+use log::warn;
+
+use wasm_timer::{Delay, Interval};
+
+use crate::addresses::AddressBook;
+use crate::behaviour::{Behaviour, BehaviourEvent, PeerInfo};
+use crate::config::Config;
+use crate::status::{Payload, StatusStats};
+use crate::transport::{FilteredTransport, RecordSecurity, SecurityTable};
+use crate::trust::{IdentityError, TrustStore, TrustedIdentity};
+
+
+/// Transport with erased type, as used by [`DxSwarm`]
+pub type DxTransport = Boxed<(PeerId, StreamMuxerBox), io::Error>;
+
+/// Swarm driving the dx behaviour
+pub type DxSwarm = Swarm<DxTransport, Behaviour>;
+
+/// Erase type of an authenticated and multiplexed transport
+pub fn boxed_transport<T, M>(transport: T) -> DxTransport
+where
+    T: Transport<Output = (PeerId, M)> + Clone + Send + Sync + 'static,
+    T::Error: Error,
+    T::Listener: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+    T::Dial: Send + 'static,
+    M: StreamMuxer + Send + Sync + 'static,
+    M::Substream: Send + 'static,
+    M::OutboundSubstream: Send + 'static,
+{
+    transport
+        .map(|(peer, muxer), _| (peer, StreamMuxerBox::new(muxer)))
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error.to_string()))
+        .boxed()
+}
+
+/// Transport like [`libp2p::build_development_transport`], i.e. TCP and
+/// websockets secured by secio and multiplexed by yamux or mplex, that only
+/// resolves `/dns4` and `/dns6` addresses if `dns` is set.
+///
+/// Without DNS, hostnames are refused right away instead of asking a resolver,
+/// e.g. in air-gapped networks. The security protocol of every connection is
+/// recorded in the given table, see [`RecordSecurity`].
+pub fn development_transport(key: Keypair, dns: bool, security: SecurityTable) -> io::Result<DxTransport> {
+    let tcp = TcpConfig::new().nodelay(true);
+
+    // Either transport refuses what it can not dial, so the first one to
+    // accept an address dials it
+    let resolver = if dns {
+        OptionalTransport::some(DnsConfig::new(tcp.clone())?)
+    } else {
+        OptionalTransport::none()
+    };
+    let transport = resolver.or_transport(tcp);
+    let transport = transport.clone().or_transport(WsConfig::new(transport));
+
+    Ok(boxed_transport(transport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(RecordSecurity::new(SecioConfig::new(key), security))
+        .multiplex(SelectUpgrade::new(yamux::Config::default(), MplexConfig::new()))
+        .timeout(Duration::from_secs(20))))
+}
+
+/// Swarm of a status node running as the named local identity of the trust
+/// store, as used by `dxstatus`, over the given authenticated transport,
+/// e.g. [`development_transport`].
+///
+/// The status is signed by the signer of the identity, see
+/// [`TrustedIdentity::signer`], and only signed statuses are accepted. Its
+/// private key may thus be held by an external signer only, as long as the
+/// transport is authenticated by other means. All other
+/// identities of the store are trusted and dialed, along with the peers
+/// configured at known addresses, and at the addresses remembered in the
+/// address book. Security protocols are taken from the table the transport
+/// records them in, see [`RecordSecurity`].
+pub fn status_swarm<T, M>(transport: T, security: SecurityTable, store: &TrustStore, name: &str, status: Payload, config: &Config, book: &AddressBook) -> Result<DxSwarm, IdentityError>
+where
+    T: Transport<Output = (PeerId, M)> + Clone + Send + Sync + 'static,
+    T::Error: Error,
+    T::Listener: Send + 'static,
+    T::ListenerUpgrade: Send + 'static,
+    T::Dial: Send + 'static,
+    M: StreamMuxer + Send + Sync + 'static,
+    M::Substream: Send + 'static,
+    M::OutboundSubstream: Send + 'static,
+{
+    let identity = store.local(name)?;
+    let signer = identity.signer()?;
+
+    let transport = boxed_transport(FilteredTransport::new(transport, config.dial_protocols.clone()));
+
+    // Sign our status and only accept signed ones, named after our identity
+    let status = config.status_config(status)
+        .with_signer(signer)
+        .with_require_signature(true)
+        .with_name(name);
+    let behaviour_config = config.behaviour_config(status).with_security_table(security);
+    let mut behaviour = Behaviour::with_config(identity.id(), behaviour_config);
+
+    for other in store.ids.iter() {
+        if other.name != name {
+            behaviour.add_trusted_peer(other.id())
+        }
+    }
+
+    // Watch peers with known addresses, no lookup needed
+    for (peer, addr) in config.peers.iter() {
+        behaviour.add_peer_with_address(peer.clone(), addr.clone());
+    }
+    book.seed(&mut behaviour);
+
+    let mut swarm = Swarm::new(transport, behaviour, identity.id());
+
+    // Try to connect to trusted peers
+    for other in store.ids.iter() {
+        if other.name != name {
+            swarm.dial_peer(other.id());
+        }
+    }
+
+    Ok(swarm)
+}
+
+/// Event observed while running a node
+#[derive(Debug)]
+pub enum NodeEvent {
+    /// Event reported by the swarm, including new listen addresses
+    Swarm(SwarmEvent<BehaviourEvent>),
+    /// Periodic tick, e.g. to refresh a display
+    Tick,
+}
+
+/// Drive the swarm, passing every event to the handler until it returns `false`.
+///
+/// Listen addresses are reported as [`SwarmEvent::NewListenAddr`] whenever
+/// they appear, including those of listeners added while running. If a tick
+/// period is given, [`NodeEvent::Tick`] is additionally reported periodically.
+pub async fn run<F>(swarm: &mut DxSwarm, tick: Option<Duration>, handle: F)
+where
+    F: FnMut(&mut DxSwarm, NodeEvent) -> bool,
+{
+    drive(swarm, tick, None, handle).await
+}
+
+/// What woke up a running node
+enum Wakeup {
+    Event(NodeEvent),
+    Command(Command),
+}
+
+/// Drive the swarm like [`run`], additionally executing the commands of
+/// [`NodeHandle`]s, if any, in between events
+async fn drive<F>(swarm: &mut DxSwarm, tick: Option<Duration>, mut commands: Option<&mut mpsc::UnboundedReceiver<Command>>, mut handle: F)
+where
+    F: FnMut(&mut DxSwarm, NodeEvent) -> bool,
+{
+    let mut ticks = tick.map(Interval::new);
+
+    loop {
+        let wakeup = {
+            let event = swarm.next_event().map(|event| Wakeup::Event(NodeEvent::Swarm(event)));
+            let tick = match ticks.as_mut() {
+                Some(ticks) => ticks.next().map(|_| Wakeup::Event(NodeEvent::Tick)).left_future(),
+                None => future::pending().right_future(),
+            };
+            let command = match commands.as_mut() {
+                Some(commands) => commands.next().then(|command| match command {
+                    Some(command) => future::ready(Wakeup::Command(command)).left_future(),
+                    None => future::pending().right_future(),
+                }).left_future(),
+                None => future::pending().right_future(),
+            };
+            futures::pin_mut!(event, tick, command);
+
+            match future::select(event, future::select(tick, command)).await {
+                Either::Left((wakeup, _)) => wakeup,
+                Either::Right((Either::Left((wakeup, _)), _)) => wakeup,
+                Either::Right((Either::Right((wakeup, _)), _)) => wakeup,
+            }
+        };
+
+        match wakeup {
+            Wakeup::Event(event) => if !handle(swarm, event) {
+                return
+            },
+            Wakeup::Command(command) => command.execute(swarm),
+        }
+    }
+}
+
+/// Request sent by a [`NodeHandle`] to the task running the node
+enum Command {
+    PeerInfo(PeerId, oneshot::Sender<Option<PeerInfo>>),
+    AllPeerInfo(oneshot::Sender<Vec<PeerInfo>>),
+    StatusStats(PeerId, oneshot::Sender<Option<StatusStats>>),
+    RequestStatus(PeerId),
+    AddPeer(PeerId, Multiaddr),
+    AddTrustedPeer(PeerId),
+    RemovePeer(PeerId),
+    DialPeer(PeerId),
+}
+
+impl Command {
+    fn execute(self, swarm: &mut DxSwarm) {
+        // Senders that gave up waiting for a reply are of no concern
+        match self {
+            Command::PeerInfo(id, reply) => { let _ = reply.send(swarm.get_peer_info(&id)); },
+            Command::AllPeerInfo(reply) => { let _ = reply.send(swarm.all_peer_info()); },
+            Command::StatusStats(id, reply) => { let _ = reply.send(swarm.status_stats(&id)); },
+            Command::RequestStatus(id) => swarm.request_status(&id),
+            Command::AddPeer(id, addr) => swarm.add_peer_with_address(id, addr),
+            Command::AddTrustedPeer(id) => swarm.add_trusted_peer(id),
+            Command::RemovePeer(id) => swarm.remove_peer(&id),
+            Command::DialPeer(id) => swarm.dial_peer(id),
+        }
+    }
+}
+
+/// The node a [`NodeHandle`] belongs to is gone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NodeStopped;
+
+impl fmt::Display for NodeStopped {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Node stopped")
+    }
+}
+
+impl Error for NodeStopped {}
+
+/// Cloneable handle to query and control a [`DxNode`] from other tasks or
+/// threads while it runs.
+///
+/// The swarm is owned by the task running the node, so all requests are sent
+/// to it and executed in between events. They are only answered while the
+/// node is running, e.g. via [`DxNode::run`].
+#[derive(Clone)]
+pub struct NodeHandle {
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl fmt::Debug for NodeHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NodeHandle")
+            .field("stopped", &self.commands.is_closed())
+            .finish()
+    }
+}
+
+impl NodeHandle {
+    fn send(&self, command: Command) -> Result<(), NodeStopped> {
+        self.commands.unbounded_send(command).map_err(|_| NodeStopped)
+    }
+
+    async fn ask<T>(&self, command: impl FnOnce(oneshot::Sender<T>) -> Command) -> Result<T, NodeStopped> {
+        let (reply, answer) = oneshot::channel();
+        self.send(command(reply))?;
+        answer.await.map_err(|_| NodeStopped)
+    }
+
+    /// Current information about a watched peer
+    pub async fn peer_info(&self, id: PeerId) -> Result<Option<PeerInfo>, NodeStopped> {
+        self.ask(|reply| Command::PeerInfo(id, reply)).await
+    }
+
+    /// Current information about all watched peers
+    pub async fn all_peer_info(&self) -> Result<Vec<PeerInfo>, NodeStopped> {
+        self.ask(Command::AllPeerInfo).await
+    }
+
+    /// Statistics of the status exchange with a connected peer
+    pub async fn status_stats(&self, id: PeerId) -> Result<Option<StatusStats>, NodeStopped> {
+        self.ask(|reply| Command::StatusStats(id, reply)).await
+    }
+
+    /// Exchange statuses with a connected peer right away, see
+    /// [`Behaviour::request_status`]
+    pub fn request_status(&self, id: PeerId) -> Result<(), NodeStopped> {
+        self.send(Command::RequestStatus(id))
+    }
+
+    /// Watch a peer at the given address, see [`Behaviour::add_peer_with_address`]
+    pub fn add_peer_with_address(&self, id: PeerId, addr: Multiaddr) -> Result<(), NodeStopped> {
+        self.send(Command::AddPeer(id, addr))
+    }
+
+    /// Watch a trusted peer, see [`Behaviour::add_trusted_peer`]
+    pub fn add_trusted_peer(&self, id: PeerId) -> Result<(), NodeStopped> {
+        self.send(Command::AddTrustedPeer(id))
+    }
+
+    /// Stop watching a peer, see [`Behaviour::remove_peer`]
+    pub fn remove_peer(&self, id: PeerId) -> Result<(), NodeStopped> {
+        self.send(Command::RemovePeer(id))
+    }
+
+    /// Connect to a peer, see [`Behaviour::dial_peer`]
+    pub fn dial_peer(&self, id: PeerId) -> Result<(), NodeStopped> {
+        self.send(Command::DialPeer(id))
+    }
+}
+
+/// How often readiness is checked while waiting for it, in case it changes
+/// without reporting an event, e.g. when bootstrapping completes
+const READY_CHECK: Duration = Duration::from_millis(50);
+
+/// How long [`DxNode::shutdown`] waits for connections to close
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Readiness of a node, see [`DxNode::wait_ready`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Readiness {
+    /// Whether the node listens on any address
+    pub listening: bool,
+    /// Whether Kademlia has bootstrapped
+    pub bootstrapped: bool,
+    /// Whether a trusted peer is connected, trivially so without any
+    pub connected: bool,
+}
+
+impl Readiness {
+    /// Current readiness of the swarm
+    pub fn of(swarm: &DxSwarm) -> Self {
+        let peers = swarm.all_peer_info();
+
+        Readiness {
+            listening: Swarm::listeners(swarm).next().is_some(),
+            bootstrapped: swarm.is_bootstrapped(),
+            connected: !peers.iter().any(|info| info.trusted()) ||
+                peers.iter().any(|info| info.trusted() && info.online()),
+        }
+    }
+
+    /// Whether the node is usable
+    pub fn is_ready(&self) -> bool {
+        self.listening && self.bootstrapped && self.connected
+    }
+}
+
+impl fmt::Display for Readiness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_ready() {
+            return f.write_str("Node is ready")
+        }
+
+        let missing: Vec<&str> = [
+            (self.listening, "not listening"),
+            (self.bootstrapped, "not bootstrapped"),
+            (self.connected, "no trusted peer connected"),
+        ].iter().filter(|(done, _)| !done).map(|(_, what)| *what).collect();
+
+        write!(f, "Node not ready: {}", missing.join(", "))
+    }
+}
+
+impl Error for Readiness {}
+
+/// Number of events buffered for each consumer of [`DxNode::events`]
+pub const EVENT_BUFFER: usize = 64;
+
+/// Handle of a subscription to the events of a [`DxNode`], to end it early
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+/// Consumer of behaviour events
+struct Subscriber {
+    id: SubscriptionId,
+    /// Peers whose events are wanted, all events if `None`
+    peers: Option<HashSet<PeerId>>,
+    sender: mpsc::Sender<BehaviourEvent>,
+}
+
+impl Subscriber {
+    fn wants(&self, event: &BehaviourEvent) -> bool {
+        match &self.peers {
+            Some(peers) => event.peer().map_or(false, |peer| peers.contains(peer)),
+            None => true,
+        }
+    }
+}
+
+/// Node driving a swarm, allowing async consumers to subscribe to its events
+pub struct DxNode {
+    swarm: DxSwarm,
+    /// Channels of all consumers of behaviour events
+    subscribers: Vec<Subscriber>,
+    /// Id of the next subscription
+    next_id: u64,
+    /// Requests of all handles of the node
+    commands: mpsc::UnboundedReceiver<Command>,
+    /// Kept to hand out further handles
+    handle: NodeHandle,
+    /// Listeners started via [`DxNode::listen_on`]
+    listeners: Vec<ListenerId>,
+    /// Address book saved on shutdown, along with where to
+    book: Option<(AddressBook, PathBuf)>,
+    /// Whether the node has been shut down
+    stopped: bool,
+}
+
+impl DxNode {
+    /// Node driving the given swarm, which should not listen yet, as only
+    /// listeners started via [`DxNode::listen_on`] are stopped on shutdown.
+    pub fn new(swarm: DxSwarm) -> Self {
+        if Swarm::listeners(&swarm).next().is_some() {
+            warn!("Swarm already listens, these listeners are not stopped on shutdown");
+        }
+
+        let (sender, commands) = mpsc::unbounded();
+        let handle = NodeHandle { commands: sender };
+
+        DxNode { swarm, subscribers: Vec::new(), next_id: 0, commands, handle, listeners: Vec::new(), book: None, stopped: false }
+    }
+
+    /// Save the addresses of watched peers to the address book at the given
+    /// path once the node is shut down, see [`DxNode::shutdown`]
+    pub fn with_address_book(mut self, book: AddressBook, path: PathBuf) -> Self {
+        self.book = Some((book, path));
+        self
+    }
+
+    /// Listen on the given address until the node is shut down
+    pub fn listen_on(&mut self, addr: Multiaddr) -> Result<ListenerId, TransportError<io::Error>> {
+        let id = Swarm::listen_on(&mut self.swarm, addr)?;
+        self.listeners.push(id);
+        Ok(id)
+    }
+
+    /// Handle to query and control the node from other tasks or threads
+    pub fn handle(&self) -> NodeHandle {
+        self.handle.clone()
+    }
+
+    /// Access the underlying swarm, e.g. to dial.
+    ///
+    /// Listen via [`DxNode::listen_on`] instead, the swarm does not tell the
+    /// listeners started on it directly, so they can not be stopped by
+    /// [`DxNode::shutdown`].
+    pub fn swarm(&mut self) -> &mut DxSwarm {
+        &mut self.swarm
+    }
+
+    /// Stream of all behaviour events reported while the node is running.
+    ///
+    /// Up to [`EVENT_BUFFER`] events are buffered for each stream. If a
+    /// consumer does not keep up, further events are dropped for it instead
+    /// of stalling the node. The stream ends once the node is dropped.
+    pub fn events(&mut self) -> impl Stream<Item = BehaviourEvent> {
+        self.subscribe(None).1
+    }
+
+    /// Stream of the behaviour events concerning any of the given peers,
+    /// buffered like [`DxNode::events`].
+    ///
+    /// Events not about a single peer, e.g. [`BehaviourEvent::EventsDropped`],
+    /// are not included. The stream ends once it is passed to [`DxNode::unsubscribe`].
+    pub fn peer_events(&mut self, peers: impl IntoIterator<Item = PeerId>) -> (SubscriptionId, impl Stream<Item = BehaviourEvent>) {
+        self.subscribe(Some(peers.into_iter().collect()))
+    }
+
+    /// End the given subscription, no further events are sent to it
+    pub fn unsubscribe(&mut self, id: SubscriptionId) {
+        self.subscribers.retain(|subscriber| subscriber.id != id);
+    }
+
+    fn subscribe(&mut self, peers: Option<HashSet<PeerId>>) -> (SubscriptionId, mpsc::Receiver<BehaviourEvent>) {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+
+        let (sender, receiver) = mpsc::channel(EVENT_BUFFER);
+        self.subscribers.push(Subscriber { id, peers, sender });
+        (id, receiver)
+    }
+
+    /// Drive the node until it is usable, i.e. listens, has bootstrapped
+    /// Kademlia and is connected to at least one trusted peer, if it has any.
+    ///
+    /// Fails with the readiness reached so far if that takes longer than the
+    /// timeout. The event streams are fed meanwhile.
+    pub async fn wait_ready(&mut self, timeout: Duration) -> Result<(), Readiness> {
+        let ready = {
+            let run = self.run(Some(READY_CHECK), |swarm, _| !Readiness::of(swarm).is_ready());
+            let deadline = Delay::new(timeout);
+            futures::pin_mut!(run);
+
+            match future::select(run, deadline).await {
+                Either::Left(_) => true,
+                Either::Right(_) => false,
+            }
+        };
+
+        let readiness = Readiness::of(&self.swarm);
+        if ready || readiness.is_ready() {
+            Ok(())
+        } else {
+            Err(readiness)
+        }
+    }
+
+    /// Stop the node for good, returning once it is fully stopped.
+    ///
+    /// Stops all listeners, drains the behaviour and closes all connections,
+    /// waiting a few seconds at most. Then the address book is saved, if any,
+    /// all event streams end and handles fail with [`NodeStopped`]. Running
+    /// the node or shutting it down again does nothing afterwards.
+    pub async fn shutdown(&mut self) -> io::Result<()> {
+        if self.stopped {
+            return Ok(())
+        }
+        self.stopped = true;
+
+        for id in self.listeners.drain(..) {
+            let _ = Swarm::remove_listener(&mut self.swarm, id);
+        }
+        for addr in Swarm::listeners(&self.swarm) {
+            warn!("Still listening on {} after shutdown, started without DxNode::listen_on", addr);
+        }
+
+        self.swarm.begin_drain();
+        for peer in self.swarm.connected_peers() {
+            self.swarm.disconnect(&peer);
+        }
+
+        if !self.swarm.connected_peers().is_empty() {
+            let run = drive(&mut self.swarm, Some(READY_CHECK), None, |swarm, _| !swarm.connected_peers().is_empty());
+            let deadline = Delay::new(SHUTDOWN_TIMEOUT);
+            futures::pin_mut!(run);
+
+            if let Either::Right(_) = future::select(run, deadline).await {
+                warn!("Connections still open after {:?}, stopping anyway", SHUTDOWN_TIMEOUT);
+            }
+        }
+
+        self.commands.close();
+        self.subscribers.clear();
+
+        match self.book.as_mut() {
+            Some((book, path)) => {
+                book.update(&mut self.swarm);
+                book.save(path)
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Drive the node like [`run`], additionally feeding the event streams
+    /// and answering the requests of its handles
+    ///
+    /// Returns right away once the node has been shut down.
+    pub async fn run<F>(&mut self, tick: Option<Duration>, mut handle: F)
+    where
+        F: FnMut(&mut DxSwarm, NodeEvent) -> bool,
+    {
+        if self.stopped {
+            return
+        }
+
+        let DxNode { swarm, subscribers, commands, .. } = self;
+
+        drive(swarm, tick, Some(commands), |swarm, event| {
+            if let NodeEvent::Swarm(SwarmEvent::Behaviour(event)) = &event {
+                // Forget about consumers that dropped their stream
+                subscribers.retain(|subscriber| !subscriber.sender.is_closed());
+
+                for subscriber in subscribers.iter_mut().filter(|subscriber| subscriber.wants(event)) {
+                    // Consumer is lagging behind if this fails, drop event
+                    let _ = subscriber.sender.try_send(event.clone());
+                }
+            }
+
+            handle(swarm, event)
+        }).await
+    }
+}
+
+/// Several nodes in one process, each running as a different identity of
+/// the trust store, e.g. to simulate a small network
+pub struct MultiNode {
+    nodes: Vec<(String, DxNode)>,
+}
+
+impl MultiNode {
+    /// Create a node for each of the named identities, watching all other
+    /// identities of the store as trusted peers.
+    ///
+    /// The node of every identity is set up by `build`, which e.g. picks its
+    /// transport and listens via [`DxNode::listen_on`]. Returns `None` if any
+    /// of the names is not in the store.
+    pub fn new<F>(store: &TrustStore, names: &[&str], mut build: F) -> Option<Self>
+    where
+        F: FnMut(&TrustedIdentity) -> DxNode,
+    {
+        let mut nodes = Vec::new();
+
+        for name in names {
+            let identity = store.find(name)?;
+            let mut node = build(identity);
+
+            for other in store.ids.iter() {
+                if other.name != identity.name {
+                    node.swarm().add_trusted_peer(other.id());
+                }
+            }
+
+            nodes.push((identity.name.clone(), node));
+        }
+
+        Some(MultiNode { nodes })
+    }
+
+    /// Names of all identities, in the order they were given
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.nodes.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Access the node running as the named identity
+    pub fn node(&mut self, name: &str) -> Option<&mut DxNode> {
+        self.nodes.iter_mut().find(|(n, _)| n == name).map(|(_, node)| node)
+    }
+
+    /// Stream of the behaviour events of all nodes, tagged with the name of
+    /// the identity reporting them, see [`DxNode::events`]
+    pub fn events(&mut self) -> impl Stream<Item = (String, BehaviourEvent)> {
+        stream::select_all(self.nodes.iter_mut().map(|(name, node)| {
+            let name = name.clone();
+            node.events().map(move |event| (name.clone(), event)).boxed()
+        }))
+    }
+
+    /// Drive all nodes, passing every event to the handler together with the
+    /// name of the identity it occurred for, until the handler returns `false`
+    pub async fn run<F>(&mut self, tick: Option<Duration>, handle: F)
+    where
+        F: FnMut(&str, &mut DxSwarm, NodeEvent) -> bool,
+    {
+        if self.nodes.is_empty() {
+            return
+        }
+
+        // Nodes are all polled from this task, so never handle two events at once
+        let handle = RefCell::new(handle);
+        let handle = &handle;
+
+        let runs = self.nodes.iter_mut().map(|&mut (ref name, ref mut node)| {
+            let name = name.as_str();
+            node.run(tick, move |swarm, event| (&mut *handle.borrow_mut())(name, swarm, event)).boxed_local()
+        });
+
+        future::select_all(runs).await;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use crate::behaviour::BehaviourConfig;
+    use crate::dht::KademliaMode;
+    use crate::status::{StatusConfig, generate_payload};
+
+    use libp2p::{
+        Multiaddr,
+        core::{
+            multiaddr::{multiaddr, Protocol},
+            transport::{TransportError, memory::MemoryTransport},
+        },
+    };
+    use rand::{thread_rng, Rng};
+    use std::cell::Cell;
+    use std::collections::{HashMap, HashSet};
+    use std::sync::Arc;
+
+    /// Memory transport authenticated with the given key
+    pub fn memory_transport(key: Keypair) -> DxTransport {
+        boxed_transport(MemoryTransport
+            .upgrade(upgrade::Version::V1)
+            .authenticate(SecioConfig::new(key))
+            .multiplex(MplexConfig::new()))
+    }
+
+    /// Swarm over memory transport without any discovery
+    pub fn memory_swarm(config: impl FnOnce(BehaviourConfig) -> BehaviourConfig) -> DxSwarm {
+        let key = Keypair::generate_ed25519();
+        let id = key.public().into_peer_id();
+
+        let base = BehaviourConfig::new(StatusConfig::new(generate_payload()))
+            .with_mdns(false)
+            .with_bootstrap(Vec::new());
+
+        Swarm::new(memory_transport(key), Behaviour::with_config(id.clone(), config(base)), id)
+    }
+
+    pub fn memory_addr() -> Multiaddr {
+        multiaddr![Memory(thread_rng().gen::<u64>())]
+    }
+
+    /// Time after which tests waiting on the network fail instead of hanging
+    pub const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Run the test to completion while driving the nodes, failing if the
+    /// nodes stop first or the test takes longer than [`TEST_TIMEOUT`]
+    pub fn race<T>(test: impl Future<Output = T>, drive: impl Future) -> T {
+        async_std::task::block_on(async {
+            let deadline = Delay::new(TEST_TIMEOUT);
+            futures::pin_mut!(test, drive);
+
+            match future::select(test, future::select(drive, deadline)).await {
+                Either::Left((output, _)) => output,
+                Either::Right((Either::Left(_), _)) => panic!("Nodes stopped"),
+                Either::Right((Either::Right(_), _)) => panic!("Timed out after {:?}", TEST_TIMEOUT),
+            }
+        })
+    }
+
+    /// Run the test to completion, failing if it takes longer than [`TEST_TIMEOUT`]
+    pub fn timeout<T>(test: impl Future<Output = T>) -> T {
+        race(test, future::pending::<()>())
+    }
+
+    /// Wait for the first event the filter picks something out of, skipping all others
+    pub async fn find_event<E, T>(events: &mut (impl Stream<Item = E> + Unpin), mut filter: impl FnMut(E) -> Option<T>) -> T {
+        loop {
+            let event = events.next().await.expect("Events ended");
+            if let Some(found) = filter(event) {
+                return found
+            }
+        }
+    }
+
+    #[test]
+    fn report_late_listener() {
+        let mut swarm = memory_swarm(|c| c);
+
+        let first = memory_addr();
+        let second = memory_addr();
+        Swarm::listen_on(&mut swarm, first.clone()).unwrap();
+
+        let mut reported = Vec::new();
+        timeout(run(&mut swarm, None, |swarm, event| {
+            if let NodeEvent::Swarm(SwarmEvent::NewListenAddr(addr)) = event {
+                // Start listening on another address after the first one is up
+                if addr == first {
+                    Swarm::listen_on(swarm, second.clone()).unwrap();
+                }
+                reported.push(addr);
+            }
+            reported.len() < 2
+        }));
+
+        assert_eq!(reported, vec![first, second]);
+    }
+
+    #[test]
+    fn track_listen_addresses() {
+        let mut swarm = memory_swarm(|c| c);
+
+        let addr = memory_addr();
+        Swarm::listen_on(&mut swarm, addr.clone()).unwrap();
+
+        timeout(run(&mut swarm, None, |_, event| {
+            match event {
+                NodeEvent::Swarm(SwarmEvent::NewListenAddr(_)) => false,
+                _ => true,
+            }
+        }));
+
+        assert_eq!(swarm.listen_addresses(), vec![addr]);
+    }
+
+    #[test]
+    fn stream_events() {
+        let mut b = DxNode::new(memory_swarm(|c| c));
+        let b_id = Swarm::local_peer_id(b.swarm()).clone();
+        let b_addr = memory_addr();
+        b.listen_on(b_addr.clone()).unwrap();
+
+        // Node A watches B and finds it via bootstrapping
+        let mut a = DxNode::new(memory_swarm(|c| c.with_bootstrap(vec![(b_id.clone(), b_addr)])));
+        a.swarm().add_peers(b_id.clone());
+
+        let mut events = a.events();
+
+        let consume = async {
+            let online = find_event(&mut events, |event| match event {
+                BehaviourEvent::PeerOnline { peer } => Some(peer),
+                _ => None,
+            }).await;
+            assert_eq!(online, b_id);
+
+            let negotiated = find_event(&mut events, |event| match event {
+                BehaviourEvent::ProtocolNegotiated { peer, .. } => Some(peer),
+                _ => None,
+            }).await;
+            assert_eq!(negotiated, b_id);
+        };
+
+        race(consume, future::join(a.run(None, |_, _| true), b.run(None, |_, _| true)));
+    }
+
+    #[test]
+    fn dial_known_address() {
+        let mut b = DxNode::new(memory_swarm(|c| c));
+        let b_id = Swarm::local_peer_id(b.swarm()).clone();
+        let b_addr = memory_addr();
+        b.listen_on(b_addr.clone()).unwrap();
+
+        // Node A knows where B is, without any DHT to look it up in
+        let mut a = DxNode::new(memory_swarm(|c| c));
+        a.swarm().add_peer_with_address(b_id.clone(), b_addr);
+        a.swarm().dial_peer(b_id.clone());
+
+        let mut events = a.events();
+
+        let online = find_event(&mut events, |event| match event {
+            BehaviourEvent::PeerOnline { peer } => Some(peer),
+            _ => None,
+        });
+
+        assert_eq!(race(online, future::join(a.run(None, |_, _| true), b.run(None, |_, _| true))), b_id);
+    }
+
+    #[test]
+    fn subscribe_to_peer() {
+        let mut b = DxNode::new(memory_swarm(|c| c));
+        let b_id = Swarm::local_peer_id(b.swarm()).clone();
+        let b_addr = memory_addr();
+        b.listen_on(b_addr.clone()).unwrap();
+
+        let mut c = DxNode::new(memory_swarm(|c| c));
+        let c_id = Swarm::local_peer_id(c.swarm()).clone();
+        let c_addr = memory_addr();
+        c.listen_on(c_addr.clone()).unwrap();
+
+        // Node A watches both, but is only interested in C
+        let mut a = DxNode::new(memory_swarm(|c| c));
+        a.swarm().add_peer_with_address(b_id.clone(), b_addr);
+        a.swarm().add_peer_with_address(c_id.clone(), c_addr);
+        a.swarm().dial_peer(b_id.clone());
+        a.swarm().dial_peer(c_id.clone());
+
+        let (_, mut events) = a.peer_events(vec![c_id.clone()]);
+
+        // Ended subscriptions receive nothing at all
+        let (id, mut ended) = a.peer_events(vec![b_id.clone()]);
+        a.unsubscribe(id);
+        assert!(timeout(ended.next()).is_none());
+
+        let online = find_event(&mut events, |event| {
+            assert_eq!(event.peer(), Some(&c_id));
+            match event {
+                BehaviourEvent::PeerOnline { .. } => Some(()),
+                _ => None,
+            }
+        });
+
+        race(online, future::join3(a.run(None, |_, _| true), b.run(None, |_, _| true), c.run(None, |_, _| true)));
+    }
+
+    #[test]
+    fn status_without_kademlia() {
+        let status_only = |c: BehaviourConfig| c
+            .with_kademlia_mode(KademliaMode::Disabled)
+            .with_bootstrap(vec![(PeerId::random(), memory_addr())]);
+
+        let mut b = DxNode::new(memory_swarm(status_only));
+        let b_id = Swarm::local_peer_id(b.swarm()).clone();
+        let b_addr = memory_addr();
+        b.listen_on(b_addr.clone()).unwrap();
+
+        // Node A knows where B is, so it needs no lookup
+        let mut a = DxNode::new(memory_swarm(status_only));
+        a.swarm().add_peer_with_address(b_id.clone(), b_addr);
+        a.swarm().dial_peer(b_id.clone());
+
+        let exchange = a.run(None, |swarm, _| swarm.status_stats(&b_id).map_or(0, |stats| stats.received) == 0);
+        race(exchange, b.run(None, |_, _| true));
+
+        // Neither bootstrapped nor looked up any peer
+        for node in [&mut a, &mut b].iter_mut() {
+            assert_eq!(node.swarm().dht().queries(), 0);
+            assert!(node.swarm().is_bootstrapped());
+        }
+    }
+
+    #[test]
+    fn wait_until_ready() {
+        let mut b = DxNode::new(memory_swarm(|c| c));
+        let b_id = Swarm::local_peer_id(b.swarm()).clone();
+        let b_addr = memory_addr();
+        b.listen_on(b_addr.clone()).unwrap();
+
+        // Node A bootstraps from B, which it also trusts
+        let mut a = DxNode::new(memory_swarm(|c| c.with_bootstrap(vec![(b_id.clone(), b_addr)])));
+        a.listen_on(memory_addr()).unwrap();
+        a.swarm().add_trusted_peer(b_id.clone());
+        a.swarm().dial_peer(b_id.clone());
+        assert!(!Readiness::of(a.swarm()).is_ready());
+
+        let ready = race(a.wait_ready(Duration::from_secs(5)), b.run(None, |_, _| true));
+        assert_eq!(ready, Ok(()));
+
+        // Trusted peer that is nowhere to be found
+        let mut lonely = DxNode::new(memory_swarm(|c| c));
+        lonely.listen_on(memory_addr()).unwrap();
+        lonely.swarm().add_trusted_peer(PeerId::random());
+
+        let error = timeout(lonely.wait_ready(Duration::from_millis(200))).unwrap_err();
+        assert_eq!(error, Readiness { listening: true, bootstrapped: true, connected: false });
+        assert_eq!(error.to_string(), "Node not ready: no trusted peer connected");
+    }
+
+    #[test]
+    fn query_through_handle() {
+        let mut b = DxNode::new(memory_swarm(|c| c));
+        let b_id = Swarm::local_peer_id(b.swarm()).clone();
+        let b_addr = memory_addr();
+        b.listen_on(b_addr.clone()).unwrap();
+
+        let mut a = DxNode::new(memory_swarm(|c| c));
+        let handle = a.handle();
+
+        // Another task controls node A while it runs
+        let query = async_std::task::spawn(async move {
+            let received = |handle: NodeHandle, id: PeerId| async move {
+                handle.status_stats(id).await.unwrap().map_or(0, |stats| stats.received)
+            };
+
+            handle.add_peer_with_address(b_id.clone(), b_addr).unwrap();
+            handle.dial_peer(b_id.clone()).unwrap();
+            while received(handle.clone(), b_id.clone()).await == 0 {
+                Delay::new(Duration::from_millis(10)).await.unwrap();
+            }
+
+            let info = handle.peer_info(b_id.clone()).await.unwrap().expect("Peer unknown");
+            assert!(info.online());
+            assert!(info.status().is_some());
+
+            // Forced exchange long before the next interval
+            handle.request_status(b_id.clone()).unwrap();
+            while received(handle.clone(), b_id.clone()).await == 1 {
+                Delay::new(Duration::from_millis(10)).await.unwrap();
+            }
+
+            handle.remove_peer(b_id.clone()).unwrap();
+            handle.all_peer_info().await.unwrap()
+        });
+
+        let peers = race(query, future::join(a.run(None, |_, _| true), b.run(None, |_, _| true)));
+        assert!(peers.is_empty());
+
+        // Handles notice once the node is gone
+        let handle = a.handle();
+        drop(a);
+        assert_eq!(handle.request_status(PeerId::random()), Err(NodeStopped));
+        assert_eq!(timeout(handle.all_peer_info()).err(), Some(NodeStopped));
+    }
+
+    #[test]
+    fn withhold_status_from_strangers() {
+        let swarm = |status: StatusConfig| {
+            let key = Keypair::generate_ed25519();
+            let id = key.public().into_peer_id();
+            let config = BehaviourConfig::new(status.with_interval(Duration::from_millis(100)))
+                .with_mdns(false)
+                .with_bootstrap(Vec::new());
+
+            DxNode::new(Swarm::new(memory_transport(key), Behaviour::with_config(id.clone(), config), id))
+        };
+
+        let payload = generate_payload();
+        let mut server = swarm(StatusConfig::new(payload).with_trusted_only(true));
+        let server_id = Swarm::local_peer_id(server.swarm()).clone();
+        let server_addr = memory_addr();
+        server.listen_on(server_addr.clone()).unwrap();
+
+        let mut friend = swarm(StatusConfig::new(generate_payload()));
+        let mut stranger = swarm(StatusConfig::new(generate_payload()));
+        server.swarm().add_trusted_peer(Swarm::local_peer_id(friend.swarm()).clone());
+
+        for client in [&mut friend, &mut stranger].iter_mut() {
+            client.swarm().add_peer_with_address(server_id.clone(), server_addr.clone());
+            client.swarm().dial_peer(server_id.clone());
+        }
+
+        let stranger_id = Swarm::local_peer_id(stranger.swarm()).clone();
+        let status_of = |swarm: &mut DxSwarm| swarm.get_peer_info(&server_id).and_then(|info| info.status().cloned());
+        let withholds = |swarm: &mut DxSwarm| swarm.get_peer_info(&server_id).map_or(false, |info| info.withholds_status());
+
+        // All nodes keep running until the server withheld its status and
+        // both clients got a response
+        let (withheld, befriended, estranged) = (Cell::new(false), Cell::new(false), Cell::new(false));
+        let done = || withheld.get() && befriended.get() && estranged.get();
+
+        timeout(future::join3(
+            server.run(None, |_, event| {
+                if let NodeEvent::Swarm(SwarmEvent::Behaviour(BehaviourEvent::StatusWithheld { peer })) = event {
+                    assert_eq!(peer, stranger_id);
+                    withheld.set(true);
+                }
+                !done()
+            }),
+            friend.run(None, |swarm, _| {
+                befriended.set(befriended.get() || status_of(swarm) == Some(payload));
+                !done()
+            }),
+            stranger.run(None, |swarm, _| {
+                assert!(status_of(swarm).is_none());
+                estranged.set(estranged.get() || withholds(swarm));
+                !done()
+            }),
+        ));
+    }
+
+    #[test]
+    fn report_security() {
+        let mut b = DxNode::new(memory_swarm(|c| c));
+        let b_id = Swarm::local_peer_id(b.swarm()).clone();
+        let b_addr = memory_addr();
+        b.listen_on(b_addr.clone()).unwrap();
+
+        // Node A records the security protocol of its connections
+        let key = Keypair::generate_ed25519();
+        let id = key.public().into_peer_id();
+        let table = SecurityTable::default();
+        let transport = boxed_transport(MemoryTransport
+            .upgrade(upgrade::Version::V1)
+            .authenticate(RecordSecurity::new(SecioConfig::new(key), table.clone()))
+            .multiplex(MplexConfig::new()));
+
+        let config = BehaviourConfig::new(StatusConfig::new(generate_payload()))
+            .with_mdns(false)
+            .with_bootstrap(Vec::new())
+            .with_security_table(table.clone());
+        let mut a = DxNode::new(Swarm::new(transport, Behaviour::with_config(id.clone(), config), id));
+        a.swarm().add_peer_with_address(b_id.clone(), b_addr);
+        a.swarm().dial_peer(b_id.clone());
+
+        let mut events = a.events();
+
+        let negotiated = find_event(&mut events, |event| match event {
+            BehaviourEvent::SecurityNegotiated { peer, security } => Some((peer, security)),
+            _ => None,
+        });
+
+        let (peer, security) = race(negotiated, future::join(a.run(None, |_, _| true), b.run(None, |_, _| true)));
+        assert_eq!(peer, b_id);
+        assert_eq!(security, "/secio/1.0.0");
+
+        let info = a.swarm().get_peer_info(&b_id).unwrap();
+        assert_eq!(info.security(), Some("/secio/1.0.0"));
+    }
+
+    #[test]
+    fn shutdown_node() {
+        let mut b = DxNode::new(memory_swarm(|c| c));
+        let b_id = Swarm::local_peer_id(b.swarm()).clone();
+        let b_addr = memory_addr();
+        b.listen_on(b_addr.clone()).unwrap();
+
+        let path = std::env::temp_dir().join(format!("dx-test-{}.toml", thread_rng().gen::<u64>()));
+        let mut a = DxNode::new(memory_swarm(|c| c)).with_address_book(AddressBook::default(), path.clone());
+        let a_addr = memory_addr();
+        a.listen_on(a_addr.clone()).unwrap();
+        a.listen_on(memory_addr()).unwrap();
+        a.swarm().add_peer_with_address(b_id.clone(), b_addr.clone());
+        a.swarm().dial_peer(b_id.clone());
+        let handle = a.handle();
+
+        // Connect, then shut down while the other side keeps running
+        race(a.run(None, |_, event| match event {
+            NodeEvent::Swarm(SwarmEvent::Behaviour(BehaviourEvent::PeerOnline { .. })) => false,
+            _ => true,
+        }), b.run(None, |_, _| true));
+
+        race(a.shutdown(), b.run(None, |_, _| true)).unwrap();
+
+        // Listeners and connections are gone and the address book is flushed
+        assert_eq!(Swarm::listeners(a.swarm()).count(), 0);
+        assert!(a.swarm().connected_peers().is_empty());
+        let book = AddressBook::load(&path, Duration::from_secs(60)).unwrap();
+        assert_eq!(book.addresses(&b_id), vec![&b_addr]);
+        assert_eq!(handle.dial_peer(b_id.clone()), Err(NodeStopped));
+
+        // Nothing happens the second time around
+        std::fs::remove_file(&path).unwrap();
+        async_std::task::block_on(a.shutdown()).unwrap();
+        assert!(!path.exists());
+
+        // Nobody can connect anymore
+        let mut c = DxNode::new(memory_swarm(|c| c));
+        let a_id = Swarm::local_peer_id(a.swarm()).clone();
+        c.swarm().add_peer_with_address(a_id.clone(), a_addr);
+        c.swarm().dial_peer(a_id);
+
+        let mut failed = false;
+        timeout(c.run(None, |_, event| match event {
+            NodeEvent::Swarm(SwarmEvent::Behaviour(BehaviourEvent::DialFailed { .. })) => {
+                failed = true;
+                false
+            },
+            NodeEvent::Swarm(SwarmEvent::Behaviour(BehaviourEvent::PeerOnline { .. })) => panic!("Connected to stopped node"),
+            _ => true,
+        }));
+        assert!(failed);
+    }
+
+    #[test]
+    fn disconnect_peer() {
+        let mut b = DxNode::new(memory_swarm(|c| c));
+        let b_id = Swarm::local_peer_id(b.swarm()).clone();
+        let b_addr = memory_addr();
+        b.listen_on(b_addr.clone()).unwrap();
+
+        let mut a = DxNode::new(memory_swarm(|c| c));
+        a.swarm().add_peer_with_address(b_id.clone(), b_addr);
+        a.swarm().dial_peer(b_id.clone());
+
+        // Sever the link as soon as it is up, then wait for it to go down
+        let drive_a = a.run(None, |swarm, event| match event {
+            NodeEvent::Swarm(SwarmEvent::Behaviour(BehaviourEvent::PeerOnline { peer })) => {
+                assert_eq!(swarm.connected_peers(), vec![peer.clone()]);
+                swarm.disconnect(&peer);
+                true
+            },
+            NodeEvent::Swarm(SwarmEvent::Behaviour(BehaviourEvent::PeerOffline { .. })) => false,
+            _ => true,
+        });
+
+        race(drive_a, b.run(None, |_, _| true));
+
+        assert!(a.swarm().connected_peers().is_empty());
+        assert!(!a.swarm().get_peer_info(&b_id).unwrap().online());
+    }
+
+    #[test]
+    fn resolve_dns_addresses() {
+        // Without DNS, hostnames are not even dialed
+        let addr: Multiaddr = "/dns4/localhost/tcp/4001".parse().unwrap();
+        match development_transport(Keypair::generate_ed25519(), false, SecurityTable::default()).unwrap().dial(addr) {
+            Err(TransportError::MultiaddrNotSupported(_)) => (),
+            _ => panic!("Dialed hostname without DNS"),
+        }
+
+        let tcp_swarm = || {
+            let key = Keypair::generate_ed25519();
+            let id = key.public().into_peer_id();
+            let config = BehaviourConfig::new(StatusConfig::new(generate_payload()))
+                .with_mdns(false)
+                .with_bootstrap(Vec::new());
+
+            Swarm::new(development_transport(key, true, SecurityTable::default()).unwrap(), Behaviour::with_config(id.clone(), config), id)
+        };
+
+        let mut b = DxNode::new(tcp_swarm());
+        let b_id = Swarm::local_peer_id(b.swarm()).clone();
+        b.listen_on("/ip4/127.0.0.1/tcp/0".parse().unwrap()).unwrap();
+
+        let mut port = None;
+        timeout(run(b.swarm(), None, |_, event| {
+            if let NodeEvent::Swarm(SwarmEvent::NewListenAddr(addr)) = event {
+                port = addr.iter().filter_map(|p| match p {
+                    Protocol::Tcp(port) => Some(port),
+                    _ => None,
+                }).next();
+            }
+            port.is_none()
+        }));
+
+        // Node A only knows B by hostname
+        let b_addr = format!("/dns4/localhost/tcp/{}", port.unwrap()).parse().unwrap();
+        let mut a = DxNode::new(tcp_swarm());
+        a.swarm().add_peer_with_address(b_id.clone(), b_addr);
+        a.swarm().dial_peer(b_id.clone());
+
+        let mut events = a.events();
+
+        let online = find_event(&mut events, |event| match event {
+            BehaviourEvent::PeerOnline { peer } => Some(peer),
+            BehaviourEvent::DialFailed { error, .. } => panic!("Dial failed: {}", error),
+            _ => None,
+        });
+
+        assert_eq!(race(online, future::join(a.run(None, |_, _| true), b.run(None, |_, _| true))), b_id);
+    }
+
+    #[test]
+    fn run_status_nodes() {
+        let dir = std::env::temp_dir().join(format!("dx-test-{}", thread_rng().gen::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        TrustedIdentity::new("alice".into(), &dir);
+        TrustedIdentity::new("bob".into(), &dir);
+        let store = TrustStore::load_at(&dir);
+        let addrs: HashMap<PeerId, Multiaddr> = store.ids.iter().map(|i| (i.id(), memory_addr())).collect();
+        let mut payloads = HashMap::new();
+
+        // Set up like dxstatus, only over memory transport and knowing where the other is
+        let mut multi = MultiNode::new(&store, &["alice", "bob"], |identity| {
+            let id = identity.id();
+            let others: Vec<(PeerId, Multiaddr)> = addrs.iter()
+                .filter(|(other, _)| **other != id)
+                .map(|(other, addr)| (other.clone(), addr.clone()))
+                .collect();
+
+            let config = Config {
+                peers: others.clone(),
+                bootstrap: others,
+                interval: Some(Duration::from_secs(1)),
+                ..Config::default()
+            };
+
+            let payload = generate_payload();
+            payloads.insert(identity.name.clone(), payload);
+
+            let transport = memory_transport(identity.key().unwrap());
+            let swarm = status_swarm(transport, SecurityTable::default(), &store, &identity.name, payload, &config, &AddressBook::default()).unwrap();
+            let mut node = DxNode::new(swarm);
+            node.listen_on(addrs[&id].clone()).unwrap();
+            node
+        }).unwrap();
+
+        // Both keep exchanging signed statuses
+        let mut received = HashMap::new();
+        timeout(multi.run(Some(Duration::from_millis(100)), |name, swarm, _| {
+            let other = store.ids.iter().find(|other| other.name != name).unwrap().id();
+            let count = swarm.status_stats(&other).map_or(0, |stats| stats.received);
+            received.insert(name.to_owned(), count);
+
+            received.len() < 2 || received.values().any(|count| *count < 2)
+        }));
+
+        for (name, other) in [("alice", "bob"), ("bob", "alice")].iter() {
+            let other_id = store.find(other).unwrap().id();
+            let info = multi.node(name).unwrap().swarm().get_peer_info(&other_id).unwrap();
+            assert_eq!(info.status(), Some(&payloads[*other]));
+            assert_eq!(info.name(), Some(*other));
+            assert_eq!(info.security(), Some("/secio/1.0.0"));
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn status_with_external_signer() {
+        let dir = std::env::temp_dir().join(format!("dx-test-{}", thread_rng().gen::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        TrustedIdentity::new("alice".into(), &dir);
+        TrustedIdentity::new("bob".into(), &dir);
+
+        // Alice's private key is only held by her signer, not by the store
+        let key = TrustStore::load_at(&dir).local("alice").unwrap().key().unwrap();
+        std::fs::remove_file(dir.join("alice.key")).unwrap();
+        let mut store = TrustStore::load_at(&dir);
+        store.set_signer("alice", Arc::new(key.clone())).unwrap();
+        assert!(store.local("alice").unwrap().key().is_err());
+
+        let addrs: HashMap<PeerId, Multiaddr> = store.ids.iter().map(|i| (i.id(), memory_addr())).collect();
+
+        let mut multi = MultiNode::new(&store, &["alice", "bob"], |identity| {
+            let id = identity.id();
+            let others: Vec<(PeerId, Multiaddr)> = addrs.iter()
+                .filter(|(other, _)| **other != id)
+                .map(|(other, addr)| (other.clone(), addr.clone()))
+                .collect();
+
+            let config = Config {
+                peers: others,
+                interval: Some(Duration::from_secs(1)),
+                ..Config::default()
+            };
+
+            // The transport of alice is authenticated without the store
+            let transport = memory_transport(identity.key().unwrap_or_else(|_| key.clone()));
+            let swarm = status_swarm(transport, SecurityTable::default(), &store, &identity.name, generate_payload(), &config, &AddressBook::default()).unwrap();
+            let mut node = DxNode::new(swarm);
+            node.listen_on(addrs[&id].clone()).unwrap();
+            node
+        }).unwrap();
+
+        // Bob only accepts signed statuses, so these were signed by her signer
+        let alice = store.find("alice").unwrap().id();
+        timeout(multi.run(Some(Duration::from_millis(100)), |name, swarm, _| {
+            name != "bob" || swarm.status_stats(&alice).map_or(true, |stats| stats.received < 2)
+        }));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn multiple_identities() {
+        let dir = std::env::temp_dir().join(format!("dx-test-{}", thread_rng().gen::<u64>()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        TrustedIdentity::new("alice".into(), &dir);
+        TrustedIdentity::new("bob".into(), &dir);
+        let store = TrustStore::load_at(&dir);
+
+        // Every identity listens on a known address and bootstraps from the others
+        let addrs: HashMap<PeerId, Multiaddr> = store.ids.iter().map(|i| (i.id(), memory_addr())).collect();
+        let mut payloads = HashMap::new();
+
+        let mut multi = MultiNode::new(&store, &["alice", "bob"], |identity| {
+            let id = identity.id();
+            let bootstrap = addrs.iter()
+                .filter(|(other, _)| **other != id)
+                .map(|(other, addr)| (other.clone(), addr.clone()))
+                .collect();
+
+            let payload = generate_payload();
+            payloads.insert(identity.name.clone(), payload);
+
+            let config = BehaviourConfig::new(StatusConfig::new(payload))
+                .with_mdns(false)
+                .with_bootstrap(bootstrap);
+
+            let swarm = Swarm::new(memory_transport(identity.key().unwrap()), Behaviour::with_config(id.clone(), config), id.clone());
+            let mut node = DxNode::new(swarm);
+            node.listen_on(addrs[&id].clone()).unwrap();
+            node
+        }).unwrap();
+
+        assert_eq!(multi.names().collect::<Vec<_>>(), vec!["alice", "bob"]);
+
+        // Wait for both identities to receive the status of the other
+        let mut events = multi.events();
+        let consume = async {
+            let mut received = HashSet::new();
+            while received.len() < 2 {
+                received.insert(find_event(&mut events, |(name, event)| match event {
+                    BehaviourEvent::ProtocolNegotiated { .. } => Some(name),
+                    _ => None,
+                }).await);
+            }
+        };
+
+        race(consume, multi.run(None, |_, _, _| true));
+
+        for (name, other) in [("alice", "bob"), ("bob", "alice")].iter() {
+            let other_id = store.find(other).unwrap().id();
+            let info = multi.node(name).unwrap().swarm().get_peer_info(&other_id).unwrap();
+            assert_eq!(info.status(), Some(&payloads[*other]));
+        }
+    }
+}
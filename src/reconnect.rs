@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::status::StatusFailure;
+
+
+/// Backoff used when re-dialing trusted peers after losing the connection.
+///
+/// The first attempt is made immediately, subsequent attempts are delayed
+/// exponentially starting at `base` and capped at `max`. Every delay is
+/// randomly shortened by up to the `jitter` fraction, so that nodes losing
+/// the same peer at once spread their attempts instead of stampeding it.
+///
+/// Trusted peers are also re-dialed right away if a status exchange with them
+/// fails in a way that hints at a broken connection, see [`RedialPolicy`].
+#[derive(Debug, Clone)]
+pub struct ReconnectConfig {
+    base: Duration,
+    max: Duration,
+    jitter: f64,
+    policy: RedialPolicy,
+}
+
+impl Default for ReconnectConfig {
+    /// Creates a `ReconnectConfig` with a base delay of 1s, a maximum
+    /// delay of 5min, a jitter of 20% and the default [`RedialPolicy`].
+    fn default() -> Self {
+        ReconnectConfig {
+            base: Duration::from_secs(1),
+            max: Duration::from_secs(5 * 60),
+            jitter: 0.2,
+            policy: RedialPolicy::default(),
+        }
+    }
+}
+
+/// Failed status exchanges with a trusted peer that make it be re-dialed
+/// immediately, instead of waiting for the connection to be closed.
+///
+/// Failures that say nothing about the connection, like the peer not
+/// supporting the protocol, never do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RedialPolicy {
+    /// Whether to re-dial after [`StatusFailure::ConnectionClosed`].
+    pub connection_closed: bool,
+    /// Whether to re-dial after [`StatusFailure::Timeout`] or [`StatusFailure::Stalled`].
+    pub timeout: bool,
+}
+
+impl Default for RedialPolicy {
+    /// Re-dials after both closed connections and timeouts.
+    fn default() -> Self {
+        RedialPolicy { connection_closed: true, timeout: true }
+    }
+}
+
+impl RedialPolicy {
+    /// Policy that never re-dials on failures.
+    pub fn never() -> Self {
+        RedialPolicy { connection_closed: false, timeout: false }
+    }
+
+    /// Whether the failure calls for re-dialing the peer.
+    pub fn applies(&self, failure: &StatusFailure) -> bool {
+        match failure {
+            StatusFailure::ConnectionClosed => self.connection_closed,
+            StatusFailure::Timeout | StatusFailure::Stalled { .. } => self.timeout,
+            _ => false,
+        }
+    }
+}
+
+impl ReconnectConfig {
+    /// Sets the delay before the second attempt.
+    pub fn with_base(mut self, d: Duration) -> Self {
+        self.base = d;
+        self
+    }
+
+    /// Sets the maximum delay between attempts.
+    pub fn with_max(mut self, d: Duration) -> Self {
+        self.max = d;
+        self
+    }
+
+    /// Sets the fraction (between 0 and 1) by which delays are randomly shortened.
+    pub fn with_jitter(mut self, fraction: f64) -> Self {
+        self.jitter = fraction.max(0.0).min(1.0);
+        self
+    }
+
+    /// Sets which failed status exchanges trigger an immediate re-dial.
+    pub fn with_policy(mut self, policy: RedialPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn policy(&self) -> RedialPolicy {
+        self.policy
+    }
+
+    /// Delay without jitter before the given attempt, counting from 0.
+    pub fn nominal_delay(&self, attempt: u32) -> Duration {
+        if attempt == 0 {
+            return Duration::new(0, 0)
+        }
+
+        1u32.checked_shl(attempt - 1)
+            .and_then(|factor| self.base.checked_mul(factor))
+            .map_or(self.max, |delay| delay.min(self.max))
+    }
+
+    /// Randomized delay before the given attempt, counting from 0.
+    pub fn delay(&self, attempt: u32, rng: &mut impl Rng) -> Duration {
+        let shorten = self.jitter * rng.gen::<f64>();
+        self.nominal_delay(attempt).mul_f64(1.0 - shorten)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReconnectConfig;
+    use rand::thread_rng;
+    use std::time::Duration;
+
+    #[test]
+    fn delays_grow_within_bounds() {
+        let config = ReconnectConfig::default()
+            .with_base(Duration::from_secs(1))
+            .with_max(Duration::from_secs(60))
+            .with_jitter(0.5);
+
+        let mut rng = thread_rng();
+
+        // First attempt is immediate
+        assert_eq!(config.delay(0, &mut rng), Duration::new(0, 0));
+
+        let mut last = Duration::new(0, 0);
+        for attempt in 1..20 {
+            let nominal = config.nominal_delay(attempt);
+            assert!(nominal >= last);
+            assert!(nominal <= Duration::from_secs(60));
+            last = nominal;
+
+            for _ in 0..10 {
+                let delay = config.delay(attempt, &mut rng);
+                assert!(delay <= nominal);
+                assert!(delay >= nominal / 2);
+            }
+        }
+
+        assert_eq!(config.nominal_delay(3), Duration::from_secs(4));
+        assert_eq!(last, Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use libp2p::core::PeerId;
+
+use super::protocol::Payload;
+
+
+/// Cached status of a single peer
+#[derive(Debug, Clone)]
+struct Entry {
+    payload: Payload,
+    /// When the status was (originally) received
+    seen: Instant,
+    /// Whether the status was received from the peer itself
+    direct: bool,
+    /// Value of the update counter at the last update, to find the least recent one
+    updated: u64,
+}
+
+/// Last known statuses of peers, both directly received and second-hand.
+///
+/// Directly received statuses are always kept, while only up to `capacity`
+/// second-hand statuses are, evicting the least recently updated ones first.
+#[derive(Debug, Clone)]
+pub struct StatusCache {
+    capacity: usize,
+    entries: HashMap<PeerId, Entry>,
+    /// Number of updates so far
+    updates: u64,
+    /// Number of second-hand entries
+    indirect: usize,
+}
+
+impl StatusCache {
+    /// Create empty cache holding up to `capacity` second-hand statuses
+    pub fn new(capacity: usize) -> Self {
+        StatusCache { capacity, entries: HashMap::new(), updates: 0, indirect: 0 }
+    }
+
+    /// Remember status received from the peer itself
+    pub fn insert_direct(&mut self, peer: PeerId, payload: Payload, seen: Instant) {
+        self.updates += 1;
+        let entry = Entry { payload, seen, direct: true, updated: self.updates };
+
+        if let Some(old) = self.entries.insert(peer, entry) {
+            if !old.direct {
+                self.indirect -= 1;
+            }
+        }
+    }
+
+    /// Remember status of a peer learned second-hand, if it is newer than the known one
+    pub fn insert_indirect(&mut self, peer: PeerId, payload: Payload, seen: Instant) {
+        self.updates += 1;
+        let updated = self.updates;
+
+        match self.entries.get_mut(&peer) {
+            Some(entry) => if entry.seen < seen {
+                entry.payload = payload;
+                entry.seen = seen;
+                entry.updated = updated;
+            },
+            None => {
+                self.entries.insert(peer, Entry { payload, seen, direct: false, updated });
+                self.indirect += 1;
+                self.evict();
+            },
+        }
+    }
+
+    /// Drop least recently updated second-hand statuses beyond capacity
+    fn evict(&mut self) {
+        while self.indirect > self.capacity {
+            let oldest = self.entries.iter()
+                .filter(|(_, entry)| !entry.direct)
+                .min_by_key(|(_, entry)| entry.updated)
+                .map(|(peer, _)| peer.clone());
+
+            match oldest {
+                Some(peer) => {
+                    self.entries.remove(&peer);
+                    self.indirect -= 1;
+                },
+                None => break,
+            }
+        }
+    }
+
+    /// Last known status of peer and when it was received
+    pub fn get(&self, peer: &PeerId) -> Option<(Payload, Instant)> {
+        self.entries.get(peer).map(|entry| (entry.payload, entry.seen))
+    }
+
+    /// All known statuses and when they were received
+    pub fn iter(&self) -> impl Iterator<Item = (&PeerId, &Payload, Instant)> {
+        self.entries.iter().map(|(peer, entry)| (peer, &entry.payload, entry.seen))
+    }
+
+    /// Number of known statuses
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no status is known
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StatusCache;
+    use crate::status::generate_payload;
+    use libp2p::core::PeerId;
+    use std::time::Instant;
+
+    #[test]
+    fn evict_least_recent_indirect() {
+        let mut cache = StatusCache::new(2);
+        let now = Instant::now();
+
+        let direct = PeerId::random();
+        cache.insert_direct(direct.clone(), generate_payload(), now);
+
+        let peers: Vec<PeerId> = (0..3).map(|_| PeerId::random()).collect();
+        for peer in peers.iter() {
+            cache.insert_indirect(peer.clone(), generate_payload(), now);
+        }
+
+        // Least recently updated indirect peer is gone, direct one is kept
+        assert_eq!(cache.len(), 3);
+        assert!(cache.get(&direct).is_some());
+        assert!(cache.get(&peers[0]).is_none());
+        assert!(cache.get(&peers[1]).is_some());
+        assert!(cache.get(&peers[2]).is_some());
+
+        // Updating a peer protects it from being evicted next
+        cache.insert_indirect(peers[1].clone(), generate_payload(), Instant::now());
+        cache.insert_indirect(PeerId::random(), generate_payload(), now);
+
+        assert!(cache.get(&peers[1]).is_some());
+        assert!(cache.get(&peers[2]).is_none());
+    }
+}
@@ -18,15 +18,23 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use crate::status::protocol;
+use crate::clock::{self, SharedClock, Timer};
+use crate::config::NetworkName;
+use crate::trust::SharedSigner;
+use crate::status::{cache::StatusCache, protocol, provider::{SharedProvider, StaticProvider}};
 
 
-use std::{error::Error, io, fmt, num::NonZeroU32, pin::Pin, time::Duration, task::{Context, Poll}};
-use std::collections::VecDeque;
+use std::{borrow::Cow, error::Error, io, fmt, num::{NonZeroU32, NonZeroUsize}, pin::Pin, time::{Duration, Instant}, task::{Context, Poll}};
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex, atomic::{AtomicUsize, Ordering}};
 
 use futures::prelude::*;
 
+use log::{debug, warn};
+
+use libp2p::core::{PeerId, either::EitherOutput, identity::Keypair, upgrade::{DeniedUpgrade, EitherUpgrade, UpgradeError}};
 use libp2p::swarm::{
+    IntoProtocolsHandler,
     KeepAlive,
     SubstreamProtocol,
     ProtocolsHandler,
@@ -34,27 +42,94 @@ use libp2p::swarm::{
     ProtocolsHandlerEvent
 };
 
-use wasm_timer::Delay;
-
 use void::Void;
 
+/// Upper bound of any delay, far enough in the future to never elapse in practice.
+const MAX_DELAY: Duration = Duration::from_secs(10 * 365 * 24 * 60 * 60);
+
+/// Clamps the delay to [`MAX_DELAY`], as adding an absurdly large delay to
+/// the current time would overflow `Instant` and panic.
+fn clamp_delay(d: Duration) -> Duration {
+    match Instant::now().checked_add(d) {
+        Some(_) if d <= MAX_DELAY => d,
+        _ => MAX_DELAY,
+    }
+}
+
+/// Whether the I/O error kind means that the remote closed the connection.
+fn is_closed(kind: io::ErrorKind) -> bool {
+    match kind {
+        io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted |
+            io::ErrorKind::BrokenPipe | io::ErrorKind::UnexpectedEof => true,
+        _ => false,
+    }
+}
+
 /// The configuration for outbound requests.
 #[derive(Clone, Debug)]
 pub struct StatusConfig {
-    /// The current status sent on request
-    status: protocol::Payload,
+    /// The source of the status sent in response to inbound requests
+    inbound_status: SharedProvider,
+    /// The status sent along with outbound requests, once supported by the protocol
+    outbound_status: protocol::Payload,
     /// The timeout of an outbound request.
     timeout: Duration,
+    /// How long a response may not make progress before it is given up, if
+    /// not derived from the timeout.
+    stall_timeout: Option<Duration>,
     /// The duration between the last successful outbound or inbound request
     /// and the next outbound request.
     interval: Duration,
+    /// How the interval scales with the round trip time, if at all.
+    adaptive_interval: Option<AdaptiveInterval>,
     /// The maximum number of failed outbound requests before the associated
     /// connection is deemed unhealthy, indicating to the `Swarm` that it
     /// should be closed.
     max_failures: NonZeroU32,
+    /// How much each kind of failure counts toward `max_failures`.
+    failure_weights: FailureWeights,
+    /// How long a failure counts toward `max_failures`, if not until the
+    /// next success.
+    failure_window: Option<Duration>,
     /// Whether the connection should generally be kept alive unless
     /// `max_failures` occur.
     keep_alive: bool,
+    /// How long the connection is kept alive after the last successful
+    /// exchange, if not generally kept alive.
+    keep_alive_idle: Option<Duration>,
+    /// Whether the last known statuses of other peers are sent along
+    /// with our own.
+    gossip: bool,
+    /// The maximum number of second-hand statuses remembered.
+    gossip_capacity: usize,
+    /// Key the status is signed with before it is sent, if any.
+    signer: Option<protocol::Signer>,
+    /// Whether responses without a valid signature are rejected.
+    require_signature: bool,
+    /// The local peer, which signatures of remotes must be addressed to.
+    local_peer: Option<PeerId>,
+    /// Whether our status is only served to trusted peers.
+    trusted_only: bool,
+    /// Human readable name sent along with our status, if any.
+    name: Option<String>,
+    /// Epoch sent along with our status, telling peers when we restarted.
+    epoch: u64,
+    /// Identifier of the format of our payload, if it is not raw bytes.
+    schema: Option<String>,
+    /// Network the protocol is scoped to, if any.
+    network: Option<NetworkName>,
+    /// Optional features we offer to peers that support them as well.
+    capabilities: protocol::Capabilities,
+    /// The maximum rate of inbound requests answered per connection, if limited.
+    inbound_rate: Option<RateLimit>,
+    /// The maximum number of inbound substreams open at once per connection.
+    max_inbound: NonZeroUsize,
+    /// The maximum number of outbound substreams open at once per connection.
+    max_outbound: NonZeroUsize,
+    /// The maximum number of results waiting to be reported per connection.
+    max_pending: NonZeroUsize,
+    /// The clock used for all timing.
+    clock: SharedClock,
 }
 
 impl StatusConfig {
@@ -62,8 +137,13 @@ impl StatusConfig {
     ///
     ///   * [`StatusConfig::with_interval`] 15s
     ///   * [`StatusConfig::with_timeout`] 20s
+    ///   * [`StatusConfig::with_stall_timeout`] a quarter of the timeout
     ///   * [`StatusConfig::with_max_failures`] 1
     ///   * [`StatusConfig::with_keep_alive`] false
+    ///   * [`StatusConfig::with_gossip_capacity`] 256
+    ///   * [`StatusConfig::with_inbound_rate`] bursts of 10, one more every second
+    ///   * [`StatusConfig::with_max_substreams`] 4 inbound, 1 outbound
+    ///   * [`StatusConfig::with_max_pending_results`] 32
     ///
     /// These settings have the following effect:
     ///
@@ -75,28 +155,137 @@ impl StatusConfig {
     ///   * The connection may be closed at any time as far as the status protocol
     ///     is concerned, i.e. the status protocol itself does not keep the
     ///     connection alive.
+    ///   * Inbound requests beyond the rate limit are refused, which peers
+    ///     requesting on any sane interval never hit.
+    ///   * No new request is sent while the previous one is outstanding.
+    ///
+    /// The given status is used for both inbound and outbound requests, along
+    /// with a random epoch that peers notice to change when we restart.
     pub fn new(status: protocol::Payload) -> Self {
         Self {
-            status,
+            inbound_status: Arc::new(StaticProvider(status)),
+            outbound_status: status,
             timeout: Duration::from_secs(20),
+            stall_timeout: None,
             interval: Duration::from_secs(15),
+            adaptive_interval: None,
             max_failures: NonZeroU32::new(1).expect("1 != 0"),
-            keep_alive: false
+            failure_weights: FailureWeights::default(),
+            failure_window: None,
+            keep_alive: false,
+            keep_alive_idle: None,
+            gossip: false,
+            gossip_capacity: 256,
+            signer: None,
+            require_signature: false,
+            local_peer: None,
+            trusted_only: false,
+            name: None,
+            // Zero is received as no epoch at all
+            epoch: rand::random::<u64>().max(1),
+            schema: None,
+            network: None,
+            capabilities: protocol::Capabilities::all(),
+            inbound_rate: Some(RateLimit { burst: 10, period: Duration::from_secs(1) }),
+            max_inbound: NonZeroUsize::new(4).expect("4 != 0"),
+            max_outbound: NonZeroUsize::new(1).expect("1 != 0"),
+            max_pending: NonZeroUsize::new(32).expect("32 != 0"),
+            clock: clock::real(),
         }
     }
 
+    /// Creates a new `StatusConfig` for peers that must notice failures
+    /// quickly, on reliable networks, with the following settings, and
+    /// otherwise those of [`StatusConfig::new`]:
+    ///
+    ///   * [`StatusConfig::with_interval`] 5s
+    ///   * [`StatusConfig::with_timeout`] 5s
+    ///   * [`StatusConfig::with_max_failures`] 1
+    ///
+    /// A connection is thus closed at most 10 seconds after the peer stopped
+    /// answering.
+    pub fn aggressive(status: protocol::Payload) -> Self {
+        Self::new(status)
+            .with_interval(Duration::from_secs(5))
+            .with_timeout(Duration::from_secs(5))
+            .with_max_failures(NonZeroU32::new(1).expect("1 != 0"))
+    }
+
+    /// Creates a new `StatusConfig` for peers on slow or flaky networks, or
+    /// where traffic is costly, with the following settings, and otherwise
+    /// those of [`StatusConfig::new`]:
+    ///
+    ///   * [`StatusConfig::with_interval`] 60s
+    ///   * [`StatusConfig::with_timeout`] 60s
+    ///   * [`StatusConfig::with_max_failures`] 5
+    ///   * [`StatusConfig::with_failure_window`] 1h
+    ///
+    /// A connection is thus only closed once 5 requests failed within an
+    /// hour, without any successful one in between.
+    pub fn relaxed(status: protocol::Payload) -> Self {
+        Self::new(status)
+            .with_interval(Duration::from_secs(60))
+            .with_timeout(Duration::from_secs(60))
+            .with_max_failures(NonZeroU32::new(5).expect("5 != 0"))
+            .with_failure_window(Duration::from_secs(60 * 60))
+    }
+
+    /// Sets the status advertised in response to inbound requests, e.g. to
+    /// only reveal a redacted status to others.
+    pub fn with_inbound_status(mut self, status: protocol::Payload) -> Self {
+        self.inbound_status = Arc::new(StaticProvider(status));
+        self
+    }
+
+    /// Sets the source of the status advertised in response to inbound
+    /// requests, for statuses that change over time.
+    ///
+    /// The provider is asked for the current status on every request.
+    pub fn with_provider(mut self, provider: SharedProvider) -> Self {
+        self.inbound_status = provider;
+        self
+    }
+
+    /// Sets the status sent along with outbound requests.
+    ///
+    /// The current protocol is unidirectional, so it is not sent yet.
+    pub fn with_outbound_status(mut self, status: protocol::Payload) -> Self {
+        self.outbound_status = status;
+        self
+    }
+
     /// Sets the request timeout.
     pub fn with_timeout(mut self, d: Duration) -> Self {
         self.timeout = d;
         self
     }
 
+    /// Sets how long a response may not make any progress before it is
+    /// given up, failing with [`StatusFailure::Stalled`] ahead of the timeout.
+    ///
+    /// By default this is a quarter of the request timeout, so that a large
+    /// response arriving slowly but steadily is still received in time.
+    pub fn with_stall_timeout(mut self, d: Duration) -> Self {
+        self.stall_timeout = Some(d);
+        self
+    }
+
     /// Sets the request interval.
     pub fn with_interval(mut self, d: Duration) -> Self {
         self.interval = d;
         self
     }
 
+    /// Sets the request interval to scale with the round trip time of the
+    /// last exchange, or disables scaling with `None`, the default.
+    ///
+    /// Slow peers are then requested less often, so that exchanges do not
+    /// overlap, while fast peers keep the configured interval.
+    pub fn with_adaptive_interval(mut self, adaptive: Option<AdaptiveInterval>) -> Self {
+        self.adaptive_interval = adaptive;
+        self
+    }
+
     /// Sets the maximum number of consecutive request failures upon which the remote
     /// peer is considered unreachable and the connection closed.
     pub fn with_max_failures(mut self, n: NonZeroU32) -> Self {
@@ -104,6 +293,23 @@ impl StatusConfig {
         self
     }
 
+    /// Sets how much each kind of failure counts toward the maximum number
+    /// of failures, by default every failure counts fully.
+    pub fn with_failure_weights(mut self, weights: FailureWeights) -> Self {
+        self.failure_weights = weights;
+        self
+    }
+
+    /// Sets how long a failure counts toward the maximum number of failures.
+    ///
+    /// By default failures only reset on a successful exchange, so a mostly
+    /// healthy peer with rare timeouts slowly accumulates them. With a window,
+    /// only failures within the given duration of the latest one count.
+    pub fn with_failure_window(mut self, d: Duration) -> Self {
+        self.failure_window = Some(d);
+        self
+    }
+
     /// Sets whether the status protocol itself should keep the connection alive,
     /// apart from the maximum allowed failures.
     ///
@@ -118,6 +324,450 @@ impl StatusConfig {
         self.keep_alive = b;
         self
     }
+
+    /// Sets the status protocol to keep the connection alive until the given
+    /// duration has passed since the last successful exchange, e.g. for bursts
+    /// of work, after which it may be closed if otherwise idle.
+    ///
+    /// This has no effect if the connection is kept alive in general, see
+    /// [`StatusConfig::with_keep_alive`].
+    pub fn with_keep_alive_until(mut self, idle: Duration) -> Self {
+        self.keep_alive_idle = Some(idle);
+        self
+    }
+
+    /// Sets whether the last known statuses of other peers are gossiped
+    /// to the requesting peers.
+    ///
+    /// With gossip enabled, peers learn about the statuses of others second-hand,
+    /// allowing for a much longer request interval in large networks.
+    pub fn with_gossip(mut self, b: bool) -> Self {
+        self.gossip = b;
+        self
+    }
+
+    /// Sets the maximum number of statuses of peers that are only known
+    /// second-hand, bounding the memory used to remember them.
+    ///
+    /// Statuses received from the peers themselves are not limited.
+    pub fn with_gossip_capacity(mut self, n: usize) -> Self {
+        self.gossip_capacity = n;
+        self
+    }
+
+    /// Empty table to remember known statuses in, according to the configuration.
+    pub(crate) fn gossip_table(&self) -> GossipTable {
+        Arc::new(Mutex::new(StatusCache::new(self.gossip_capacity)))
+    }
+
+    /// Signs the inbound status with the given keypair, which must belong to the
+    /// local peer, allowing remotes to verify that it was not tampered with.
+    ///
+    /// Whenever signing fails, e.g. because the key type does not support it,
+    /// the status is sent unsigned.
+    pub fn with_keypair(self, keypair: &Keypair) -> Self {
+        self.with_signer(Arc::new(keypair.clone()))
+    }
+
+    /// Signs the inbound status with the given signer, e.g. an external one
+    /// holding the key of the local peer, see [`StatusConfig::with_keypair`].
+    ///
+    /// The signer is only used once statuses are sent, not beforehand.
+    pub fn with_signer(mut self, signer: SharedSigner) -> Self {
+        self.local_peer = Some(signer.public().into_peer_id());
+        self.signer = Some(protocol::Signer::from_shared(signer));
+        self
+    }
+
+    /// Sets the local peer, which remotes address their signatures to.
+    ///
+    /// Set along with the signer of the local peer, if any. Signed responses
+    /// are rejected as long as the local peer is unknown.
+    pub fn with_local_peer_id(mut self, peer: PeerId) -> Self {
+        self.local_peer = Some(peer);
+        self
+    }
+
+    /// Sets whether responses must be signed by the remote.
+    ///
+    /// Signatures are always verified if present. By default, unsigned
+    /// responses are accepted as well.
+    pub fn with_require_signature(mut self, b: bool) -> Self {
+        self.require_signature = b;
+        self
+    }
+
+    /// Sets whether our status is only served to trusted peers.
+    ///
+    /// Other peers still get a response, but one flagged as withheld, see
+    /// [`protocol::Status::withheld`], which they do not take for our status.
+    /// Peers are trusted once the behaviour says so, see
+    /// [`crate::status::Status::trust`].
+    pub fn with_trusted_only(mut self, b: bool) -> Self {
+        self.trusted_only = b;
+        self
+    }
+
+    /// Sets the human readable name sent along with our status, e.g. the
+    /// hostname or role of the node, to be shown instead of its peer id.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets the epoch sent along with our status, e.g. a persisted counter
+    /// incremented on every start instead of the random default. Peers
+    /// receive an epoch of zero as none.
+    pub fn with_epoch(mut self, epoch: u64) -> Self {
+        self.epoch = epoch;
+        self
+    }
+
+    /// Sets the identifier of the format of our payload, e.g. `git-sha1`,
+    /// which is sent along with it.
+    ///
+    /// Payloads of peers declaring a different schema are not interpreted,
+    /// while payloads without any schema are assumed to be compatible.
+    pub fn with_schema(mut self, schema: impl Into<String>) -> Self {
+        self.schema = Some(schema.into());
+        self
+    }
+
+    /// Scopes the status protocol to the given network, e.g.
+    /// `/dx/testnet/status/0.1.0`, so that nodes of different networks never
+    /// exchange statuses. The behaviour scopes Kademlia to it as well.
+    pub fn with_network(mut self, network: NetworkName) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Sets the optional features we offer, all of them by default.
+    ///
+    /// Each connection only uses the features both sides offer. Peers that do
+    /// not announce any capabilities are assumed to support everything.
+    pub fn with_capabilities(mut self, capabilities: protocol::Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Sets the maximum rate of inbound requests answered on each connection,
+    /// or `None` to answer all of them.
+    ///
+    /// Requests beyond the limit are refused, so that a misbehaving peer can not
+    /// make us serve our status constantly.
+    pub fn with_inbound_rate(mut self, limit: Option<RateLimit>) -> Self {
+        self.inbound_rate = limit;
+        self
+    }
+
+    /// Sets the maximum number of status substreams each connection may have
+    /// open at once, inbound and outbound respectively.
+    ///
+    /// Inbound substreams beyond the limit are closed without a response, only
+    /// counting those that negotiated the status protocol, while outbound
+    /// requests are deferred until one of the outstanding ones completes.
+    pub fn with_max_substreams(mut self, inbound: NonZeroUsize, outbound: NonZeroUsize) -> Self {
+        self.max_inbound = inbound;
+        self.max_outbound = outbound;
+        self
+    }
+
+    /// Sets the maximum number of results each connection holds until they
+    /// are reported to the behaviour.
+    ///
+    /// Results beyond the limit replace the oldest pending ones, inbound
+    /// results first, as they do not decide whether the connection is kept.
+    /// Dropped results are counted, see [`PendingResults::dropped`].
+    pub fn with_max_pending_results(mut self, n: NonZeroUsize) -> Self {
+        self.max_pending = n;
+        self
+    }
+
+    /// Sets the clock used for all timing, e.g. a [`clock::ManualClock`] in tests.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// The clock used for all timing.
+    pub fn clock(&self) -> &SharedClock {
+        &self.clock
+    }
+
+    /// The epoch sent along with our status.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Whether our status is only served to trusted peers.
+    pub fn trusted_only(&self) -> bool {
+        self.trusted_only
+    }
+
+    /// The optional features we offer.
+    pub fn capabilities(&self) -> protocol::Capabilities {
+        self.capabilities
+    }
+
+    /// The identifier of the format of our payload, if any.
+    pub fn schema(&self) -> Option<&str> {
+        self.schema.as_ref().map(String::as_str)
+    }
+
+    /// The network the protocol is scoped to, if any.
+    pub fn network(&self) -> Option<&NetworkName> {
+        self.network.as_ref()
+    }
+
+    /// The name the status protocol is negotiated as.
+    pub fn protocol_name(&self) -> Cow<'static, [u8]> {
+        match &self.network {
+            Some(network) => Cow::Owned(network.protocol("status/0.1.0").into_bytes()),
+            None => Cow::Borrowed(protocol::PROTOCOL_NAME),
+        }
+    }
+
+    /// The request interval.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// How the interval scales with the round trip time, if at all.
+    pub fn adaptive_interval(&self) -> Option<AdaptiveInterval> {
+        self.adaptive_interval
+    }
+
+    /// The request interval after an exchange with the given round trip time.
+    pub fn effective_interval(&self, rtt: Duration) -> Duration {
+        match &self.adaptive_interval {
+            Some(adaptive) => adaptive.apply(self.interval, rtt),
+            None => self.interval,
+        }
+    }
+
+    /// The request timeout.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// The duration without progress after which a response is given up.
+    pub fn stall_timeout(&self) -> Duration {
+        self.stall_timeout.unwrap_or(self.timeout / 4)
+    }
+
+    /// The maximum number of consecutive request failures.
+    pub fn max_failures(&self) -> NonZeroU32 {
+        self.max_failures
+    }
+
+    /// How long a failure counts toward the maximum, if not until the next success.
+    pub fn failure_window(&self) -> Option<Duration> {
+        self.failure_window
+    }
+
+    /// Whether the response of the given peer carries an acceptable signature,
+    /// i.e. one addressed to the local peer.
+    fn accepts(&self, peer: &PeerId, response: &protocol::Response) -> bool {
+        match (&response.signature, &self.local_peer) {
+            (Some(signature), Some(local)) => signature.verify(peer, &response.payload, response.epoch, local),
+            (Some(_), None) => false,
+            (None, _) => !self.require_signature,
+        }
+    }
+}
+
+/// Rate limit of inbound requests, as a token bucket that holds up to `burst`
+/// tokens and is refilled by one token every `period`.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    /// The number of requests answered in quick succession.
+    pub burst: u32,
+    /// The time after which one more request is answered.
+    pub period: Duration,
+}
+
+/// Scaling of the request interval with the round trip time, see
+/// [`StatusConfig::with_adaptive_interval`].
+///
+/// The interval is the configured one or `factor` times the round trip time,
+/// whichever is longer, but at least `floor` and at most `ceiling`.
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptiveInterval {
+    /// The multiple of the round trip time between requests.
+    pub factor: u32,
+    /// The shortest interval.
+    pub floor: Duration,
+    /// The longest interval.
+    pub ceiling: Duration,
+}
+
+impl AdaptiveInterval {
+    /// The interval for the configured one and the given round trip time.
+    pub fn apply(&self, interval: Duration, rtt: Duration) -> Duration {
+        let scaled = rtt.checked_mul(self.factor).unwrap_or(self.ceiling);
+
+        interval.max(scaled).max(self.floor).min(self.ceiling)
+    }
+}
+
+/// State of the token bucket of a [`RateLimit`].
+#[derive(Clone, Copy, Debug)]
+struct TokenBucket {
+    /// The number of requests that can be answered right away.
+    tokens: u32,
+    /// When the bucket was last refilled.
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    /// Full bucket for the given limit.
+    fn new(limit: &RateLimit, now: Instant) -> Self {
+        TokenBucket { tokens: limit.burst, refilled: now }
+    }
+
+    /// Takes a token if one is available, after refilling the tokens of
+    /// all periods that passed since.
+    fn take(&mut self, limit: &RateLimit, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.refilled);
+        let periods = if limit.period > Duration::new(0, 0) {
+            elapsed.as_nanos() / limit.period.as_nanos()
+        } else {
+            u128::from(limit.burst)
+        };
+
+        if self.tokens.saturating_add(periods.min(u128::from(limit.burst)) as u32) >= limit.burst {
+            self.tokens = limit.burst;
+            self.refilled = now;
+        } else if periods > 0 {
+            self.tokens += periods as u32;
+            self.refilled += limit.period * periods as u32;
+        }
+
+        if self.tokens > 0 {
+            self.tokens -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Preset of status settings, picking one of the `StatusConfig` constructors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatusProfile {
+    /// See [`StatusConfig::new`].
+    Default,
+    /// See [`StatusConfig::aggressive`].
+    Aggressive,
+    /// See [`StatusConfig::relaxed`].
+    Relaxed,
+}
+
+impl StatusProfile {
+    /// Profile of the given name, i.e. `default`, `aggressive` or `relaxed`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(StatusProfile::Default),
+            "aggressive" => Some(StatusProfile::Aggressive),
+            "relaxed" => Some(StatusProfile::Relaxed),
+            _ => None,
+        }
+    }
+
+    /// Creates a new `StatusConfig` with the settings of this profile.
+    pub fn config(self, status: protocol::Payload) -> StatusConfig {
+        match self {
+            StatusProfile::Default => StatusConfig::new(status),
+            StatusProfile::Aggressive => StatusConfig::aggressive(status),
+            StatusProfile::Relaxed => StatusConfig::relaxed(status),
+        }
+    }
+}
+
+impl Default for StatusProfile {
+    fn default() -> Self {
+        StatusProfile::Default
+    }
+}
+
+/// Weights with which failed requests count toward the maximum number of
+/// failures, e.g. to not close connections to peers that merely do not
+/// support the protocol.
+#[derive(Clone, Debug)]
+pub struct FailureWeights {
+    /// Weight of [`StatusFailure::Timeout`] and [`StatusFailure::Stalled`].
+    pub timeout: f64,
+    /// Weight of [`StatusFailure::Unsupported`].
+    pub unsupported: f64,
+    /// Weight of any other failure.
+    pub other: f64,
+}
+
+impl Default for FailureWeights {
+    fn default() -> Self {
+        FailureWeights { timeout: 1.0, unsupported: 1.0, other: 1.0 }
+    }
+}
+
+impl FailureWeights {
+    /// How much the given failure counts toward the maximum.
+    ///
+    /// Throttled inbound requests never count, as they say nothing about the
+    /// health of the connection.
+    pub fn weight(&self, failure: &StatusFailure) -> f64 {
+        match failure {
+            StatusFailure::Throttled => 0.0,
+            StatusFailure::Timeout | StatusFailure::Stalled { .. } => self.timeout,
+            StatusFailure::Unsupported => self.unsupported,
+            StatusFailure::BadSignature | StatusFailure::ConnectionClosed | StatusFailure::Other { .. } => self.other,
+        }
+    }
+}
+
+/// Last known statuses of peers, shared among all handlers for gossiping.
+pub type GossipTable = Arc<Mutex<StatusCache>>;
+
+/// Number of results waiting to be reported by the handlers sharing it,
+/// to monitor how far reporting lags behind.
+#[derive(Debug, Default)]
+pub struct PendingResults {
+    depth: AtomicUsize,
+    high_water: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+impl PendingResults {
+    /// The number of results currently pending.
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    /// The largest number of results that were pending at once.
+    pub fn high_water(&self) -> usize {
+        self.high_water.load(Ordering::Relaxed)
+    }
+
+    /// The number of results dropped as too many were pending, see
+    /// [`StatusConfig::with_max_pending_results`].
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    fn add(&self) {
+        let depth = self.depth.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let mut high = self.high_water.load(Ordering::Relaxed);
+        while depth > high {
+            match self.high_water.compare_exchange_weak(high, depth, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => break,
+                Err(current) => high = current,
+            }
+        }
+    }
+
+    fn remove(&self, n: usize) {
+        self.depth.fetch_sub(n, Ordering::Relaxed);
+    }
 }
 
 /// The result of an inbound or outbound request.
@@ -128,8 +778,18 @@ pub type StatusResult = Result<StatusSuccess, StatusFailure>;
 pub enum StatusSuccess {
     /// Received status request
     Requested,
+    /// Received status request from an untrusted peer, which got a withheld
+    /// status, see [`StatusConfig::with_trusted_only`]
+    Withheld,
     /// Requested and received status
-    Received( protocol::Payload ),
+    Received {
+        /// The response of the remote.
+        response: protocol::Response,
+        /// The time between sending the request and receiving the response.
+        rtt: Duration,
+        /// The name of the negotiated protocol, e.g. `/dx/status/0.1.0`.
+        protocol: String,
+    },
 }
 
 /// An outbound request failure.
@@ -138,6 +798,18 @@ pub enum StatusFailure {
     /// The status request timed out, i.e. no response was received within the
     /// configured timeout.
     Timeout,
+    /// The response stopped making progress for longer than the configured
+    /// stall timeout, after the given number of bytes had been received.
+    Stalled { received: usize },
+    /// The remote does not support the status protocol, i.e. negotiating it failed.
+    Unsupported,
+    /// The response was not signed by the remote, or the signature is invalid.
+    BadSignature,
+    /// The remote sent inbound requests faster than the configured rate
+    /// limit, so some of them have been refused since the last report.
+    Throttled,
+    /// The connection was closed or reset while exchanging the status.
+    ConnectionClosed,
     /// The request failed for reasons other than a timeout.
     Other { error: Box<dyn std::error::Error + Send + 'static> }
 }
@@ -146,6 +818,11 @@ impl fmt::Display for StatusFailure {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             StatusFailure::Timeout => f.write_str("Status timeout"),
+            StatusFailure::Stalled { received } => write!(f, "Status response stalled after {} bytes", received),
+            StatusFailure::Unsupported => f.write_str("Status protocol not supported"),
+            StatusFailure::BadSignature => f.write_str("Invalid status signature"),
+            StatusFailure::Throttled => f.write_str("Status requests throttled"),
+            StatusFailure::ConnectionClosed => f.write_str("Status connection closed"),
             StatusFailure::Other { error } => write!(f, "Status error: {}", error)
         }
     }
@@ -154,12 +831,35 @@ impl fmt::Display for StatusFailure {
 impl Error for StatusFailure {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            StatusFailure::Timeout => None,
+            StatusFailure::Timeout | StatusFailure::Stalled { .. } | StatusFailure::Unsupported |
+                StatusFailure::BadSignature | StatusFailure::Throttled | StatusFailure::ConnectionClosed => None,
             StatusFailure::Other { error } => Some(&**error)
         }
     }
 }
 
+/// Event sent by the behaviour to the handler of a connection.
+#[derive(Debug, Clone)]
+pub enum StatusIn {
+    /// Stop sending outbound requests, inbound requests are still answered.
+    Pause,
+    /// Resume sending outbound requests.
+    Resume,
+    /// Send the next outbound request right away, or as soon as the
+    /// outstanding one completed.
+    Request,
+    /// Serve our actual status to the remote, which is trusted.
+    Serve,
+    /// Withhold our status from the remote, which is not trusted.
+    Withhold,
+    /// Stop sending outbound requests for good and no longer keep the
+    /// connection alive, so that it can be closed.
+    Shutdown,
+    /// Like [`StatusIn::Shutdown`], but also refuse inbound requests, while
+    /// still reporting the results of exchanges already in flight.
+    Drain,
+}
+
 /// Protocol handler that handles requesting the remote at a regular period
 /// and answering status requests.
 ///
@@ -168,76 +868,516 @@ pub struct StatusHandler {
     /// Configuration options.
     config: StatusConfig,
     /// The timer for when to send the next request.
-    next_request: Delay,
-    /// The pending results from inbound or outbound requests, ready
-    /// to be `poll()`ed.
-    pending_results: VecDeque<StatusResult>,
-    /// The number of consecutive request failures that occurred.
-    failures: u32,
+    next_request: Timer,
+    /// The pending results from inbound requests, ready to be `poll()`ed.
+    ///
+    /// Both queues are first in, first out, see [`StatusHandler::next_result`].
+    inbound_results: VecDeque<StatusResult>,
+    /// The pending results from outbound requests, ready to be `poll()`ed.
+    outbound_results: VecDeque<StatusResult>,
+    /// The number of pending results, shared with other handlers for monitoring.
+    pending: Arc<PendingResults>,
+    /// Whether the next result is taken from the inbound queue, if not empty.
+    inbound_turn: bool,
+    /// The weighted number of consecutive request failures that occurred.
+    failures: f64,
+    /// When each of the counted failures occurred and its weight, if they
+    /// expire after [`StatusConfig::with_failure_window`].
+    recent_failures: VecDeque<(Instant, f64)>,
+    /// Whether sending outbound requests is currently suspended.
+    paused: bool,
+    /// Whether the behaviour released the connection.
+    shutdown: bool,
+    /// Whether the local node is shutting down, refusing inbound requests.
+    draining: bool,
+    /// Whether inbound requests are answered with our actual status.
+    serving: bool,
+    /// Until when the connection is kept alive, if bounded.
+    keep_alive_until: Option<Instant>,
+    /// The known statuses of other peers to gossip, if enabled.
+    gossip: Option<GossipTable>,
+    /// The remote peer, if known, which is never gossiped its own status.
+    remote: Option<PeerId>,
+    /// The optional features announced by the remote, if any yet.
+    remote_capabilities: Option<protocol::Capabilities>,
+    /// When the currently outstanding outbound request was issued.
+    request_sent: Option<Instant>,
+    /// Whether a request was asked for while another one was outstanding,
+    /// so that its response does not postpone it to the next interval.
+    forced: bool,
+    /// The limits inbound requests are answered within.
+    ///
+    /// Checked by the responses themselves, as substreams of all protocols
+    /// of the connection are offered ours before any is negotiated.
+    inbound_gate: Arc<InboundGate>,
+    /// The number of outbound substreams currently open.
+    outbound_open: usize,
+}
+
+impl fmt::Debug for StatusHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StatusHandler")
+            .field("config", &self.config)
+            .field("inbound_results", &self.inbound_results)
+            .field("outbound_results", &self.outbound_results)
+            .field("failures", &self.failures)
+            .field("paused", &self.paused)
+            .field("shutdown", &self.shutdown)
+            .field("gossip", &self.gossip.is_some())
+            .field("inbound_gate", &self.inbound_gate)
+            .field("outbound_open", &self.outbound_open)
+            .finish()
+    }
+}
+
+impl Drop for StatusHandler {
+    fn drop(&mut self) {
+        // Results of closed connections are never reported
+        self.pending.remove(self.inbound_results.len() + self.outbound_results.len());
+    }
 }
 
 impl StatusHandler {
     /// Builds a new `StatusHandler` with the given configuration.
     pub fn new(config: StatusConfig) -> Self {
+        let now = config.clock.now();
         StatusHandler {
+            next_request: Timer::new(config.clock.clone(), Duration::new(0,0)),
+            inbound_gate: Arc::new(InboundGate::new(&config)),
+            outbound_open: 0,
+            inbound_results: VecDeque::with_capacity(2),
+            outbound_results: VecDeque::with_capacity(2),
+            pending: Arc::new(PendingResults::default()),
+            inbound_turn: false,
+            failures: 0.0,
+            recent_failures: VecDeque::new(),
+            paused: false,
+            shutdown: false,
+            draining: false,
+            serving: !config.trusted_only,
+            keep_alive_until: config.keep_alive_idle.map(|idle| now + clamp_delay(idle)),
+            gossip: None,
+            remote: None,
+            remote_capabilities: None,
+            request_sent: None,
+            forced: false,
             config,
-            next_request: Delay::new(Duration::new(0,0)),
-            pending_results: VecDeque::with_capacity(2),
-            failures: 0,
         }
     }
+
+    /// Counts the pending results in the given metrics, along with those of
+    /// other handlers sharing them.
+    pub fn with_pending_results(mut self, pending: Arc<PendingResults>) -> Self {
+        self.pending.remove(self.inbound_results.len() + self.outbound_results.len());
+        self.pending = pending;
+        self
+    }
+
+    /// The number of pending results, shared with other handlers if any.
+    pub fn pending_results(&self) -> &PendingResults {
+        &self.pending
+    }
+
+    /// Gossips the statuses in the given table, if enabled by the configuration.
+    pub fn with_gossip(mut self, table: GossipTable) -> Self {
+        if self.config.gossip {
+            self.gossip = Some(table);
+        }
+        self
+    }
+
+    /// Sets the remote peer of the connection.
+    pub fn with_remote(mut self, peer: PeerId) -> Self {
+        self.remote = Some(peer);
+        self
+    }
+
+    /// Whether the given feature is offered by both sides of the connection.
+    ///
+    /// Until the remote announces its capabilities, it is assumed to support
+    /// all of them, as peers predating the capabilities do.
+    fn agreed(&self, feature: protocol::Capabilities) -> bool {
+        self.config.capabilities.contains(feature)
+            && self.remote_capabilities.map_or(true, |remote| remote.contains(feature))
+    }
+
+    /// Collects the statuses of other peers to send along with our own,
+    /// leaving out the remote, which knows its own status best.
+    fn gossip(&self) -> Vec<protocol::Gossip> {
+        let now = self.config.clock.now();
+
+        if !self.agreed(protocol::Capabilities::GOSSIP) {
+            return Vec::new()
+        }
+
+        match &self.gossip {
+            Some(table) => table.lock().unwrap().iter()
+                .filter(|(peer, _, _)| Some(*peer) != self.remote.as_ref())
+                .map(|(peer, payload, seen)| protocol::Gossip {
+                    peer: peer.clone(),
+                    payload: *payload,
+                    age: now.saturating_duration_since(seen),
+                })
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Records that an outbound substream completed, successfully or not.
+    fn close_outbound(&mut self) {
+        self.outbound_open = self.outbound_open.saturating_sub(1);
+    }
+
+    /// Adds the given failure to the weighted count, forgetting failures that
+    /// fell out of the failure window, if any.
+    fn count_failure(&mut self, failure: &StatusFailure) {
+        let weight = self.config.failure_weights.weight(failure);
+
+        let window = match self.config.failure_window {
+            Some(window) => window,
+            None => {
+                self.failures += weight;
+                return
+            },
+        };
+
+        let now = self.config.clock.now();
+        self.recent_failures.push_back((now, weight));
+        while let Some(&(at, _)) = self.recent_failures.front() {
+            if now.duration_since(at) < window {
+                break
+            }
+            self.recent_failures.pop_front();
+        }
+
+        self.failures = self.recent_failures.iter().map(|(_, weight)| weight).sum();
+    }
+
+    /// Queues a result to report, dropping the oldest pending one if there
+    /// are too many already.
+    ///
+    /// Failures are counted right away, so that dropping them never keeps
+    /// the connection from being closed.
+    fn push_result(&mut self, inbound: bool, result: StatusResult) {
+        match &result {
+            Ok(StatusSuccess::Received { .. }) => {
+                self.failures = 0.0;
+                self.recent_failures.clear();
+            },
+            Err(e) => self.count_failure(e),
+            Ok(_) => (),
+        }
+
+        if self.inbound_results.len() + self.outbound_results.len() >= self.config.max_pending.get() {
+            // Inbound results are merely informational, so they go first
+            let dropped = self.inbound_results.pop_back().or_else(|| self.outbound_results.pop_back());
+            if let Some(dropped) = dropped {
+                debug!("Too many pending status results, dropping {:?}", dropped);
+                self.pending.remove(1);
+                self.pending.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        if inbound {
+            self.inbound_results.push_front(result);
+        } else {
+            self.outbound_results.push_front(result);
+        }
+        self.pending.add();
+    }
+
+    /// The response to the next inbound request.
+    fn response(&self) -> protocol::Status {
+        if !self.serving {
+            return protocol::Status::withheld().with_protocol_name(self.config.protocol_name())
+        }
+
+        let signer = if self.agreed(protocol::Capabilities::SIGNATURES) {
+            self.config.signer.clone()
+        } else {
+            None
+        };
+
+        // Payload is only taken from the provider once the response is written
+        protocol::Status::from_provider(self.config.inbound_status.clone(), signer)
+            .with_recipient(self.remote.clone())
+            .with_gossip(self.gossip())
+            .with_name(self.config.name.clone())
+            .with_epoch(Some(self.config.epoch))
+            .with_schema(self.config.schema.clone())
+            .with_capabilities(Some(self.config.capabilities))
+            .with_protocol_name(self.config.protocol_name())
+    }
+
+    /// Takes the next result to report.
+    ///
+    /// Results of the same direction are reported in the order they
+    /// occurred. Both directions take turns while they have results pending,
+    /// so that a flood of inbound requests can not hold back the responses
+    /// to our own requests, which decide whether the connection is kept.
+    fn next_result(&mut self) -> Option<StatusResult> {
+        let (first, second) = if self.inbound_turn {
+            (&mut self.inbound_results, &mut self.outbound_results)
+        } else {
+            (&mut self.outbound_results, &mut self.inbound_results)
+        };
+
+        let result = match first.pop_back() {
+            Some(result) => {
+                self.inbound_turn = !self.inbound_turn;
+                Some(result)
+            },
+            None => second.pop_back(),
+        };
+
+        if result.is_some() {
+            self.pending.remove(1);
+        }
+        result
+    }
+}
+
+/// Limits on answering inbound requests, passed along with the responses so
+/// that only substreams the status protocol was negotiated for are counted.
+#[derive(Debug)]
+struct InboundGate {
+    /// The rate limit of inbound requests, if any.
+    limit: Option<RateLimit>,
+    /// The maximum number of inbound requests being answered at once.
+    max_open: NonZeroUsize,
+    /// The clock the tokens are refilled by.
+    clock: SharedClock,
+    /// The state shared with the pending responses.
+    state: Mutex<GateState>,
+}
+
+/// State of an [`InboundGate`].
+#[derive(Debug)]
+struct GateState {
+    /// Tokens left to answer inbound requests, if rate limited.
+    tokens: Option<TokenBucket>,
+    /// The number of inbound requests currently being answered.
+    open: usize,
+    /// Whether inbound requests have been refused since the last report.
+    throttled: bool,
+}
+
+impl InboundGate {
+    /// Gate with a full bucket for the configured rate limit, if any.
+    fn new(config: &StatusConfig) -> Self {
+        let now = config.clock.now();
+        InboundGate {
+            limit: config.inbound_rate,
+            max_open: config.max_inbound,
+            clock: config.clock.clone(),
+            state: Mutex::new(GateState {
+                tokens: config.inbound_rate.as_ref().map(|limit| TokenBucket::new(limit, now)),
+                open: 0,
+                throttled: false,
+            }),
+        }
+    }
+
+    /// Whether inbound requests have been refused since the last call.
+    fn take_throttled(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        std::mem::replace(&mut state.throttled, false)
+    }
+}
+
+impl protocol::Admission for InboundGate {
+    fn admit(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let now = self.clock.now();
+
+        // Too many open requests are refused without using up a token
+        if state.open >= self.max_open.get() {
+            return false
+        }
+
+        let admitted = match (&self.limit, &mut state.tokens) {
+            (Some(limit), Some(bucket)) => bucket.take(limit, now),
+            _ => true,
+        };
+
+        if admitted {
+            state.open += 1;
+        } else {
+            state.throttled = true;
+        }
+        admitted
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.open = state.open.saturating_sub(1);
+    }
+}
+
+/// Builds the [`StatusHandler`] of a new connection, once the remote is known.
+pub struct IntoStatusHandler {
+    handler: StatusHandler,
+    /// Peers our status is served to, if only served to trusted ones.
+    trusted: HashSet<PeerId>,
+}
+
+impl IntoStatusHandler {
+    /// Wraps the handler to be used for the connection.
+    pub fn new(handler: StatusHandler) -> Self {
+        IntoStatusHandler { handler, trusted: HashSet::new() }
+    }
+
+    /// Sets the peers our status is served to right from the start of the
+    /// connection, if only trusted peers are served.
+    pub fn with_trusted(mut self, trusted: HashSet<PeerId>) -> Self {
+        self.trusted = trusted;
+        self
+    }
+}
+
+impl IntoProtocolsHandler for IntoStatusHandler {
+    type Handler = StatusHandler;
+
+    fn into_handler(self, remote: &PeerId) -> StatusHandler {
+        let mut handler = self.handler.with_remote(remote.clone());
+        handler.serving = !handler.config.trusted_only || self.trusted.contains(remote);
+        handler
+    }
+
+    fn inbound_protocol(&self) -> <StatusHandler as ProtocolsHandler>::InboundProtocol {
+        EitherUpgrade::A(self.handler.response())
+    }
 }
 
 impl ProtocolsHandler for StatusHandler
 {
-    type InEvent = Void;
+    type InEvent = StatusIn;
     type OutEvent = StatusResult;
     type Error = StatusFailure;
-    type InboundProtocol = protocol::Status;
+    type InboundProtocol = EitherUpgrade<protocol::Status, DeniedUpgrade>;
     type OutboundProtocol = protocol::Status;
     type OutboundOpenInfo = ();
 
-    fn listen_protocol(&self) -> SubstreamProtocol<protocol::Status> {
-        SubstreamProtocol::new(protocol::Status( self.config.status ))
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol> {
+        if self.draining {
+            return SubstreamProtocol::new(EitherUpgrade::B(DeniedUpgrade))
+        }
+
+        // Only requests that negotiated the protocol count against the limits,
+        // as substreams of all protocols of the connection are offered ours
+        let response = self.response().with_admission(self.inbound_gate.clone());
+        SubstreamProtocol::new(EitherUpgrade::A(response))
+            .with_timeout(clamp_delay(self.config.timeout))
     }
 
-    fn inject_fully_negotiated_inbound(&mut self, _: ()) {
+    fn inject_fully_negotiated_inbound(&mut self, _: EitherOutput<(), Void>) {
         // A request from a remote peer has been answered.
-        self.pending_results.push_front(Ok(StatusSuccess::Requested));
+        let result = Ok(if self.serving {
+            StatusSuccess::Requested
+        } else {
+            StatusSuccess::Withheld
+        });
+        self.push_result(true, result);
     }
 
-    fn inject_fully_negotiated_outbound(&mut self, payload: protocol::Payload, _info: ()) {
+    fn inject_fully_negotiated_outbound(&mut self, (response, protocol): (protocol::Response, String), _info: ()) {
         // A request initiated by the local peer was answered by the remote.
-        self.pending_results.push_front(Ok(StatusSuccess::Received(payload)));
+        self.close_outbound();
+        let now = self.config.clock.now();
+        let rtt = self.request_sent.take().map_or(Duration::new(0, 0), |sent| now.saturating_duration_since(sent));
+
+        // Only trust responses signed by the remote, if required, while
+        // withheld ones carry nothing to trust
+        let accepted = match &self.remote {
+            _ if response.withheld => true,
+            Some(remote) => self.config.accepts(remote, &response),
+            None => response.signature.is_none() && !self.config.require_signature,
+        };
+        if !accepted {
+            self.push_result(false, Err(StatusFailure::BadSignature));
+            return
+        }
+
+        if response.capabilities.is_some() && !response.withheld {
+            self.remote_capabilities = response.capabilities;
+        }
+        self.push_result(false, Ok(StatusSuccess::Received { response, rtt, protocol }));
     }
 
-    fn inject_event(&mut self, _: Void) {}
+    fn inject_event(&mut self, event: StatusIn) {
+        match event {
+            StatusIn::Pause => self.paused = true,
+            StatusIn::Resume => self.paused = false,
+            StatusIn::Request => {
+                self.forced = true;
+                self.next_request.reset(Duration::new(0, 0));
+            },
+            StatusIn::Serve => self.serving = true,
+            StatusIn::Withhold => self.serving = false,
+            StatusIn::Shutdown => self.shutdown = true,
+            StatusIn::Drain => {
+                self.shutdown = true;
+                self.draining = true;
+            },
+        }
+    }
+
+    fn inject_dial_upgrade_error(&mut self, _info: (), error: ProtocolsHandlerUpgrErr<protocol::DecodeError>) {
+        self.close_outbound();
 
-    fn inject_dial_upgrade_error(&mut self, _info: (), error: ProtocolsHandlerUpgrErr<io::Error>) {
-        self.pending_results.push_front(
+        // A broken timer is a local problem, so retry instead of blaming the peer
+        if let ProtocolsHandlerUpgrErr::Timer = error {
+            warn!("Status request timer failed, retrying");
+            self.request_sent = None;
+            self.next_request.reset(Duration::new(0, 0));
+            return
+        }
+
+        self.push_result(false,
             Err(match error {
                 ProtocolsHandlerUpgrErr::Timeout => StatusFailure::Timeout,
+                ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Select(_)) => StatusFailure::Unsupported,
+                ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Apply(protocol::DecodeError::BadSignature)) =>
+                    StatusFailure::BadSignature,
+                ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Apply(protocol::DecodeError::Stalled { received })) =>
+                    StatusFailure::Stalled { received },
+                ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Apply(protocol::DecodeError::Io(ref error)))
+                    if error.kind() == io::ErrorKind::TimedOut => StatusFailure::Timeout,
+                // Remote went away mid-exchange
+                ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Apply(protocol::DecodeError::Io(ref error)))
+                    if is_closed(error.kind()) => StatusFailure::ConnectionClosed,
                 e => StatusFailure::Other { error: Box::new(e) }
             }))
     }
 
     fn connection_keep_alive(&self) -> KeepAlive {
-        if self.config.keep_alive {
+        if self.shutdown {
+            KeepAlive::No
+        } else if self.config.keep_alive {
             KeepAlive::Yes
+        } else if let Some(deadline) = self.keep_alive_until {
+            KeepAlive::Until(deadline)
         } else {
             KeepAlive::No
         }
     }
 
     fn poll(&mut self, cx: &mut Context) -> Poll<ProtocolsHandlerEvent<protocol::Status, (), StatusResult, Self::Error>> {
-        if let Some(result) = self.pending_results.pop_back() {
-            if let Ok(StatusSuccess::Received ( .. )) = result {
-                self.failures = 0;
-                self.next_request.reset(self.config.interval);
+        if self.inbound_gate.take_throttled() {
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(Err(StatusFailure::Throttled)))
+        }
+
+        if let Some(result) = self.next_result() {
+            if let (Ok(_), Some(idle)) = (&result, self.config.keep_alive_idle) {
+                self.keep_alive_until = Some(self.config.clock.now() + clamp_delay(idle));
+            }
+            if let Ok(StatusSuccess::Received { rtt, .. }) = &result {
+                if !self.forced {
+                    self.next_request.reset(clamp_delay(self.config.effective_interval(*rtt)));
+                }
             }
             if let Err(e) = result {
-                self.failures += 1;
-                if self.failures >= self.config.max_failures.get() {
+                if self.failures >= f64::from(self.config.max_failures.get()) {
                     return Poll::Ready(ProtocolsHandlerEvent::Close(e))
                 } else {
                     return Poll::Ready(ProtocolsHandlerEvent::Custom(Err(e)))
@@ -246,20 +1386,741 @@ impl ProtocolsHandler for StatusHandler
             return Poll::Ready(ProtocolsHandlerEvent::Custom(result))
         }
 
+        if self.paused || self.shutdown {
+            return Poll::Pending
+        }
+
+        // Completing an outstanding request wakes us up again, the timer
+        // stays due until then
+        if self.outbound_open >= self.config.max_outbound.get() {
+            return Poll::Pending
+        }
 
         match Future::poll(Pin::new(&mut self.next_request), cx) {
-            Poll::Ready(Ok(())) => {
-                self.next_request.reset(self.config.timeout);
-                let protocol = SubstreamProtocol::new(protocol::Status( self.config.status ))
-                    .with_timeout(self.config.timeout);
+            Poll::Ready(()) => {
+                self.forced = false;
+                self.next_request.reset(clamp_delay(self.config.timeout));
+                self.request_sent = Some(self.config.clock.now());
+                self.outbound_open += 1;
+                let status = protocol::Status::new(self.config.outbound_status)
+                    .with_stall_timeout(self.config.clock.clone(), clamp_delay(self.config.stall_timeout()))
+                    .with_protocol_name(self.config.protocol_name());
+                let protocol = SubstreamProtocol::new(status)
+                    .with_timeout(clamp_delay(self.config.timeout));
                 Poll::Ready(ProtocolsHandlerEvent::OutboundSubstreamRequest {
                     protocol,
                     info: (),
                 })
             },
             Poll::Pending => Poll::Pending,
-            Poll::Ready(Err(e)) =>
-                Poll::Ready(ProtocolsHandlerEvent::Close(StatusFailure::Other { error: Box::new(e) }))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::clock::ManualClock;
+    use crate::status::provider::PayloadProvider;
+    use std::sync::atomic::{AtomicU8, Ordering};
+    use futures::{executor::block_on, future::{self, BoxFuture, Either}};
+    use libp2p::core::upgrade::{InboundUpgrade, UpgradeInfo};
+    use libp2p::core::upgrade::NegotiationError;
+    use wasm_timer::Delay;
+
+    /// Negotiates the status protocol for an inbound substream, if offered,
+    /// without writing the response yet.
+    fn negotiate_inbound(handler: &StatusHandler) -> Option<BoxFuture<'static, io::Result<()>>> {
+        match handler.listen_protocol().upgrade().clone() {
+            EitherUpgrade::A(status) => {
+                let info = status.protocol_info().next().unwrap();
+                Some(status.upgrade_inbound(futures::io::Cursor::new(Vec::new()), info))
+            },
+            EitherUpgrade::B(_) => None,
+        }
+    }
+
+    /// Whether an inbound request is answered.
+    fn answers_inbound(handler: &StatusHandler) -> bool {
+        negotiate_inbound(handler).map_or(false, |response| block_on(response).is_ok())
+    }
+
+    /// Whether the handler requests an outbound substream within a short grace period
+    fn requests_outbound(handler: &mut StatusHandler) -> bool {
+        block_on(async {
+            let next = future::poll_fn(|cx| handler.poll(cx));
+            match future::select(next, Delay::new(Duration::from_millis(100))).await {
+                Either::Left((ProtocolsHandlerEvent::OutboundSubstreamRequest { .. }, _)) => true,
+                _ => false,
+            }
+        })
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let mut handler = StatusHandler::new(StatusConfig::new([0; 20]));
+
+        handler.inject_event(StatusIn::Pause);
+        assert!(!requests_outbound(&mut handler));
+
+        handler.inject_event(StatusIn::Resume);
+        assert!(requests_outbound(&mut handler));
+    }
+
+    #[test]
+    fn trust_known_from_the_start() {
+        let trusted = PeerId::random();
+        let config = StatusConfig::new([1; 20]).with_trusted_only(true);
+        let withheld = |remote: &PeerId| {
+            let handler = IntoStatusHandler::new(StatusHandler::new(config.clone()))
+                .with_trusted(vec![trusted.clone()].into_iter().collect())
+                .into_handler(remote);
+            match handler.listen_protocol().upgrade() {
+                EitherUpgrade::A(status) => status.is_withheld(),
+                EitherUpgrade::B(_) => panic!("Unexpected refusal"),
+            }
+        };
+
+        // Served before the behaviour tells the handler about the trust
+        assert!(!withheld(&trusted));
+        assert!(withheld(&PeerId::random()));
+    }
+
+    #[test]
+    fn request_while_outstanding() {
+        let mut handler = StatusHandler::new(StatusConfig::new([0; 20]).with_interval(Duration::from_secs(60)));
+        assert!(requests_outbound(&mut handler));
+
+        // Asked for while the first request is still outstanding
+        handler.inject_event(StatusIn::Request);
+        assert!(!requests_outbound(&mut handler));
+
+        // Its response does not postpone the next request by the interval
+        handler.inject_fully_negotiated_outbound((protocol::Response::new([0; 20]), String::new()), ());
+        match block_on(future::poll_fn(|cx| handler.poll(cx))) {
+            ProtocolsHandlerEvent::Custom(Ok(StatusSuccess::Received { .. })) => (),
+            _ => panic!("Unexpected event"),
+        }
+        assert!(requests_outbound(&mut handler));
+
+        // Afterwards the interval applies again
+        handler.inject_fully_negotiated_outbound((protocol::Response::new([0; 20]), String::new()), ());
+        match block_on(future::poll_fn(|cx| handler.poll(cx))) {
+            ProtocolsHandlerEvent::Custom(Ok(StatusSuccess::Received { .. })) => (),
+            _ => panic!("Unexpected event"),
+        }
+        assert!(!requests_outbound(&mut handler));
+    }
+
+    #[test]
+    fn scope_protocol_to_network() {
+        use libp2p::core::UpgradeInfo;
+
+        assert_eq!(&*StatusConfig::new([0; 20]).protocol_name(), protocol::PROTOCOL_NAME);
+
+        let config = StatusConfig::new([0; 20]).with_network("testnet".parse().unwrap());
+        assert_eq!(&*config.protocol_name(), &b"/dx/testnet/status/0.1.0"[..]);
+
+        let handler = StatusHandler::new(config);
+        match handler.listen_protocol().upgrade() {
+            EitherUpgrade::A(status) => assert_eq!(&*status.protocol_info().next().unwrap(), &b"/dx/testnet/status/0.1.0"[..]),
+            EitherUpgrade::B(_) => panic!("Unexpected refusal"),
+        }
+    }
+
+    #[test]
+    fn clamp_absurd_durations() {
+        let max = Duration::new(u64::max_value(), 999_999_999);
+        let config = StatusConfig::new([0; 20])
+            .with_interval(max)
+            .with_timeout(max);
+        let mut handler = StatusHandler::new(config);
+
+        // Sending the first request arms the timeout
+        assert!(requests_outbound(&mut handler));
+
+        // Receiving the response arms the interval
+        let response = protocol::Response::new([0; 20]);
+        handler.inject_fully_negotiated_outbound((response, String::new()), ());
+        assert!(!requests_outbound(&mut handler));
+    }
+
+    #[test]
+    fn separate_inbound_and_outbound_status() {
+        let config = StatusConfig::new([1; 20])
+            .with_inbound_status([0; 20]);
+        let mut handler = StatusHandler::new(config);
+
+        match handler.listen_protocol().upgrade() {
+            EitherUpgrade::A(status) => assert_eq!(status.clone().resolve().payload(), &[0; 20]),
+            EitherUpgrade::B(_) => panic!("Unexpected refusal"),
+        }
+
+        let request = block_on(future::poll_fn(|cx| handler.poll(cx)));
+        match request {
+            ProtocolsHandlerEvent::OutboundSubstreamRequest { protocol, .. } =>
+                assert_eq!(protocol.upgrade().clone().resolve().payload(), &[1; 20]),
+            _ => panic!("Unexpected event"),
+        }
+    }
+
+    #[test]
+    fn provide_changing_status() {
+        /// Counts up with every status provided
+        #[derive(Debug, Default)]
+        struct Counter(AtomicU8);
+
+        impl PayloadProvider for Counter {
+            fn current(&self) -> protocol::Payload {
+                [self.0.fetch_add(1, Ordering::SeqCst); 20]
+            }
+        }
+
+        let key = Keypair::generate_ed25519();
+        let peer = key.public().into_peer_id();
+        let remote = PeerId::random();
+        let config = StatusConfig::new([0xff; 20])
+            .with_provider(Arc::new(Counter::default()))
+            .with_keypair(&key);
+        let epoch = config.epoch();
+        let handler = StatusHandler::new(config).with_remote(remote.clone());
+
+        let upgrades: Vec<protocol::Status> = (0..3)
+            .map(|_| match handler.listen_protocol().upgrade() {
+                EitherUpgrade::A(status) => status.clone(),
+                EitherUpgrade::B(_) => panic!("Unexpected refusal"),
+            })
+            .collect();
+
+        // Statuses are taken in the order responses are written, none before
+        for (n, status) in (0..3).zip(upgrades.into_iter().rev()) {
+            let status = status.resolve();
+            assert_eq!(status.payload(), &[n; 20]);
+            assert!(status.signature().unwrap().verify(&peer, status.payload(), Some(epoch), &remote));
+        }
+    }
+
+    #[test]
+    fn negotiate_capabilities() {
+        let key = Keypair::generate_ed25519();
+        let config = StatusConfig::new([0; 20])
+            .with_keypair(&key)
+            .with_gossip(true);
+        let table = config.gossip_table();
+        table.lock().unwrap().insert_direct(PeerId::random(), [1; 20], config.clock().now());
+        let mut handler = StatusHandler::new(config).with_gossip(table).with_remote(PeerId::random());
+
+        let answer = |handler: &StatusHandler| match handler.listen_protocol().upgrade() {
+            EitherUpgrade::A(status) => status.clone().resolve(),
+            EitherUpgrade::B(_) => panic!("Unexpected refusal"),
+        };
+
+        // Remotes are assumed to support everything until they say otherwise
+        let status = answer(&handler);
+        assert_eq!(status.capabilities(), Some(protocol::Capabilities::all()));
+        assert_eq!(status.gossip().len(), 1);
+        assert!(status.signature().is_some());
+
+        let response = protocol::Response { capabilities: Some(protocol::Capabilities::SIGNATURES), ..protocol::Response::new([0; 20]) };
+        handler.inject_fully_negotiated_outbound((response, String::new()), ());
+
+        let status = answer(&handler);
+        assert!(status.gossip().is_empty());
+        assert!(status.signature().is_some());
+    }
+
+    #[test]
+    fn shutdown_releases_connection() {
+        let mut handler = StatusHandler::new(StatusConfig::new([0; 20]).with_keep_alive(true));
+        assert_eq!(handler.connection_keep_alive(), KeepAlive::Yes);
+
+        handler.inject_event(StatusIn::Shutdown);
+        assert_eq!(handler.connection_keep_alive(), KeepAlive::No);
+        assert!(!requests_outbound(&mut handler));
+
+        // Can not be undone by resuming
+        handler.inject_event(StatusIn::Resume);
+        assert!(!requests_outbound(&mut handler));
+    }
+
+    #[test]
+    fn reject_bad_signature() {
+        let local = PeerId::random();
+        let key = Keypair::generate_ed25519();
+        let remote = key.public().into_peer_id();
+        let config = StatusConfig::new([0; 20])
+            .with_require_signature(true)
+            .with_local_peer_id(local.clone())
+            .with_max_failures(NonZeroU32::new(3).unwrap());
+        let mut handler = StatusHandler::new(config).with_remote(remote.clone());
+
+        let mut receive = |payload, signature| {
+            let response = protocol::Response { signature, epoch: Some(1), ..protocol::Response::new(payload) };
+            handler.inject_fully_negotiated_outbound((response, String::new()), ());
+            block_on(future::poll_fn(|cx| handler.poll(cx)))
+        };
+
+        // Valid signature
+        let signature = protocol::Signature::sign(&key, &[1; 20], Some(1), &local).unwrap();
+        match receive([1; 20], Some(signature.clone())) {
+            ProtocolsHandlerEvent::Custom(Ok(StatusSuccess::Received { .. })) => (),
+            _ => panic!("Unexpected event"),
+        }
+
+        // Tampered payload
+        match receive([2; 20], Some(signature)) {
+            ProtocolsHandlerEvent::Custom(Err(StatusFailure::BadSignature)) => (),
+            _ => panic!("Unexpected event"),
+        }
+
+        // Addressed to another peer, i.e. replayed
+        let replayed = protocol::Signature::sign(&key, &[1; 20], Some(1), &PeerId::random()).unwrap();
+        match receive([1; 20], Some(replayed)) {
+            ProtocolsHandlerEvent::Custom(Err(StatusFailure::BadSignature)) => (),
+            _ => panic!("Unexpected event"),
+        }
+
+        // Signed by another peer, which counts as the last failure allowed
+        let other = protocol::Signature::sign(&Keypair::generate_ed25519(), &[1; 20], Some(1), &local).unwrap();
+        match receive([1; 20], Some(other)) {
+            ProtocolsHandlerEvent::Close(StatusFailure::BadSignature) => (),
+            _ => panic!("Unexpected event"),
+        }
+    }
+
+    #[test]
+    fn drain_connection() {
+        let mut handler = StatusHandler::new(StatusConfig::new([0; 20]).with_keep_alive(true));
+        assert!(requests_outbound(&mut handler));
+        handler.inject_event(StatusIn::Drain);
+
+        // The request in flight is still reported
+        handler.inject_dial_upgrade_error((), ProtocolsHandlerUpgrErr::Timeout);
+        match block_on(future::poll_fn(|cx| handler.poll(cx))) {
+            ProtocolsHandlerEvent::Close(StatusFailure::Timeout) => (),
+            _ => panic!("Unexpected event"),
+        }
+
+        // But no new ones are started or answered
+        assert_eq!(handler.connection_keep_alive(), KeepAlive::No);
+        assert!(!requests_outbound(&mut handler));
+        match handler.listen_protocol().upgrade() {
+            EitherUpgrade::A(_) => panic!("Unexpected answer"),
+            EitherUpgrade::B(_) => (),
+        }
+    }
+
+    #[test]
+    fn weighted_failures() {
+        let weights = FailureWeights { unsupported: 0.0, ..FailureWeights::default() };
+        let config = StatusConfig::new([0; 20])
+            .with_max_failures(NonZeroU32::new(2).unwrap())
+            .with_failure_weights(weights);
+        let mut handler = StatusHandler::new(config);
+
+        let fail = |handler: &mut StatusHandler, error| {
+            handler.inject_dial_upgrade_error((), error);
+            match block_on(future::poll_fn(|cx| handler.poll(cx))) {
+                ProtocolsHandlerEvent::Custom(Err(_)) => false,
+                ProtocolsHandlerEvent::Close(_) => true,
+                _ => panic!("Unexpected event"),
+            }
+        };
+
+        // Negotiation errors never close the connection
+        for _ in 0..10 {
+            let error = ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Select(NegotiationError::Failed));
+            assert!(!fail(&mut handler, error));
+        }
+
+        // Timeouts do
+        assert!(!fail(&mut handler, ProtocolsHandlerUpgrErr::Timeout));
+        assert!(fail(&mut handler, ProtocolsHandlerUpgrErr::Timeout));
+    }
+
+    #[test]
+    fn profile_presets() {
+        let default = StatusProfile::Default.config([0; 20]);
+        assert_eq!(default.interval(), Duration::from_secs(15));
+        assert_eq!(default.timeout(), Duration::from_secs(20));
+        assert_eq!(default.stall_timeout(), Duration::from_secs(5));
+        assert_eq!(default.max_failures().get(), 1);
+        assert_eq!(default.failure_window(), None);
+
+        let aggressive = StatusProfile::Aggressive.config([0; 20]);
+        assert_eq!(aggressive.interval(), Duration::from_secs(5));
+        assert_eq!(aggressive.timeout(), Duration::from_secs(5));
+        assert_eq!(aggressive.max_failures().get(), 1);
+        assert_eq!(aggressive.failure_window(), None);
+        assert_eq!(aggressive.with_stall_timeout(Duration::from_secs(2)).stall_timeout(), Duration::from_secs(2));
+
+        let relaxed = StatusProfile::Relaxed.config([0; 20]);
+        assert_eq!(relaxed.interval(), Duration::from_secs(60));
+        assert_eq!(relaxed.timeout(), Duration::from_secs(60));
+        assert_eq!(relaxed.max_failures().get(), 5);
+        assert_eq!(relaxed.failure_window(), Some(Duration::from_secs(60 * 60)));
+
+        assert_eq!(StatusProfile::from_name("relaxed"), Some(StatusProfile::Relaxed));
+        assert_eq!(StatusProfile::from_name("Relaxed"), None);
+    }
+
+    #[test]
+    fn failure_window() {
+        let clock = ManualClock::new();
+        let config = StatusConfig::new([0; 20])
+            .with_max_failures(NonZeroU32::new(3).unwrap())
+            .with_failure_window(Duration::from_secs(60))
+            .with_clock(Arc::new(clock.clone()));
+        let mut handler = StatusHandler::new(config);
+
+        let fail = |handler: &mut StatusHandler| {
+            handler.inject_dial_upgrade_error((), ProtocolsHandlerUpgrErr::Timeout);
+            match block_on(future::poll_fn(|cx| handler.poll(cx))) {
+                ProtocolsHandlerEvent::Custom(Err(_)) => false,
+                ProtocolsHandlerEvent::Close(_) => true,
+                _ => panic!("Unexpected event"),
+            }
+        };
+
+        // Failures far apart never add up
+        for _ in 0..10 {
+            assert!(!fail(&mut handler));
+            clock.advance(Duration::from_secs(60));
+        }
+        assert_eq!(handler.failures, 1.0);
+
+        // Clustered ones do
+        assert!(!fail(&mut handler));
+        clock.advance(Duration::from_secs(20));
+        assert!(!fail(&mut handler));
+        clock.advance(Duration::from_secs(20));
+        assert!(fail(&mut handler));
+    }
+
+    #[test]
+    fn request_on_schedule() {
+        let clock = ManualClock::new();
+        let interval = Duration::from_secs(15);
+        let config = StatusConfig::new([0; 20])
+            .with_interval(interval)
+            .with_clock(Arc::new(clock.clone()));
+        let mut handler = StatusHandler::new(config);
+
+        assert!(requests_outbound(&mut handler));
+
+        clock.advance(Duration::from_millis(30));
+        let response = protocol::Response::new([0; 20]);
+        handler.inject_fully_negotiated_outbound((response, String::new()), ());
+
+        match block_on(future::poll_fn(|cx| handler.poll(cx))) {
+            ProtocolsHandlerEvent::Custom(Ok(StatusSuccess::Received { rtt, .. })) =>
+                assert_eq!(rtt, Duration::from_millis(30)),
+            _ => panic!("Unexpected event"),
+        }
+
+        clock.advance(interval - Duration::from_millis(1));
+        assert!(!requests_outbound(&mut handler));
+
+        clock.advance(Duration::from_millis(1));
+        assert!(requests_outbound(&mut handler));
+    }
+
+    #[test]
+    fn scale_interval_with_rtt() {
+        let clock = ManualClock::new();
+        let adaptive = AdaptiveInterval { factor: 100, floor: Duration::from_secs(2), ceiling: Duration::from_secs(60) };
+        let config = StatusConfig::new([0; 20])
+            .with_interval(Duration::from_secs(5))
+            .with_adaptive_interval(Some(adaptive))
+            .with_clock(Arc::new(clock.clone()));
+
+        assert_eq!(config.effective_interval(Duration::from_millis(2)), Duration::from_secs(5));
+        assert_eq!(config.effective_interval(Duration::from_millis(400)), Duration::from_secs(40));
+        assert_eq!(config.effective_interval(Duration::from_secs(10)), Duration::from_secs(60));
+        assert_eq!(adaptive.apply(Duration::from_secs(1), Duration::from_millis(2)), Duration::from_secs(2));
+
+        // A high-RTT peer is requested less often than a low-RTT one
+        for (rtt, interval) in [(2, 5), (400, 40)].iter() {
+            let mut handler = StatusHandler::new(config.clone());
+            assert!(requests_outbound(&mut handler));
+
+            clock.advance(Duration::from_millis(*rtt));
+            let response = protocol::Response::new([0; 20]);
+            handler.inject_fully_negotiated_outbound((response, String::new()), ());
+            block_on(future::poll_fn(|cx| handler.poll(cx)));
+
+            clock.advance(Duration::from_secs(*interval) - Duration::from_millis(1));
+            assert!(!requests_outbound(&mut handler));
+            clock.advance(Duration::from_millis(1));
+            assert!(requests_outbound(&mut handler));
+        }
+    }
+
+    #[test]
+    fn bound_pending_results() {
+        let config = StatusConfig::new([0; 20]).with_max_pending_results(NonZeroUsize::new(3).unwrap());
+        let pending = Arc::new(PendingResults::default());
+        let mut handler = StatusHandler::new(config).with_pending_results(pending.clone());
+
+        // Flood of inbound requests never reported
+        let response = protocol::Response::new([0; 20]);
+        handler.inject_fully_negotiated_outbound((response, String::new()), ());
+        for _ in 0..5 {
+            handler.inject_fully_negotiated_inbound(EitherOutput::First(()));
+        }
+
+        // Inbound results made room, while our own response is kept
+        assert_eq!(pending.depth(), 3);
+        assert_eq!(pending.high_water(), 3);
+        assert_eq!(pending.dropped(), 3);
+        assert_eq!(handler.outbound_results.len(), 1);
+
+        // Reporting lowers the depth, but not the peak
+        match block_on(future::poll_fn(|cx| handler.poll(cx))) {
+            ProtocolsHandlerEvent::Custom(Ok(StatusSuccess::Received { .. })) => (),
+            _ => panic!("Unexpected event"),
+        }
+        assert_eq!(pending.depth(), 2);
+        assert_eq!(pending.high_water(), 3);
+
+        // Nor are results of closed connections pending any longer
+        drop(handler);
+        assert_eq!(pending.depth(), 0);
+        assert_eq!(pending.high_water(), 3);
+    }
+
+    #[test]
+    fn count_dropped_failures() {
+        let config = StatusConfig::new([0; 20])
+            .with_max_pending_results(NonZeroUsize::new(1).unwrap())
+            .with_max_failures(NonZeroU32::new(2).unwrap());
+        let pending = Arc::new(PendingResults::default());
+        let mut handler = StatusHandler::new(config).with_pending_results(pending.clone());
+
+        // The first failure makes room for the second, but still counts
+        handler.inject_dial_upgrade_error((), ProtocolsHandlerUpgrErr::Timeout);
+        handler.inject_dial_upgrade_error((), ProtocolsHandlerUpgrErr::Timeout);
+        assert_eq!(pending.dropped(), 1);
+        assert_eq!(handler.failures, 2.0);
+
+        match block_on(future::poll_fn(|cx| handler.poll(cx))) {
+            ProtocolsHandlerEvent::Close(StatusFailure::Timeout) => (),
+            _ => panic!("Unexpected event"),
+        }
+    }
+
+    #[test]
+    fn retry_on_timer_error() {
+        let mut handler = StatusHandler::new(StatusConfig::new([0; 20]).with_keep_alive(true));
+        assert!(requests_outbound(&mut handler));
+
+        // Neither reported nor counted as failure, the request is just sent again
+        handler.inject_dial_upgrade_error((), ProtocolsHandlerUpgrErr::Timer);
+        assert!(handler.outbound_results.is_empty());
+        assert_eq!(handler.failures, 0.0);
+
+        assert!(requests_outbound(&mut handler));
+        assert_eq!(handler.connection_keep_alive(), KeepAlive::Yes);
+    }
+
+    #[test]
+    fn interleave_results() {
+        let mut handler = StatusHandler::new(StatusConfig::new([0; 20]).with_keep_alive(true));
+        assert!(requests_outbound(&mut handler));
+
+        // Burst of answered inbound requests ahead of our own response
+        for _ in 0..10 {
+            handler.inject_fully_negotiated_inbound(EitherOutput::First(()));
+        }
+        let response = protocol::Response::new([0; 20]);
+        handler.inject_fully_negotiated_outbound((response, String::new()), ());
+
+        let mut results = Vec::new();
+        while let Poll::Ready(event) = handler.poll(&mut Context::from_waker(futures::task::noop_waker_ref())) {
+            match event {
+                ProtocolsHandlerEvent::Custom(Ok(success)) => results.push(success),
+                _ => break,
+            }
+        }
+
+        // Response is not held back by the burst, which is still delivered in full
+        assert_eq!(results.len(), 11);
+        assert!(results.iter().take(2).any(|result| match result {
+            StatusSuccess::Received { .. } => true,
+            _ => false,
+        }));
+        assert_eq!(results.iter().filter(|result| match result {
+            StatusSuccess::Requested => true,
+            _ => false,
+        }).count(), 10);
+    }
+
+    #[test]
+    fn map_decode_errors() {
+        let mut handler = StatusHandler::new(StatusConfig::new([0; 20]).with_max_failures(NonZeroU32::new(10).unwrap()));
+
+        let mut fail = |error| {
+            handler.inject_dial_upgrade_error((), ProtocolsHandlerUpgrErr::Upgrade(UpgradeError::Apply(error)));
+            match block_on(future::poll_fn(|cx| handler.poll(cx))) {
+                ProtocolsHandlerEvent::Custom(Err(failure)) => failure,
+                _ => panic!("Unexpected event"),
+            }
+        };
+
+        match fail(protocol::DecodeError::BadSignature) {
+            StatusFailure::BadSignature => (),
+            other => panic!("Unexpected failure: {:?}", other),
+        }
+        match fail(protocol::DecodeError::Io(io::ErrorKind::TimedOut.into())) {
+            StatusFailure::Timeout => (),
+            other => panic!("Unexpected failure: {:?}", other),
+        }
+        match fail(protocol::DecodeError::Stalled { received: 42 }) {
+            StatusFailure::Stalled { received: 42 } => (),
+            other => panic!("Unexpected failure: {:?}", other),
+        }
+        match fail(protocol::DecodeError::Io(io::ErrorKind::ConnectionReset.into())) {
+            StatusFailure::ConnectionClosed => (),
+            other => panic!("Unexpected failure: {:?}", other),
+        }
+        match fail(protocol::DecodeError::Truncated) {
+            StatusFailure::Other { .. } => (),
+            other => panic!("Unexpected failure: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn throttle_inbound_flood() {
+        let clock = ManualClock::new();
+        let limit = RateLimit { burst: 3, period: Duration::from_secs(1) };
+        let config = StatusConfig::new([0; 20])
+            .with_inbound_rate(Some(limit))
+            .with_clock(Arc::new(clock.clone()));
+        let mut handler = StatusHandler::new(config);
+
+        // Polling on a normal interval is never throttled
+        for _ in 0..10 {
+            assert!(answers_inbound(&handler));
+            clock.advance(Duration::from_secs(15));
+        }
+
+        // Flooding is, after the burst has been used up
+        let refused = (0..100).filter(|_| !answers_inbound(&handler)).count();
+        assert_eq!(refused, 97);
+
+        // Reported once, without counting as failure
+        match block_on(future::poll_fn(|cx| handler.poll(cx))) {
+            ProtocolsHandlerEvent::Custom(Err(StatusFailure::Throttled)) => (),
+            _ => panic!("Unexpected event"),
+        }
+        assert_eq!(handler.failures, 0.0);
+        assert!(requests_outbound(&mut handler));
+
+        // Tokens are refilled over time
+        clock.advance(Duration::from_millis(1500));
+        assert!(answers_inbound(&handler));
+        assert!(!answers_inbound(&handler));
+    }
+
+    #[test]
+    fn throttle_only_status_substreams() {
+        let limit = RateLimit { burst: 2, period: Duration::from_secs(60) };
+        let config = StatusConfig::new([0; 20])
+            .with_inbound_rate(Some(limit))
+            .with_max_substreams(NonZeroUsize::new(1).unwrap(), NonZeroUsize::new(1).unwrap())
+            .with_clock(Arc::new(ManualClock::new()));
+        let mut handler = StatusHandler::new(config);
+
+        // Substreams of other protocols, e.g. Kademlia or identify, are offered
+        // the status protocol as well, but negotiate another one
+        for _ in 0..100 {
+            drop(handler.listen_protocol());
+        }
+        assert!(requests_outbound(&mut handler));
+
+        // Status requests still get through, up to the limits
+        assert!(answers_inbound(&handler));
+        assert!(answers_inbound(&handler));
+        assert!(!answers_inbound(&handler));
+    }
+
+    #[test]
+    fn limit_substreams() {
+        let clock = ManualClock::new();
+        let timeout = Duration::from_secs(20);
+        let config = StatusConfig::new([0; 20])
+            .with_timeout(timeout)
+            .with_inbound_rate(None)
+            .with_max_substreams(NonZeroUsize::new(3).unwrap(), NonZeroUsize::new(2).unwrap())
+            .with_clock(Arc::new(clock.clone()));
+        let mut handler = StatusHandler::new(config);
+
+        // Substreams of other protocols never take up a slot
+        for _ in 0..10 {
+            drop(handler.listen_protocol());
+        }
+
+        // A burst of inbound requests is only answered up to the limit
+        let mut open: Vec<_> = (0..10).filter_map(|_| negotiate_inbound(&handler)).collect();
+        let refused = open.split_off(3).into_iter().map(block_on).filter(Result::is_err).count();
+        assert_eq!(refused, 7);
+        assert!(!answers_inbound(&handler));
+
+        // Answering one makes room for another
+        assert!(block_on(open.pop().unwrap()).is_ok());
+        assert!(answers_inbound(&handler));
+        open.extend(negotiate_inbound(&handler));
+        assert!(!answers_inbound(&handler));
+
+        // As does giving up on one, e.g. once its upgrade timed out
+        drop(open.pop());
+        assert!(answers_inbound(&handler));
+        drop(open);
+        assert_eq!((0..10).filter(|_| answers_inbound(&handler)).count(), 10);
+
+        // Outbound requests stop at the limit, even once the timer is due again
+        assert!(requests_outbound(&mut handler));
+        clock.advance(timeout);
+        assert!(requests_outbound(&mut handler));
+        clock.advance(timeout);
+        assert!(!requests_outbound(&mut handler));
+        assert_eq!(handler.outbound_open, 2);
+
+        // The deferred request is sent as soon as one completes
+        handler.inject_dial_upgrade_error((), ProtocolsHandlerUpgrErr::Timer);
+        assert!(requests_outbound(&mut handler));
+        assert!(!requests_outbound(&mut handler));
+    }
+
+    #[test]
+    fn bounded_keep_alive() {
+        let clock = ManualClock::new();
+        let idle = Duration::from_secs(30);
+        let config = StatusConfig::new([0; 20])
+            .with_keep_alive_until(idle)
+            .with_clock(Arc::new(clock.clone()));
+        let mut handler = StatusHandler::new(config);
+
+        let deadline = |handler: &StatusHandler| match handler.connection_keep_alive() {
+            KeepAlive::Until(deadline) => deadline,
+            other => panic!("Unexpected keep alive: {:?}", other),
+        };
+        assert_eq!(deadline(&handler), clock.now() + idle);
+        assert!(requests_outbound(&mut handler));
+
+        // Every successful exchange advances the deadline
+        clock.advance(Duration::from_secs(20));
+        let response = protocol::Response::new([0; 20]);
+        handler.inject_fully_negotiated_outbound((response, String::new()), ());
+        block_on(future::poll_fn(|cx| handler.poll(cx)));
+        assert_eq!(deadline(&handler), clock.now() + idle);
+
+        // Inactivity lets it pass, the connection may then be closed
+        clock.advance(idle + Duration::from_secs(1));
+        assert!(deadline(&handler) < clock.now());
+
+        handler.inject_event(StatusIn::Shutdown);
+        assert_eq!(handler.connection_keep_alive(), KeepAlive::No);
+    }
+}
@@ -1,8 +1,158 @@
 use super::protocol::Payload;
 
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use rand::{distributions, prelude::*};
 
 /// Generate random status payload, use as dummy for now
 pub fn generate_payload() -> Payload {
     thread_rng().sample(distributions::Standard)
 }
+
+/// Marker of payloads with an embedded build time, see [`PayloadBuilder`]
+const BUILD_TIME_MARKER: [u8; 2] = [0xd7, 0x71];
+
+/// Builds a payload from a revision, optionally embedding the build time so
+/// that receivers can order builds chronologically.
+///
+/// Without a build time, the payload is the revision as is. With one, the 20
+/// bytes are laid out as follows:
+///
+///   * bytes 0 to 13: the first 14 bytes of the revision
+///   * bytes 14 and 15: the marker `d7 71`
+///   * bytes 16 to 19: the build time in minutes since the unix epoch, big endian
+///
+/// Either way it is a plain 20 byte payload to peers that do not care about
+/// build times. A plain revision carries the marker by chance once in 65536
+/// times though, in which case [`payload_build_time`] returns nonsense.
+#[derive(Debug, Clone)]
+pub struct PayloadBuilder {
+    revision: Payload,
+    build_time: Option<SystemTime>,
+}
+
+impl PayloadBuilder {
+    /// Start from the given revision, e.g. a git hash
+    pub fn new(revision: Payload) -> Self {
+        PayloadBuilder { revision, build_time: None }
+    }
+
+    /// Start from a random revision, see [`generate_payload`]
+    pub fn random() -> Self {
+        Self::new(generate_payload())
+    }
+
+    /// Embed the given build time, only accurate to the minute
+    pub fn with_build_time(mut self, time: SystemTime) -> Self {
+        self.build_time = Some(time);
+        self
+    }
+
+    pub fn build(self) -> Payload {
+        let time = match self.build_time {
+            Some(time) => time,
+            None => return self.revision,
+        };
+
+        let secs = time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+        let minutes = (secs / 60).min(u64::from(u32::max_value())) as u32;
+
+        let mut payload = self.revision;
+        payload[14..16].copy_from_slice(&BUILD_TIME_MARKER);
+        payload[16..].copy_from_slice(&minutes.to_be_bytes());
+        payload
+    }
+}
+
+/// Build time embedded in the payload by [`PayloadBuilder`], if any
+pub fn payload_build_time(payload: &Payload) -> Option<SystemTime> {
+    if payload[14..16] != BUILD_TIME_MARKER {
+        return None
+    }
+
+    let mut minutes = [0; 4];
+    minutes.copy_from_slice(&payload[16..]);
+
+    Some(UNIX_EPOCH + Duration::from_secs(u64::from(u32::from_be_bytes(minutes)) * 60))
+}
+
+/// Render payload as 40 character hex string, like a full git hash
+pub fn payload_to_hex(payload: &Payload) -> String {
+    payload.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Parse payload from 40 character hex string, like a full git hash
+pub fn payload_from_hex(hex: &str) -> Option<Payload> {
+    if hex.len() != 2 * Payload::default().len() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None
+    }
+
+    let mut payload = Payload::default();
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+
+    Some(payload)
+}
+
+/// Read payload as 40 character hex string, e.g. as printed by `git rev-parse HEAD`
+pub fn read_payload(mut reader: impl io::Read) -> io::Result<Payload> {
+    let mut hex = String::new();
+    reader.read_to_string(&mut hex)?;
+
+    payload_from_hex(hex.trim()).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "Status is not a 40 character hex string")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PayloadBuilder, generate_payload, payload_build_time, payload_from_hex, payload_to_hex, read_payload};
+    use std::io::Cursor;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn hex_round_trip() {
+        let payload = generate_payload();
+        let hex = payload_to_hex(&payload);
+
+        assert_eq!(hex.len(), 40);
+        assert_eq!(payload_from_hex(&hex), Some(payload));
+        assert_eq!(payload_from_hex(&hex.to_uppercase()), Some(payload));
+
+        let rev = "1a53afd0c2b1e6a0f4e1b2c3d4e5f60718293a4b";
+        assert_eq!(payload_to_hex(&payload_from_hex(rev).unwrap()), rev);
+
+        assert_eq!(payload_from_hex(&rev[..7]), None);
+        assert_eq!(payload_from_hex(&rev.replace('a', "g")), None);
+    }
+
+    #[test]
+    fn embed_build_time() {
+        let rev = payload_from_hex("1a53afd0c2b1e6a0f4e1b2c3d4e5f60718293a4b").unwrap();
+        let minute = UNIX_EPOCH + Duration::from_secs(27_000_000 * 60);
+
+        // Build time survives to the minute, next to most of the revision
+        let payload = PayloadBuilder::new(rev).with_build_time(minute + Duration::from_secs(59)).build();
+        assert_eq!(payload[..14], rev[..14]);
+        assert_eq!(payload_build_time(&payload), Some(minute));
+
+        let later = PayloadBuilder::random().with_build_time(minute + Duration::from_secs(60)).build();
+        assert!(payload_build_time(&later).unwrap() > payload_build_time(&payload).unwrap());
+
+        // Plain payloads are left alone and carry no build time
+        assert_eq!(PayloadBuilder::new(rev).build(), rev);
+        assert_eq!(payload_build_time(&rev), None);
+        assert_eq!(payload_build_time(&[0; 20]), None);
+    }
+
+    #[test]
+    fn read_hex_status() {
+        let payload = read_payload(Cursor::new("00112233445566778899aabbccddeeff01234567\n")).unwrap();
+        assert_eq!(payload[..4], [0x00, 0x11, 0x22, 0x33]);
+        assert_eq!(payload[19], 0x67);
+
+        assert!(read_payload(Cursor::new("00112233445566778899aabbccddeeff")).is_err());
+    }
+}
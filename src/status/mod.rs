@@ -18,7 +18,8 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-//! This module implements the `/dx/status/0.1.0` protocol.
+//! This module implements the `/dx/status/0.1.0` protocol, or `/dx/<network>/status/0.1.0`
+//! if scoped to a network.
 //!
 //! It is based on the IPFS ping protocol and can be used as a simple application-layer
 //! health check for connections of any [`Transport`]. It is the plan to extend it to a
@@ -44,23 +45,25 @@
 pub mod protocol;
 pub mod handler;
 pub mod helper;
+pub mod cache;
+pub mod provider;
 
-pub use protocol::Payload;
+pub use protocol::{Capabilities, DecodeError, Payload, Gossip, Response, Signature, VERSION};
 
-pub use handler::{StatusConfig, StatusResult, StatusSuccess, StatusFailure};
-use handler::StatusHandler;
+pub use handler::{StatusConfig, StatusProfile, StatusResult, StatusSuccess, StatusFailure, StatusIn, AdaptiveInterval, FailureWeights, PendingResults, RateLimit};
+use handler::{IntoStatusHandler, StatusHandler, GossipTable};
 
-pub use helper::generate_payload;
+pub use helper::{PayloadBuilder, generate_payload, payload_build_time, payload_from_hex, payload_to_hex, read_payload};
+pub use provider::{PayloadProvider, SharedProvider, StaticProvider};
 
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use libp2p::core::{ConnectedPoint, Multiaddr, PeerId};
 use libp2p::swarm::{NetworkBehaviour, NetworkBehaviourAction, PollParameters};
 
-use void::Void;
-
 
 /// `Status` is a [`NetworkBehaviour`] that responds to inbound statuss and
 /// periodically sends outbound statuss on every established connection.
@@ -69,8 +72,46 @@ use void::Void;
 pub struct Status {
     /// Configuration for outbound statuss.
     config: StatusConfig,
-    /// Queue of events to yield to the swarm.
-    events: VecDeque<StatusEvent>,
+    /// Queue of actions to yield to the swarm.
+    events: VecDeque<NetworkBehaviourAction<StatusIn, StatusEvent>>,
+    /// Statistics of the exchanges with every connected peer.
+    stats: HashMap<PeerId, StatusStats>,
+    /// Peers for which outbound requests are suspended.
+    paused: HashSet<PeerId>,
+    /// Peers our status is served to, if only served to trusted ones.
+    trusted: HashSet<PeerId>,
+    /// Whether the local node is shutting down.
+    draining: bool,
+    /// Last known statuses of all peers, directly received or gossiped.
+    known: GossipTable,
+    /// Results of all handlers waiting to be reported.
+    pending: Arc<PendingResults>,
+}
+
+/// Whether status requests are sent to a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusState {
+    /// Requests are sent periodically.
+    Active,
+    /// Outbound requests are suspended, inbound ones are still answered.
+    Paused,
+}
+
+/// Statistics of the status exchange with a connected peer.
+#[derive(Debug, Clone)]
+pub struct StatusStats {
+    /// Whether requests are currently sent.
+    pub state: StatusState,
+    /// The number of successfully received statuses.
+    pub received: u32,
+    /// The number of failed requests.
+    pub failures: u32,
+}
+
+impl StatusStats {
+    fn new(state: StatusState) -> Self {
+        StatusStats { state, received: 0, failures: 0 }
+    }
 }
 
 /// Event generated by the `Status` network behaviour.
@@ -86,10 +127,145 @@ impl Status {
     /// Creates a new `Status` network behaviour with the given configuration.
     pub fn new(config: StatusConfig) -> Self {
         Status {
-            config,
             events: VecDeque::new(),
+            stats: HashMap::new(),
+            paused: HashSet::new(),
+            trusted: HashSet::new(),
+            draining: false,
+            known: config.gossip_table(),
+            pending: Arc::new(PendingResults::default()),
+            config,
         }
     }
+
+    /// Remember the statuses contained in a response for gossiping.
+    ///
+    /// Payloads in a schema we do not understand are not passed on either,
+    /// nor are withheld ones.
+    fn learn(&mut self, peer: &PeerId, response: &Response) {
+        if response.withheld || !response.schema_matches(self.config.schema()) {
+            return
+        }
+
+        let now = self.config.clock().now();
+        let mut known = self.known.lock().unwrap();
+
+        known.insert_direct(peer.clone(), response.payload, now);
+
+        for gossip in response.gossip.iter() {
+            if &gossip.peer == peer {
+                continue
+            }
+
+            if let Some(seen) = now.checked_sub(gossip.age) {
+                known.insert_indirect(gossip.peer.clone(), gossip.payload, seen);
+            }
+        }
+    }
+
+    /// Suspend outbound status requests to the given peer until resumed.
+    pub fn pause(&mut self, peer: &PeerId) {
+        if self.paused.insert(peer.clone()) {
+            if let Some(stats) = self.stats.get_mut(peer) {
+                stats.state = StatusState::Paused;
+            }
+            self.events.push_front(NetworkBehaviourAction::SendEvent {
+                peer_id: peer.clone(),
+                event: StatusIn::Pause,
+            });
+        }
+    }
+
+    /// Resume outbound status requests to the given peer.
+    pub fn resume(&mut self, peer: &PeerId) {
+        if self.paused.remove(peer) {
+            if let Some(stats) = self.stats.get_mut(peer) {
+                stats.state = StatusState::Active;
+            }
+            self.events.push_front(NetworkBehaviourAction::SendEvent {
+                peer_id: peer.clone(),
+                event: StatusIn::Resume,
+            });
+        }
+    }
+
+    /// Send a status request to the given peer right away, instead of at the
+    /// end of the current interval. Nothing is sent to peers that are paused
+    /// or not connected.
+    pub fn request(&mut self, peer: &PeerId) {
+        if self.stats.contains_key(peer) && !self.paused.contains(peer) {
+            self.events.push_front(NetworkBehaviourAction::SendEvent {
+                peer_id: peer.clone(),
+                event: StatusIn::Request,
+            });
+        }
+    }
+
+    /// Serve our status to the given peer from now on, if only trusted
+    /// peers are served, see [`StatusConfig::with_trusted_only`].
+    pub fn trust(&mut self, peer: &PeerId) {
+        if self.trusted.insert(peer.clone()) {
+            self.notify_trust(peer, StatusIn::Serve);
+        }
+    }
+
+    /// Withhold our status from the given peer from now on, if only trusted
+    /// peers are served.
+    pub fn distrust(&mut self, peer: &PeerId) {
+        if self.trusted.remove(peer) {
+            self.notify_trust(peer, StatusIn::Withhold);
+        }
+    }
+
+    fn notify_trust(&mut self, peer: &PeerId, event: StatusIn) {
+        if self.config.trusted_only() && self.stats.contains_key(peer) {
+            self.events.push_front(NetworkBehaviourAction::SendEvent {
+                peer_id: peer.clone(),
+                event,
+            });
+        }
+    }
+
+    /// Stop sending status requests to the given peer and release the
+    /// connection, i.e. no longer keep it alive.
+    ///
+    /// This lasts for the current connection only, new connections are
+    /// handled as usual.
+    pub fn shutdown(&mut self, peer: &PeerId) {
+        self.paused.remove(peer);
+        self.events.push_front(NetworkBehaviourAction::SendEvent {
+            peer_id: peer.clone(),
+            event: StatusIn::Shutdown,
+        });
+    }
+
+    /// Stop exchanging statuses with all peers, connected now or later, while
+    /// finishing the exchanges in flight, and release all connections.
+    pub fn drain(&mut self) {
+        self.draining = true;
+
+        for peer in self.stats.keys() {
+            self.events.push_front(NetworkBehaviourAction::SendEvent {
+                peer_id: peer.clone(),
+                event: StatusIn::Drain,
+            });
+        }
+    }
+
+    /// Whether the status exchange is shutting down, see [`Status::drain`].
+    pub fn is_draining(&self) -> bool {
+        self.draining
+    }
+
+    /// Retrieve statistics of the status exchange with a connected peer.
+    pub fn stats(&self, peer: &PeerId) -> Option<&StatusStats> {
+        self.stats.get(peer)
+    }
+
+    /// Results of all connections waiting to be reported.
+    pub fn pending_results(&self) -> &PendingResults {
+        &self.pending
+    }
 }
 
 impl Default for Status {
@@ -100,33 +276,143 @@ impl Default for Status {
 
 impl NetworkBehaviour for Status
 {
-    type ProtocolsHandler = StatusHandler;
+    type ProtocolsHandler = IntoStatusHandler;
     type OutEvent = StatusEvent;
 
     fn new_handler(&mut self) -> Self::ProtocolsHandler {
-        StatusHandler::new(self.config.clone())
+        IntoStatusHandler::new(StatusHandler::new(self.config.clone())
+            .with_gossip(self.known.clone())
+            .with_pending_results(self.pending.clone()))
+            .with_trusted(if self.config.trusted_only() { self.trusted.clone() } else { HashSet::new() })
     }
 
     fn addresses_of_peer(&mut self, _peer_id: &PeerId) -> Vec<Multiaddr> {
         Vec::new()
     }
 
-    fn inject_connected(&mut self, _: PeerId, _: ConnectedPoint) {}
+    fn inject_connected(&mut self, peer: PeerId, _: ConnectedPoint) {
+        // New handlers start active, so tell them if they should not be
+        let state = if self.paused.contains(&peer) {
+            self.events.push_front(NetworkBehaviourAction::SendEvent {
+                peer_id: peer.clone(),
+                event: StatusIn::Pause,
+            });
+            StatusState::Paused
+        } else {
+            StatusState::Active
+        };
+
+        if self.draining {
+            self.events.push_front(NetworkBehaviourAction::SendEvent {
+                peer_id: peer.clone(),
+                event: StatusIn::Drain,
+            });
+        }
+
+        // Handlers start out knowing whether the peer is trusted, but it may
+        // have changed since they were created
+        if self.config.trusted_only() {
+            let event = if self.trusted.contains(&peer) { StatusIn::Serve } else { StatusIn::Withhold };
+            self.events.push_front(NetworkBehaviourAction::SendEvent {
+                peer_id: peer.clone(),
+                event,
+            });
+        }
+
+        self.stats.insert(peer, StatusStats::new(state));
+    }
 
-    fn inject_disconnected(&mut self, _: &PeerId, _: ConnectedPoint) {}
+    fn inject_disconnected(&mut self, peer: &PeerId, _: ConnectedPoint) {
+        self.stats.remove(peer);
+    }
 
     fn inject_node_event(&mut self, peer: PeerId, result: StatusResult) {
-        self.events.push_front(StatusEvent { peer, result })
+        // Signatures were already checked by the handler
+        if let Ok(StatusSuccess::Received { response, .. }) = &result {
+            self.learn(&peer, response);
+        }
+
+        if let Some(stats) = self.stats.get_mut(&peer) {
+            match result {
+                Ok(StatusSuccess::Received { .. }) => stats.received += 1,
+                Err(..) => stats.failures += 1,
+                _ => (),
+            }
+        }
+
+        self.events.push_front(NetworkBehaviourAction::GenerateEvent(StatusEvent { peer, result }))
     }
 
     fn poll(&mut self, _: &mut Context, _: &mut impl PollParameters)
-            -> Poll<NetworkBehaviourAction<Void, StatusEvent>>
+            -> Poll<NetworkBehaviourAction<StatusIn, StatusEvent>>
     {
         if let Some(e) = self.events.pop_back() {
-            Poll::Ready(NetworkBehaviourAction::GenerateEvent(e))
+            Poll::Ready(e)
         } else {
             Poll::Pending
         }
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::node::tests::{memory_addr, memory_transport, timeout};
+
+    use futures::{future, prelude::*};
+    use libp2p::{Swarm, identity::Keypair, swarm::SwarmEvent};
+    use std::time::Duration;
+
+    #[test]
+    fn gossip_between_peers() {
+        let swarm = || {
+            let key = Keypair::generate_ed25519();
+            let id = key.public().into_peer_id();
+            let config = StatusConfig::new(generate_payload())
+                .with_gossip(true)
+                .with_keep_alive(true)
+                .with_interval(Duration::from_millis(50));
+            Swarm::new(memory_transport(key), Status::new(config), id)
+        };
+
+        let (mut a, mut b, mut c) = (swarm(), swarm(), swarm());
+        let a_id = Swarm::local_peer_id(&a).clone();
+        let b_id = Swarm::local_peer_id(&b).clone();
+        let c_id = Swarm::local_peer_id(&c).clone();
+
+        // B and C only learn about each other from A, which is connected to both
+        for leaf in [&mut b, &mut c].iter_mut() {
+            let addr = memory_addr();
+            Swarm::listen_on(leaf, addr.clone()).unwrap();
+            Swarm::dial_addr(&mut a, addr).unwrap();
+        }
+
+        let mut learned = HashSet::new();
+        timeout(async {
+            while learned.len() < 2 {
+                let events = vec![
+                    a.next_event().map(|_| None).boxed_local(),
+                    b.next_event().map(|event| Some((b_id.clone(), event))).boxed_local(),
+                    c.next_event().map(|event| Some((c_id.clone(), event))).boxed_local(),
+                ];
+
+                let (receiver, response) = match future::select_all(events).await.0 {
+                    Some((receiver, SwarmEvent::Behaviour(StatusEvent { peer, result: Ok(StatusSuccess::Received { response, .. }) }))) => {
+                        assert_eq!(peer, a_id);
+                        (receiver, response)
+                    },
+                    _ => continue,
+                };
+
+                // Nobody is told its own status
+                assert!(response.gossip.iter().all(|gossip| gossip.peer != receiver));
+
+                if !response.gossip.is_empty() {
+                    learned.insert(receiver);
+                }
+            }
+        });
+    }
+}
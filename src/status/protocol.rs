@@ -18,16 +18,326 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
-use std::{io, iter};
+use std::{borrow::Cow, cmp, error, fmt, io, iter, ops, pin::Pin, time::Duration};
+use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+use std::task::{Context, Poll};
 
-use futures::{future::BoxFuture, prelude::*};
+use futures::{future::{self, BoxFuture, Either}, prelude::*};
+use log::warn;
 
-use libp2p::core::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use libp2p::core::{
+    InboundUpgrade,
+    OutboundUpgrade,
+    UpgradeInfo,
+    PeerId,
+    identity::{Keypair, PublicKey, error::SigningError},
+};
+
+use crate::clock::{SharedClock, Timer};
+use crate::status::provider::SharedProvider;
+use crate::trust::SharedSigner;
 
 
 /// Payload type of exchanged status information
 pub type Payload = [u8; 20];
 
+/// Version of the status frame, sent as its first byte.
+///
+/// This is independent of the negotiated protocol string and allows peers
+/// to detect incompatible implementations without failing the negotiation.
+pub const VERSION: u8 = 1;
+
+/// Name the status protocol is negotiated as outside of any network, see
+/// [`Status::with_protocol_name`].
+pub const PROTOCOL_NAME: &[u8] = b"/dx/status/0.1.0";
+
+/// Maximum length of a variable-length field of a status frame, which
+/// bounds the size of any buffer allocated while decoding one.
+pub const MAX_FIELD_LEN: usize = u16::max_value() as usize;
+
+/// Payload sent in place of our status to peers it is withheld from, which
+/// only peers predating the withheld flag take for a status.
+pub const WITHHELD: Payload = [0; 20];
+
+/// Flag of a status frame telling that the status is withheld.
+const FLAG_WITHHELD: u8 = 1;
+
+/// Failure to decode a status frame.
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The frame ended early.
+    Truncated,
+    /// A length prefix is zero where data is required.
+    BadLength,
+    /// The frame version is not supported, version 0 is reserved.
+    UnsupportedVersion { version: u8 },
+    /// The public key or signature of the payload is malformed.
+    BadSignature,
+    /// A gossiped peer id is malformed.
+    BadPeerId,
+    /// The node name is not valid UTF-8.
+    BadName,
+    /// The payload schema is not valid UTF-8.
+    BadSchema,
+    /// Reading from the socket failed.
+    Io(io::Error),
+    /// No data arrived for longer than the stall timeout, after the given
+    /// number of bytes of the response had been received.
+    Stalled { received: usize },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Truncated => f.write_str("Status frame truncated"),
+            DecodeError::BadLength => f.write_str("Invalid length in status frame"),
+            DecodeError::UnsupportedVersion { version } => write!(f, "Unsupported status frame version {}", version),
+            DecodeError::BadSignature => f.write_str("Malformed status signature"),
+            DecodeError::BadPeerId => f.write_str("Invalid gossip peer id"),
+            DecodeError::BadName => f.write_str("Invalid node name"),
+            DecodeError::BadSchema => f.write_str("Invalid payload schema"),
+            DecodeError::Io(error) => write!(f, "Failed to read status: {}", error),
+            DecodeError::Stalled { received } => write!(f, "Status response stalled after {} bytes", received),
+        }
+    }
+}
+
+impl error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DecodeError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for DecodeError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof => DecodeError::Truncated,
+            _ => DecodeError::Io(error),
+        }
+    }
+}
+
+/// Maximum number of bytes read from the socket at once.
+pub const CHUNK_SIZE: usize = 4096;
+
+/// Set of optional features of the status protocol supported by a peer.
+///
+/// Peers only use the features both of them support, see
+/// [`Capabilities::intersection`]. Bits unknown to us are kept, so that
+/// they simply never end up in the intersection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    /// Statuses of other peers are accepted along with the own one.
+    pub const GOSSIP: Capabilities = Capabilities(1);
+    /// Signed statuses are understood.
+    pub const SIGNATURES: Capabilities = Capabilities(1 << 1);
+
+    /// No optional features at all.
+    pub fn empty() -> Self {
+        Capabilities(0)
+    }
+
+    /// All optional features implemented by this version.
+    pub fn all() -> Self {
+        Capabilities::GOSSIP | Capabilities::SIGNATURES
+    }
+
+    /// Capabilities as encoded in a status frame.
+    pub fn from_bits(bits: u32) -> Self {
+        Capabilities(bits)
+    }
+
+    /// Encoding of the capabilities in a status frame.
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Whether all of the given capabilities are included.
+    pub fn contains(self, other: Capabilities) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Capabilities included in both sets, i.e. those two peers agree on.
+    pub fn intersection(self, other: Capabilities) -> Self {
+        Capabilities(self.0 & other.0)
+    }
+}
+
+impl ops::BitOr for Capabilities {
+    type Output = Capabilities;
+
+    fn bitor(self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 | other.0)
+    }
+}
+
+/// Status of another peer as last known by the remote.
+#[derive(Debug, Clone)]
+pub struct Gossip {
+    /// The peer the status belongs to.
+    pub peer: PeerId,
+    /// The last known status of that peer.
+    pub payload: Payload,
+    /// How long ago the remote learned about this status.
+    pub age: Duration,
+}
+
+/// Response received from the remote.
+#[derive(Debug, Clone)]
+pub struct Response {
+    /// The frame version used by the remote.
+    pub version: u8,
+    /// The status of the remote itself.
+    pub payload: Payload,
+    /// Statuses of other peers relayed by the remote.
+    pub gossip: Vec<Gossip>,
+    /// Signature of the payload by the remote, if it signs its status.
+    pub signature: Option<Signature>,
+    /// Human readable name of the remote, e.g. its hostname or role.
+    pub name: Option<String>,
+    /// Epoch of the remote, which changes whenever it restarts.
+    pub epoch: Option<u64>,
+    /// Identifier of the format of the payload, if the remote declares one.
+    pub schema: Option<String>,
+    /// Optional features supported by the remote, unless it predates them.
+    pub capabilities: Option<Capabilities>,
+    /// Whether the remote withholds its status from us, in which case the
+    /// payload tells nothing about it.
+    pub withheld: bool,
+}
+
+impl Response {
+    /// Response of the current frame version carrying only the payload.
+    pub fn new(payload: Payload) -> Self {
+        Response {
+            version: VERSION,
+            payload,
+            gossip: Vec::new(),
+            signature: None,
+            name: None,
+            epoch: None,
+            schema: None,
+            capabilities: None,
+            withheld: false,
+        }
+    }
+
+    /// Whether the payload is in a format we understand given our own schema,
+    /// i.e. neither side declares one or both declare the same.
+    pub fn schema_matches(&self, ours: Option<&str>) -> bool {
+        match (ours, self.schema()) {
+            (Some(ours), Some(theirs)) => ours == theirs,
+            _ => true,
+        }
+    }
+
+    /// The identifier of the payload schema declared by the remote, if any.
+    pub fn schema(&self) -> Option<&str> {
+        self.schema.as_ref().map(String::as_str)
+    }
+}
+
+/// Message actually signed for a status payload, which binds the payload to
+/// the epoch of the signer and to the peer it is sent to, so that a captured
+/// response can neither be replayed to other peers nor after a restart.
+///
+/// It is made up of the payload, the epoch (8 bytes, big endian, zero if
+/// none) and the peer id of the recipient.
+pub fn signed_message(payload: &Payload, epoch: Option<u64>, recipient: &PeerId) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(payload.len() + 8 + recipient.as_bytes().len());
+    msg.extend_from_slice(payload);
+    msg.extend_from_slice(&epoch.unwrap_or(0).to_be_bytes());
+    msg.extend_from_slice(recipient.as_bytes());
+    msg
+}
+
+/// Signature of a status payload, made by the peer the status belongs to.
+///
+/// Only the payload of the remote itself is signed, gossiped statuses are not.
+#[derive(Debug, Clone)]
+pub struct Signature {
+    /// The public key of the signing peer.
+    pub key: PublicKey,
+    /// The signature of the message, see [`signed_message`].
+    pub bytes: Vec<u8>,
+}
+
+impl Signature {
+    /// Signs the payload sent with the given epoch to the recipient with the keypair.
+    pub fn sign(keypair: &Keypair, payload: &Payload, epoch: Option<u64>, recipient: &PeerId) -> Result<Self, SigningError> {
+        let msg = signed_message(payload, epoch, recipient);
+        Ok(Signature { key: keypair.public(), bytes: keypair.sign(&msg)? })
+    }
+
+    /// Whether this is a valid signature of the payload made by the given peer,
+    /// which sent it with the given epoch to the recipient.
+    pub fn verify(&self, peer: &PeerId, payload: &Payload, epoch: Option<u64>, recipient: &PeerId) -> bool {
+        &self.key.clone().into_peer_id() == peer
+            && self.key.verify(&signed_message(payload, epoch, recipient), &self.bytes)
+    }
+}
+
+/// Gate inbound requests pass once the status protocol has been negotiated
+/// for them, e.g. to limit how many requests of a peer are answered, see
+/// [`Status::with_admission`].
+pub trait Admission: fmt::Debug + Send + Sync {
+    /// Whether the request is answered, counting it as open if so
+    fn admit(&self) -> bool;
+
+    /// Notes that an admitted request was answered or given up
+    fn release(&self);
+}
+
+/// Gate shared by all inbound requests of a connection
+pub type SharedAdmission = Arc<dyn Admission>;
+
+/// Admitted request, released once dropped
+struct Admitted(SharedAdmission);
+
+impl Drop for Admitted {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+/// Signer of the local peer, only showing its public key when debugged.
+#[derive(Clone)]
+pub struct Signer(SharedSigner);
+
+impl Signer {
+    pub fn new(keypair: Keypair) -> Self {
+        Signer(Arc::new(keypair))
+    }
+
+    /// Signs with the given signer, e.g. an external one holding the key.
+    pub fn from_shared(signer: SharedSigner) -> Self {
+        Signer(signer)
+    }
+
+    /// Signature of the payload sent with the given epoch to the recipient,
+    /// if signing succeeds.
+    pub fn sign(&self, payload: &Payload, epoch: Option<u64>, recipient: &PeerId) -> Option<Signature> {
+        match self.0.sign(&signed_message(payload, epoch, recipient)) {
+            Ok(bytes) => Some(Signature { key: self.0.public(), bytes }),
+            Err(error) => {
+                warn!("Failed to sign status, sending it unsigned: {:?}", error);
+                None
+            },
+        }
+    }
+}
+
+impl fmt::Debug for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Signer").field(&self.0.public()).finish()
+    }
+}
+
 /// Represents a prototype for an upgrade to handle the status protocol.
 ///
 /// In this preliminary implementation the status is made up of a 20 bytes
@@ -37,22 +347,502 @@ pub type Payload = [u8; 20];
 /// The protocol works the following way:
 ///
 /// - Dialer sends status request.
-/// - Listener receives request and sends back status.
+/// - Listener receives request and sends back its frame version and status,
+///   followed by the statuses it knows of other peers (if any).
 /// - Dialer receives the data and returns it via event.
 ///
-/// The dialer produces a 20-byte array, which corresponds to the received payload.
-#[derive(Default, Debug, Copy, Clone)]
-pub struct Status ( pub Payload );
+/// The dialer produces a [`Response`] containing the received payload and gossip,
+/// along with the negotiated protocol name.
+///
+/// Each gossip entry is encoded as the length of the peer id (1 byte), the
+/// peer id, its payload (20 bytes) and its age in seconds (4 bytes, big endian),
+/// preceded by the number of entries (1 byte).
+///
+/// Next is the protobuf encoded public key of the listener and its signature
+/// of the payload, bound to its epoch and the dialer (see [`signed_message`]),
+/// each preceded by its length (2 bytes, big endian). Unsigned frames have a
+/// key length of zero instead.
+///
+/// Then comes the node name of the listener as UTF-8, preceded by its length
+/// (2 bytes, big endian), which is zero if it has none.
+///
+/// Next is the epoch of the listener (8 bytes, big endian), chosen anew whenever
+/// it starts, so that restarts can be told apart from an unchanged status. It
+/// is zero if the listener has none but sends any of the fields below.
+///
+/// Then comes the identifier of the payload schema as UTF-8, preceded by its
+/// length (2 bytes, big endian), which is zero if the payload is raw bytes.
+///
+/// Then come the capabilities of the listener (4 bytes, big endian), see
+/// [`Capabilities`].
+///
+/// The frame ends with flags (1 byte), of which only the lowest bit is used,
+/// telling that the status is withheld. They are left out if none is set.
+///
+/// Frames of older listeners end before the name, the epoch, the schema, the
+/// capabilities or the flags, which are then treated as missing.
+#[derive(Default, Debug, Clone)]
+pub struct Status {
+    payload: Payload,
+    gossip: Vec<Gossip>,
+    signature: Option<Signature>,
+    name: Option<String>,
+    epoch: Option<u64>,
+    schema: Option<String>,
+    capabilities: Option<Capabilities>,
+    /// Peer the status is sent to, which the signature is bound to.
+    recipient: Option<PeerId>,
+    /// Source of the payload and its signer, consulted when answering.
+    provider: Option<(SharedProvider, Option<Signer>)>,
+    /// Clock and duration without data after which a response is given up.
+    stall: Option<(SharedClock, Duration)>,
+    /// Whether this stands in for our status, which is withheld.
+    withheld: bool,
+    /// Name the protocol is negotiated as, [`PROTOCOL_NAME`] if not set.
+    protocol: Option<Cow<'static, [u8]>>,
+    /// Gate requests pass before they are answered, if any.
+    admission: Option<SharedAdmission>,
+}
+
+impl Status {
+    /// Status advertising the given payload, without anything else.
+    pub fn new(payload: Payload) -> Self {
+        Status { payload, ..Status::default() }
+    }
+
+    /// Status telling nothing about us, sent to peers our status is withheld
+    /// from. It is flagged as such and its payload is [`WITHHELD`], without
+    /// gossip, name or epoch.
+    pub fn withheld() -> Self {
+        Status { withheld: true, ..Status::new(WITHHELD) }
+    }
+
+    /// Status advertising whatever payload the provider has at the time the
+    /// request is answered, signed with the signer if any and the recipient
+    /// is known. This replaces any payload or signature set otherwise.
+    pub fn from_provider(provider: SharedProvider, signer: Option<Signer>) -> Self {
+        Status { provider: Some((provider, signer)), ..Status::default() }
+    }
+
+    /// Takes the payload from the provider, if any, and signs it.
+    ///
+    /// Done just before the status is written, so the payload and signature
+    /// are only known to the resolved status.
+    pub fn resolve(mut self) -> ResolvedStatus {
+        if let Some((provider, signer)) = self.provider.take() {
+            self.payload = provider.current();
+            self.signature = match (signer, &self.recipient) {
+                (Some(signer), Some(recipient)) => signer.sign(&self.payload, self.epoch, recipient),
+                _ => None,
+            };
+        }
+        ResolvedStatus(self)
+    }
+
+    /// Gives up reading the response once no data arrived for the given
+    /// duration, as measured by the clock, before the request timeout.
+    pub fn with_stall_timeout(mut self, clock: SharedClock, d: Duration) -> Self {
+        self.stall = Some((clock, d));
+        self
+    }
+
+    /// Sets the statuses of other peers sent along.
+    pub fn with_gossip(mut self, gossip: Vec<Gossip>) -> Self {
+        self.gossip = gossip;
+        self
+    }
+
+    /// Sets the signature of the payload, if any.
+    pub fn with_signature(mut self, signature: Option<Signature>) -> Self {
+        self.signature = signature;
+        self
+    }
+
+    /// Sets the node name, if any.
+    pub fn with_name(mut self, name: Option<String>) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Sets the name the protocol is negotiated as, e.g. one scoped to a
+    /// network, so that peers of other networks do not answer.
+    pub fn with_protocol_name(mut self, name: Cow<'static, [u8]>) -> Self {
+        self.protocol = Some(name);
+        self
+    }
+
+    /// Only answers requests the gate admits, closing the substream of any
+    /// other without a response.
+    ///
+    /// Unlike refusing the protocol up front, this only counts substreams the
+    /// status protocol was actually negotiated for, not those of other
+    /// protocols of the connection.
+    pub fn with_admission(mut self, admission: SharedAdmission) -> Self {
+        self.admission = Some(admission);
+        self
+    }
+
+    /// Sets the epoch, if any. An epoch of zero is received as none.
+    pub fn with_epoch(mut self, epoch: Option<u64>) -> Self {
+        self.epoch = epoch;
+        self
+    }
+
+    /// Sets the identifier of the payload schema, if any.
+    pub fn with_schema(mut self, schema: Option<String>) -> Self {
+        self.schema = schema;
+        self
+    }
+
+    /// Sets the advertised capabilities, if any.
+    pub fn with_capabilities(mut self, capabilities: Option<Capabilities>) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Sets the peer the status is sent to, if known, which the signature
+    /// of a provided payload is bound to.
+    pub fn with_recipient(mut self, recipient: Option<PeerId>) -> Self {
+        self.recipient = recipient;
+        self
+    }
+
+    /// The statuses of other peers sent along.
+    pub fn gossip(&self) -> &[Gossip] {
+        &self.gossip
+    }
+
+    /// The node name, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(String::as_str)
+    }
+
+    /// The epoch, if any.
+    pub fn epoch(&self) -> Option<u64> {
+        self.epoch
+    }
+
+    /// The identifier of the payload schema, if any.
+    pub fn schema(&self) -> Option<&str> {
+        self.schema.as_ref().map(String::as_str)
+    }
+
+    /// The advertised capabilities, if any.
+    pub fn capabilities(&self) -> Option<Capabilities> {
+        self.capabilities
+    }
+
+    /// Whether this stands in for a withheld status, see [`Status::withheld`].
+    pub fn is_withheld(&self) -> bool {
+        self.withheld
+    }
+}
+
+/// Status as it is written, with the payload taken from the provider, if
+/// any, and signed, see [`Status::resolve`].
+#[derive(Debug, Clone)]
+pub struct ResolvedStatus(Status);
+
+impl ResolvedStatus {
+    /// The advertised payload.
+    pub fn payload(&self) -> &Payload {
+        &self.0.payload
+    }
+
+    /// The signature of the payload, if any.
+    pub fn signature(&self) -> Option<&Signature> {
+        self.0.signature.as_ref()
+    }
+
+    /// The statuses of other peers sent along.
+    pub fn gossip(&self) -> &[Gossip] {
+        self.0.gossip()
+    }
+
+    /// The node name, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.0.name()
+    }
+
+    /// The epoch, if any.
+    pub fn epoch(&self) -> Option<u64> {
+        self.0.epoch()
+    }
+
+    /// The identifier of the payload schema, if any.
+    pub fn schema(&self) -> Option<&str> {
+        self.0.schema()
+    }
+
+    /// The advertised capabilities, if any.
+    pub fn capabilities(&self) -> Option<Capabilities> {
+        self.0.capabilities()
+    }
+
+    /// Whether this stands in for a withheld status.
+    pub fn is_withheld(&self) -> bool {
+        self.0.is_withheld()
+    }
+}
 
 impl UpgradeInfo for Status {
-    type Info = &'static [u8];
+    type Info = Cow<'static, [u8]>;
     type InfoIter = iter::Once<Self::Info>;
 
     fn protocol_info(&self) -> Self::InfoIter {
-        iter::once(b"/dx/status/0.1.0")
+        iter::once(self.protocol.clone().unwrap_or(Cow::Borrowed(PROTOCOL_NAME)))
+    }
+}
+
+
+/// Writes the status frame, without flushing the socket.
+pub async fn write_status<S>(socket: &mut S, status: &ResolvedStatus) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let status = &status.0;
+
+    socket.write_all(&[VERSION]).await?;
+    socket.write_all(&status.payload).await?;
+
+    let count = cmp::min(status.gossip.len(), u8::max_value() as usize);
+    socket.write_all(&[count as u8]).await?;
+
+    for gossip in status.gossip.iter().take(count) {
+        let peer = gossip.peer.as_bytes();
+        socket.write_all(&[peer.len() as u8]).await?;
+        socket.write_all(peer).await?;
+        socket.write_all(&gossip.payload).await?;
+
+        let age = cmp::min(gossip.age.as_secs(), u32::max_value() as u64) as u32;
+        socket.write_all(&age.to_be_bytes()).await?;
+    }
+
+    match &status.signature {
+        Some(signature) => {
+            let key = signature.key.clone().into_protobuf_encoding();
+            write_bytes(socket, &key).await?;
+            write_bytes(socket, &signature.bytes).await?;
+        },
+        None => socket.write_all(&0u16.to_be_bytes()).await?,
+    }
+
+    let name = status.name().unwrap_or("");
+    write_bytes(socket, name.as_bytes()).await?;
+
+    if status.epoch.is_none() && status.schema.is_none() && status.capabilities.is_none() && !status.withheld {
+        return Ok(())
+    }
+    socket.write_all(&status.epoch.unwrap_or(0).to_be_bytes()).await?;
+
+    let schema = status.schema().unwrap_or("");
+    write_bytes(socket, schema.as_bytes()).await?;
+
+    if status.capabilities.is_none() && !status.withheld {
+        return Ok(())
+    }
+    let capabilities = status.capabilities.unwrap_or_else(Capabilities::empty);
+    socket.write_all(&capabilities.bits().to_be_bytes()).await?;
+
+    if status.withheld {
+        socket.write_all(&[FLAG_WITHHELD]).await?;
+    }
+    Ok(())
+}
+
+/// Writes the data preceded by its length as 2 bytes, big endian.
+async fn write_bytes<S>(socket: &mut S, data: &[u8]) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    if data.len() > u16::max_value() as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Data too long for status frame"))
+    }
+
+    socket.write_all(&(data.len() as u16).to_be_bytes()).await?;
+    socket.write_all(data).await
+}
+
+/// Reads data preceded by its length as 2 bytes, big endian.
+async fn read_bytes<S>(socket: &mut S) -> io::Result<Vec<u8>>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut len = [0u8; 2];
+    socket.read_exact(&mut len).await?;
+
+    let mut data = vec![0u8; u16::from_be_bytes(len) as usize];
+    socket.read_exact(&mut data).await?;
+
+    Ok(data)
+}
+
+/// Reads a status frame as written by the listener.
+pub async fn read_response<S>(socket: &mut S) -> Result<Response, DecodeError>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut version = [0u8; 1];
+    socket.read_exact(&mut version).await?;
+    let version = version[0];
+
+    if version == 0 {
+        return Err(DecodeError::UnsupportedVersion { version })
+    }
+
+    let mut payload = [0u8; 20];
+    socket.read_exact(&mut payload).await?;
+
+    let mut count = [0u8; 1];
+    socket.read_exact(&mut count).await?;
+
+    let mut gossip = Vec::with_capacity(count[0] as usize);
+    for _ in 0..count[0] {
+        let mut len = [0u8; 1];
+        socket.read_exact(&mut len).await?;
+
+        if len[0] == 0 {
+            return Err(DecodeError::BadLength)
+        }
+
+        let mut peer = vec![0u8; len[0] as usize];
+        socket.read_exact(&mut peer).await?;
+        let peer = PeerId::from_bytes(peer).map_err(|_| DecodeError::BadPeerId)?;
+
+        let mut payload = [0u8; 20];
+        socket.read_exact(&mut payload).await?;
+
+        let mut age = [0u8; 4];
+        socket.read_exact(&mut age).await?;
+        let age = Duration::from_secs(u32::from_be_bytes(age) as u64);
+
+        gossip.push(Gossip { peer, payload, age });
+    }
+
+    let key = read_bytes(socket).await?;
+    let signature = if key.is_empty() {
+        None
+    } else {
+        let key = PublicKey::from_protobuf_encoding(&key).map_err(|_| DecodeError::BadSignature)?;
+
+        let bytes = read_bytes(socket).await?;
+        if bytes.is_empty() {
+            return Err(DecodeError::BadLength)
+        }
+
+        Some(Signature { key, bytes })
+    };
+
+    let name = match read_bytes(socket).await {
+        Ok(name) if name.is_empty() => None,
+        Ok(name) => Some(String::from_utf8(name).map_err(|_| DecodeError::BadName)?),
+        // Sent by a peer that does not know about names yet
+        Err(ref error) if error.kind() == io::ErrorKind::UnexpectedEof => None,
+        Err(error) => return Err(error.into()),
+    };
+
+    // Sent by a peer that does not know about epochs yet, if missing
+    let mut epoch = Vec::with_capacity(8);
+    (&mut *socket).take(8).read_to_end(&mut epoch).await?;
+    let (extended, epoch) = match epoch.len() {
+        0 => (false, None),
+        // Zero is sent by a peer without an epoch but with a schema or capabilities
+        8 => (true, Some(epoch.iter().fold(0u64, |epoch, b| (epoch << 8) | u64::from(*b))).filter(|epoch| *epoch != 0)),
+        _ => return Err(DecodeError::Truncated),
+    };
+
+    let schema = if extended {
+        match read_bytes(socket).await {
+            Ok(schema) if schema.is_empty() => None,
+            Ok(schema) => Some(String::from_utf8(schema).map_err(|_| DecodeError::BadSchema)?),
+            // Sent by a peer that does not know about schemas yet
+            Err(ref error) if error.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(error) => return Err(error.into()),
+        }
+    } else {
+        None
+    };
+
+    // Sent by a peer that does not know about capabilities yet, if missing
+    let capabilities = if extended {
+        let mut bits = Vec::with_capacity(4);
+        (&mut *socket).take(4).read_to_end(&mut bits).await?;
+        match bits.len() {
+            0 => None,
+            4 => Some(Capabilities::from_bits(bits.iter().fold(0u32, |bits, b| (bits << 8) | u32::from(*b)))),
+            _ => return Err(DecodeError::Truncated),
+        }
+    } else {
+        None
+    };
+
+    // Left out unless any flag is set
+    let mut flags = Vec::with_capacity(1);
+    if capabilities.is_some() {
+        (&mut *socket).take(1).read_to_end(&mut flags).await?;
+    }
+    let withheld = flags.first().map_or(false, |flags| flags & FLAG_WITHHELD != 0);
+
+    Ok(Response { version, payload, gossip, signature, name, epoch, schema, capabilities, withheld })
+}
+
+/// Decodes a status frame held in memory as a whole, e.g. one captured
+/// from the wire. Trailing bytes are ignored.
+pub fn decode_response(frame: &[u8]) -> Result<Response, DecodeError> {
+    futures::executor::block_on(read_response(&mut futures::io::Cursor::new(frame)))
+}
+
+/// Reader that reads at most a chunk of bytes at once and keeps count of
+/// the bytes read so far, to observe the progress of large transfers.
+#[derive(Debug)]
+pub struct ChunkedReader<S> {
+    inner: S,
+    chunk: usize,
+    progress: Arc<AtomicUsize>,
+}
+
+impl<S> ChunkedReader<S> {
+    /// Wraps the socket, reading at most `chunk` bytes at once.
+    pub fn new(inner: S, chunk: usize) -> Self {
+        ChunkedReader { inner, chunk: cmp::max(chunk, 1), progress: Arc::new(AtomicUsize::new(0)) }
+    }
+
+    /// Shared counter of the bytes read so far.
+    pub fn progress(&self) -> Arc<AtomicUsize> {
+        self.progress.clone()
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ChunkedReader<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let len = cmp::min(buf.len(), this.chunk);
+
+        let result = Pin::new(&mut this.inner).poll_read(cx, &mut buf[..len]);
+        if let Poll::Ready(Ok(n)) = result {
+            this.progress.fetch_add(n, Ordering::SeqCst);
+        }
+        result
     }
 }
 
+/// Completes with an error once no progress has been made for the given
+/// duration, telling how many bytes were received until then.
+async fn stalled(progress: Arc<AtomicUsize>, clock: SharedClock, timeout: Duration) -> DecodeError {
+    let mut last = progress.load(Ordering::SeqCst);
+    let mut timer = Timer::new(clock, timeout);
+
+    loop {
+        (&mut timer).await;
+
+        let current = progress.load(Ordering::SeqCst);
+        if current == last {
+            return DecodeError::Stalled { received: current }
+        }
+        last = current;
+        timer.reset(timeout);
+    }
+}
 
 impl<TSocket> InboundUpgrade<TSocket> for Status
 where
@@ -63,8 +853,18 @@ where
     type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
 
     fn upgrade_inbound(self, mut socket: TSocket, _: Self::Info) -> Self::Future {
+        let admitted = match &self.admission {
+            Some(admission) if !admission.admit() => {
+                return future::err(io::Error::new(io::ErrorKind::Other, "Status request refused")).boxed()
+            },
+            Some(admission) => Some(Admitted(admission.clone())),
+            None => None,
+        };
+
         async move {
-            socket.write_all(&self.0).await?;
+            // Counts as open until answered or given up, e.g. on timeout
+            let _admitted = admitted;
+            write_status(&mut socket, &self.resolve()).await?;
             socket.flush().await?;
             Ok(())
         }.boxed()
@@ -75,25 +875,45 @@ impl<TSocket> OutboundUpgrade<TSocket> for Status
 where
     TSocket: AsyncRead + AsyncWrite + Send + Unpin + 'static,
 {
-    type Output = Payload;
-    type Error = io::Error;
+    type Output = (Response, String);
+    type Error = DecodeError;
     type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
 
-    fn upgrade_outbound(self, mut socket: TSocket, _: Self::Info) -> Self::Future {
+    fn upgrade_outbound(self, socket: TSocket, info: Self::Info) -> Self::Future {
         async move {
-            let mut payload = [0u8; 20];
-            socket.read_exact(&mut payload).await?;
-            Ok(payload)
+            // Read in chunks, giving up early if the transfer stalls
+            let mut socket = ChunkedReader::new(socket, CHUNK_SIZE);
+            let progress = socket.progress();
+            let read = read_response(&mut socket);
+
+            let response = match self.stall {
+                Some((clock, timeout)) => {
+                    let stall = stalled(progress, clock, timeout);
+                    futures::pin_mut!(stall, read);
+                    match future::select(read, stall).await {
+                        Either::Left((response, _)) => response?,
+                        Either::Right((error, _)) => return Err(error),
+                    }
+                },
+                None => read.await?,
+            };
+
+            Ok((response, String::from_utf8_lossy(&info).into_owned()))
         }.boxed()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Status;
-    use crate::status::generate_payload;
+    use super::{Capabilities, ChunkedReader, DecodeError, Gossip, PROTOCOL_NAME, Signature, Signer, Status, VERSION, WITHHELD, decode_response, read_response, write_status};
+    use std::{borrow::Cow, io, pin::Pin, sync::atomic::Ordering, task::{Context, Poll}};
+    use libp2p::core::{OutboundUpgrade, UpgradeInfo};
+    use crate::clock::ManualClock;
+    use crate::status::{generate_payload, provider::PayloadProvider};
     use futures::prelude::*;
     use libp2p::core::{
+        PeerId,
+        identity::Keypair,
         upgrade,
         multiaddr::multiaddr,
         transport::{
@@ -103,10 +923,17 @@ mod tests {
         }
     };
     use rand::{thread_rng, Rng};
+    use std::sync::{Arc, atomic::AtomicU8};
     use std::time::Duration;
 
-    #[test]
-    fn status_send_recv() {
+    /// Answers a single status request with the given upgrade and returns
+    /// the response received by the dialer and the negotiated protocol.
+    fn exchange(status: Status) -> (super::Response, String) {
+        try_exchange(status, Status::default()).unwrap()
+    }
+
+    /// Answers the request of the dialer with the status, if they agree on the protocol.
+    fn try_exchange(status: Status, dialer: Status) -> Result<(super::Response, String), upgrade::UpgradeError<DecodeError>> {
         let mem_addr = multiaddr![Memory(thread_rng().gen::<u64>())];
         let mut listener = MemoryTransport.listen_on(mem_addr).unwrap();
 
@@ -117,19 +944,360 @@ mod tests {
                 panic!("MemoryTransport not listening on an address!");
             };
 
-        let payload = generate_payload();
-
         async_std::task::spawn(async move {
             let listener_event = listener.next().await.unwrap();
             let (listener_upgrade, _) = listener_event.unwrap().into_upgrade().unwrap();
             let conn = listener_upgrade.await.unwrap();
-            upgrade::apply_inbound(conn, Status(payload)).await.unwrap();
+            let _ = upgrade::apply_inbound(conn, status).await;
         });
 
         async_std::task::block_on(async move {
             let c = MemoryTransport.dial(listener_addr).unwrap().await.unwrap();
-            let received = upgrade::apply_outbound(c, Status([0u8; 20]), upgrade::Version::V1).await.unwrap();
-            assert!(received == payload);
-        });
+            upgrade::apply_outbound(c, dialer, upgrade::Version::V1).await
+        })
+    }
+
+    /// Socket delivering the given bytes and then nothing at all, as if the
+    /// remote stopped sending halfway.
+    struct Stalling(futures::io::Cursor<Vec<u8>>);
+
+    impl AsyncRead for Stalling {
+        fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+            match Pin::new(&mut self.0).poll_read(cx, buf) {
+                Poll::Ready(Ok(0)) => Poll::Pending,
+                result => result,
+            }
+        }
+    }
+
+    impl AsyncWrite for Stalling {
+        fn poll_write(self: Pin<&mut Self>, _: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>> {
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn status_send_recv() {
+        let payload = generate_payload();
+
+        let (received, protocol) = exchange(Status::new(payload));
+
+        assert_eq!(protocol.as_bytes(), &*Status::default().protocol_info().next().unwrap());
+        assert_eq!(received.version, VERSION);
+        assert!(received.payload == payload);
+        assert!(received.gossip.is_empty());
+        assert!(received.signature.is_none());
+        assert!(received.name.is_none());
+    }
+
+    #[test]
+    fn status_protocol_name() {
+        let payload = generate_payload();
+        let testnet = || Cow::Borrowed(&b"/dx/testnet/status/0.1.0"[..]);
+
+        // Peers of the same network agree on the protocol
+        let (received, protocol) = try_exchange(
+            Status::new(payload).with_protocol_name(testnet()),
+            Status::default().with_protocol_name(testnet()),
+        ).unwrap();
+        assert!(received.payload == payload);
+        assert_eq!(protocol, "/dx/testnet/status/0.1.0");
+
+        // Those of other networks do not
+        let other = Cow::Borrowed(&b"/dx/mainnet/status/0.1.0"[..]);
+        assert!(try_exchange(Status::new(payload).with_protocol_name(testnet()), Status::default().with_protocol_name(other)).is_err());
+        assert!(try_exchange(Status::new(payload).with_protocol_name(testnet()), Status::default()).is_err());
+    }
+
+    #[test]
+    fn status_builder() {
+        let key = Keypair::generate_ed25519();
+        let peer = key.public().into_peer_id();
+        let dialer = PeerId::random();
+        let payload = generate_payload();
+        let gossip = Gossip { peer: PeerId::random(), payload: generate_payload(), age: Duration::from_secs(5) };
+
+        let status = Status::new(payload)
+            .with_gossip(vec![gossip.clone()])
+            .with_signature(Some(Signature::sign(&key, &payload, Some(7), &dialer).unwrap()))
+            .with_name(Some("alice".into()))
+            .with_epoch(Some(7))
+            .with_schema(Some("git-sha1".into()));
+
+        let resolved = status.clone().resolve();
+        assert!(resolved.payload() == &payload);
+        assert!(resolved.signature().unwrap().verify(&peer, &payload, Some(7), &dialer));
+        assert_eq!(status.gossip().len(), 1);
+        assert_eq!(status.name(), Some("alice"));
+        assert_eq!(status.epoch(), Some(7));
+        assert_eq!(status.schema(), Some("git-sha1"));
+
+        // Everything set arrives on the other side
+        let (received, _) = exchange(status);
+        assert!(received.payload == payload);
+        assert_eq!(received.gossip[0].peer, gossip.peer);
+        assert!(received.signature.unwrap().verify(&peer, &payload, received.epoch, &dialer));
+        assert_eq!(received.name.as_ref().map(String::as_str), Some("alice"));
+        assert_eq!(received.epoch, Some(7));
+        assert_eq!(received.schema.as_ref().map(String::as_str), Some("git-sha1"));
+    }
+
+    #[test]
+    fn status_from_provider() {
+        /// Counts up with every status provided
+        #[derive(Debug, Default)]
+        struct Counter(AtomicU8);
+
+        impl PayloadProvider for Counter {
+            fn current(&self) -> super::Payload {
+                [self.0.fetch_add(1, Ordering::SeqCst); 20]
+            }
+        }
+
+        let key = Keypair::generate_ed25519();
+        let peer = key.public().into_peer_id();
+        let dialer = PeerId::random();
+        let status = Status::from_provider(Arc::new(Counter::default()), Some(Signer::new(key)))
+            .with_recipient(Some(dialer.clone()));
+
+        // Same upgrade answers each request with the payload current at the time
+        let (first, _) = exchange(status.clone());
+        let (second, _) = exchange(status);
+        assert_eq!(first.payload, [0; 20]);
+        assert_eq!(second.payload, [1; 20]);
+        assert!(first.signature.unwrap().verify(&peer, &first.payload, None, &dialer));
+        assert!(second.signature.unwrap().verify(&peer, &second.payload, None, &dialer));
+    }
+
+    #[test]
+    fn status_version_mismatch() {
+        let payload = generate_payload();
+
+        let mut frame = vec![VERSION + 1];
+        frame.extend_from_slice(&payload);
+        frame.extend_from_slice(&[0, 0, 0]);
+
+        let received = futures::executor::block_on(
+            read_response(&mut futures::io::Cursor::new(frame))).unwrap();
+
+        assert_eq!(received.version, VERSION + 1);
+        assert!(received.payload == payload);
+        assert!(received.name.is_none());
+    }
+
+    #[test]
+    fn status_gossip() {
+        // Node A answers node C with its own status and the one it knows of node B
+        let a = generate_payload();
+        let b = generate_payload();
+        let b_id = PeerId::random();
+
+        let gossip = Gossip { peer: b_id.clone(), payload: b, age: Duration::from_secs(3) };
+        let (received, _) = exchange(Status::new(a).with_gossip(vec![gossip]));
+
+        assert!(received.payload == a);
+        assert_eq!(received.gossip.len(), 1);
+        assert_eq!(received.gossip[0].peer, b_id);
+        assert!(received.gossip[0].payload == b);
+        assert_eq!(received.gossip[0].age, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn status_signature() {
+        let key = Keypair::generate_ed25519();
+        let peer = key.public().into_peer_id();
+        let dialer = PeerId::random();
+        let payload = generate_payload();
+
+        let signature = Signature::sign(&key, &payload, Some(1), &dialer).unwrap();
+        let (received, _) = exchange(Status::new(payload).with_epoch(Some(1)).with_signature(Some(signature)));
+
+        let signature = received.signature.expect("Signature not received");
+        assert!(signature.verify(&peer, &received.payload, received.epoch, &dialer));
+
+        // Tampered payload
+        assert!(!signature.verify(&peer, &generate_payload(), Some(1), &dialer));
+
+        // Replayed to another peer or after a restart
+        assert!(!signature.verify(&peer, &payload, Some(1), &PeerId::random()));
+        assert!(!signature.verify(&peer, &payload, Some(2), &dialer));
+
+        // Signed with the key of another peer
+        let other = Signature::sign(&Keypair::generate_ed25519(), &payload, Some(1), &dialer).unwrap();
+        assert!(!other.verify(&peer, &payload, Some(1), &dialer));
+    }
+
+    #[test]
+    fn status_chunked() {
+        // Frame of several chunks, due to a large signature
+        let payload = generate_payload();
+        let key = Keypair::generate_ed25519().public();
+        let bytes: Vec<u8> = (0..40_000).map(|_| thread_rng().gen()).collect();
+        let status = Status::new(payload).with_signature(Some(Signature { key, bytes: bytes.clone() }));
+
+        let mut frame = futures::io::Cursor::new(Vec::new());
+        futures::executor::block_on(write_status(&mut frame, &status.resolve())).unwrap();
+        let frame = frame.into_inner();
+
+        let mut reader = ChunkedReader::new(futures::io::Cursor::new(frame.clone()), 1024);
+        let progress = reader.progress();
+        let received = futures::executor::block_on(read_response(&mut reader)).unwrap();
+
+        assert!(received.payload == payload);
+        assert_eq!(received.signature.unwrap().bytes, bytes);
+        assert_eq!(progress.load(Ordering::SeqCst), frame.len());
+    }
+
+    #[test]
+    fn status_stalled() {
+        let mut frame = futures::io::Cursor::new(Vec::new());
+        futures::executor::block_on(write_status(&mut frame, &Status::new(generate_payload()).resolve())).unwrap();
+        let partial = frame.into_inner()[..5].to_vec();
+
+        let clock = ManualClock::new();
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        // Gives up once nothing arrived for the stall timeout of the clock
+        let status = Status::default().with_stall_timeout(Arc::new(clock.clone()), Duration::from_secs(3));
+        let mut response = status.upgrade_outbound(Stalling(futures::io::Cursor::new(partial.clone())), PROTOCOL_NAME.into());
+        assert!(response.poll_unpin(&mut cx).is_pending());
+
+        clock.advance(Duration::from_secs(2));
+        assert!(response.poll_unpin(&mut cx).is_pending());
+
+        clock.advance(Duration::from_secs(1));
+        match response.poll_unpin(&mut cx) {
+            Poll::Ready(Err(DecodeError::Stalled { received })) => assert_eq!(received, 5),
+            other => panic!("Unexpected result: {:?}", other.map(|r| r.map(|_| ()))),
+        }
+
+        // Without a stall timeout only the request timeout applies
+        let mut response = Status::default().upgrade_outbound(Stalling(futures::io::Cursor::new(partial)), PROTOCOL_NAME.into());
+        assert!(response.poll_unpin(&mut cx).is_pending());
+        clock.advance(Duration::from_secs(60));
+        assert!(response.poll_unpin(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn status_name() {
+        let payload = generate_payload();
+        let (received, _) = exchange(Status::new(payload).with_name(Some("alice".into())));
+
+        assert_eq!(received.name.as_ref().map(String::as_str), Some("alice"));
+    }
+
+    #[test]
+    fn status_epoch() {
+        let payload = generate_payload();
+
+        let (received, _) = exchange(Status::new(payload).with_epoch(Some(0x0123_4567_89ab_cdef)));
+        assert_eq!(received.epoch, Some(0x0123_4567_89ab_cdef));
+
+        let (received, _) = exchange(Status::new(payload));
+        assert_eq!(received.epoch, None);
+
+        let (received, _) = exchange(Status::new(payload).with_epoch(Some(0)));
+        assert_eq!(received.epoch, None);
+    }
+
+    #[test]
+    fn status_schema() {
+        let payload = generate_payload();
+
+        let (received, _) = exchange(Status::new(payload).with_epoch(Some(1)).with_schema(Some("git-sha1".into())));
+        assert_eq!(received.epoch, Some(1));
+        assert_eq!(received.schema.as_ref().map(String::as_str), Some("git-sha1"));
+
+        let (received, _) = exchange(Status::new(payload).with_epoch(Some(1)));
+        assert_eq!(received.schema, None);
+
+        // Sent even without an epoch
+        let (received, _) = exchange(Status::new(payload).with_schema(Some("git-sha1".into())));
+        assert_eq!(received.epoch, None);
+        assert_eq!(received.schema.as_ref().map(String::as_str), Some("git-sha1"));
+    }
+
+    #[test]
+    fn status_withheld() {
+        let (received, _) = exchange(Status::withheld());
+        assert!(received.withheld);
+        assert_eq!(received.payload, WITHHELD);
+
+        // Indistinguishable by payload alone
+        let (received, _) = exchange(Status::new(WITHHELD).with_epoch(Some(1)).with_capabilities(Some(Capabilities::all())));
+        assert!(!received.withheld);
+        assert_eq!(received.capabilities, Some(Capabilities::all()));
+    }
+
+    #[test]
+    fn status_capabilities() {
+        let payload = generate_payload();
+
+        let (received, _) = exchange(Status::new(payload).with_epoch(Some(1)).with_capabilities(Some(Capabilities::GOSSIP)));
+        assert_eq!(received.capabilities, Some(Capabilities::GOSSIP));
+
+        // Older listeners send none
+        let (received, _) = exchange(Status::new(payload).with_epoch(Some(1)).with_schema(Some("git-sha1".into())));
+        assert_eq!(received.capabilities, None);
+
+        let ours = Capabilities::all();
+        let theirs = Capabilities::from_bits(Capabilities::GOSSIP.bits() | 1 << 31);
+        assert_eq!(ours.intersection(theirs), Capabilities::GOSSIP);
+        assert!(ours.contains(Capabilities::SIGNATURES));
+        assert!(!theirs.contains(Capabilities::SIGNATURES));
+    }
+
+    #[test]
+    fn decode_errors() {
+        let decode = |frame: Vec<u8>| decode_response(&frame);
+
+        // Valid frame up to the gossip count, followed by the given bytes
+        let frame = |rest: &[u8]| {
+            let mut frame = vec![VERSION];
+            frame.extend_from_slice(&generate_payload());
+            frame.extend_from_slice(rest);
+            frame
+        };
+
+        match decode(vec![VERSION, 1, 2, 3]) {
+            Err(DecodeError::Truncated) => (),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        let mut unversioned = frame(&[0, 0, 0]);
+        unversioned[0] = 0;
+        match decode(unversioned) {
+            Err(DecodeError::UnsupportedVersion { version: 0 }) => (),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        match decode(frame(&[1, 0])) {
+            Err(DecodeError::BadLength) => (),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        match decode(frame(&[1, 3, 0xff, 0xff, 0xff])) {
+            Err(DecodeError::BadPeerId) => (),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        match decode(frame(&[0, 0, 3, 0xff, 0xff, 0xff])) {
+            Err(DecodeError::BadSignature) => (),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        match decode(frame(&[0, 0, 0, 0, 2, 0xc3, 0x28])) {
+            Err(DecodeError::BadName) => (),
+            other => panic!("Unexpected result: {:?}", other),
+        }
     }
 }
@@ -0,0 +1,28 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::status::protocol::Payload;
+
+
+/// Source of the status advertised to other peers.
+///
+/// The handler asks for the current status every time it answers a request,
+/// so that values changing over time are advertised without reconfiguring
+/// any connection.
+pub trait PayloadProvider: fmt::Debug + Send + Sync {
+    /// The status to advertise right now
+    fn current(&self) -> Payload;
+}
+
+/// Provider shared among handlers
+pub type SharedProvider = Arc<dyn PayloadProvider>;
+
+/// Provider of a status that never changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaticProvider(pub Payload);
+
+impl PayloadProvider for StaticProvider {
+    fn current(&self) -> Payload {
+        self.0
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+use libp2p::PeerId;
+
+
+/// Peers and the peers they know of, as adjacency list
+pub type Topology = Vec<(PeerId, Vec<PeerId>)>;
+
+/// Display name of peer as quoted string, falling back to its id
+fn quoted(id: &PeerId, names: &HashMap<PeerId, String>) -> String {
+    let name = names.get(id).cloned().unwrap_or_else(|| id.to_base58());
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Render topology as directed graph in DOT format, e.g. for Graphviz
+pub fn render_dot(topology: &Topology, names: &HashMap<PeerId, String>) -> String {
+    let mut dot = String::from("digraph dx {\n");
+
+    for (peer, closest) in topology.iter() {
+        for other in closest.iter() {
+            dot += &format!("    {} -> {};\n", quoted(peer, names), quoted(other, names));
+        }
+    }
+
+    dot += "}\n";
+    dot
+}
+
+/// Render topology as JSON object mapping every peer to the list of peers it knows of
+pub fn render_json(topology: &Topology, names: &HashMap<PeerId, String>) -> String {
+    let entries: Vec<String> = topology.iter()
+        .map(|(peer, closest)| {
+            let closest: Vec<String> = closest.iter().map(|other| quoted(other, names)).collect();
+            format!("{}: [{}]", quoted(peer, names), closest.join(", "))
+        })
+        .collect();
+
+    format!("{{{}}}\n", entries.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn graph_rendering() {
+        let alice = PeerId::random();
+        let bob = PeerId::random();
+        let carol = PeerId::random();
+
+        let mut names = HashMap::new();
+        names.insert(alice.clone(), "alice".to_owned());
+        names.insert(bob.clone(), "bob".to_owned());
+
+        let topology = vec![
+            (alice.clone(), vec![bob.clone(), carol.clone()]),
+            (bob.clone(), vec![alice.clone()]),
+        ];
+
+        assert_eq!(render_dot(&topology, &names), format!(
+"digraph dx {{
+    \"alice\" -> \"bob\";
+    \"alice\" -> \"{carol}\";
+    \"bob\" -> \"alice\";
+}}
+", carol = carol.to_base58()));
+
+        assert_eq!(render_json(&topology, &names),
+            format!("{{\"alice\": [\"bob\", \"{}\"], \"bob\": [\"alice\"]}}\n", carol.to_base58()));
+    }
+}
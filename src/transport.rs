@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::{future::BoxFuture, prelude::*};
+
+use log::debug;
+
+use libp2p::{
+    Multiaddr,
+    PeerId,
+    Transport,
+    core::{
+        InboundUpgrade,
+        OutboundUpgrade,
+        UpgradeInfo,
+        multiaddr::Protocol,
+        transport::TransportError,
+        upgrade::ProtocolName,
+    },
+};
+
+
+/// Security protocol negotiated for the connection to each peer, e.g.
+/// `/secio/1.0.0`, as recorded by [`RecordSecurity`]
+pub type SecurityTable = Arc<Mutex<HashMap<PeerId, String>>>;
+
+/// Names of all multiaddr protocols, as used in their textual form
+const PROTOCOL_NAMES: [&str; 24] = [
+    "dccp", "dns4", "dns6", "http", "https", "ip4", "ip6", "memory",
+    "onion", "onion3", "p2p", "p2p-circuit", "p2p-webrtc-direct", "p2p-webrtc-star",
+    "p2p-websocket-star", "quic", "sctp", "tcp", "udp", "udt", "unix", "utp", "ws", "wss",
+];
+
+/// Whether the given name is that of a multiaddr protocol, e.g. `tcp`
+pub fn is_protocol_name(name: &str) -> bool {
+    PROTOCOL_NAMES.contains(&name)
+}
+
+/// Multiaddr protocols that may be dialed, e.g. `["ip4", "ip6", "tcp"]`
+#[derive(Debug, Clone, Default)]
+pub struct ProtocolAllowlist {
+    /// Allowed protocol names, `None` allows all protocols
+    protocols: Option<Vec<String>>,
+}
+
+impl ProtocolAllowlist {
+    /// Allow only addresses made up of the given protocols.
+    ///
+    /// Names are taken as they are, see [`is_protocol_name`] to check them.
+    pub fn new<I, S>(protocols: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        ProtocolAllowlist { protocols: Some(protocols.into_iter().map(Into::into).collect()) }
+    }
+
+    /// Whether every protocol of the address is allowed
+    pub fn allows(&self, addr: &Multiaddr) -> bool {
+        match &self.protocols {
+            Some(allowed) => addr.iter().all(|p| allowed.contains(&name(&p))),
+            None => true,
+        }
+    }
+}
+
+/// Delay between dialing the two families when racing them, as recommended
+/// by RFC 8305
+pub const RACE_DELAY: Duration = Duration::from_millis(250);
+
+/// Order in which the address families of a peer are tried when dialing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressPreference {
+    /// Try addresses in the order they were learned, regardless of family
+    Any,
+    /// Try IPv4 addresses before IPv6 ones
+    Ipv4,
+    /// Try IPv6 addresses before IPv4 ones
+    Ipv6,
+    /// Dial the best IPv6 address, then the best IPv4 address unless
+    /// connected within [`RACE_DELAY`], alternating between families afterwards
+    HappyEyeballs,
+}
+
+impl Default for AddressPreference {
+    fn default() -> Self {
+        AddressPreference::Any
+    }
+}
+
+/// Whether the address is an IPv6 one, `None` if it is neither IPv4 nor IPv6
+fn is_ipv6(addr: &Multiaddr) -> Option<bool> {
+    addr.iter().find_map(|p| match p {
+        Protocol::Ip4(_) => Some(false),
+        Protocol::Ip6(_) => Some(true),
+        _ => None,
+    })
+}
+
+impl AddressPreference {
+    /// Order addresses by preference, keeping their relative order within a
+    /// family. Addresses of neither family are tried last.
+    pub fn order(&self, addrs: Vec<Multiaddr>) -> Vec<Multiaddr> {
+        if *self == AddressPreference::Any {
+            return addrs
+        }
+
+        let (mut v6, mut v4, mut other) = (Vec::new(), Vec::new(), Vec::new());
+        for addr in addrs {
+            match is_ipv6(&addr) {
+                Some(true) => v6.push(addr),
+                Some(false) => v4.push(addr),
+                None => other.push(addr),
+            }
+        }
+
+        let mut ordered = match self {
+            AddressPreference::Any | AddressPreference::Ipv4 => { v4.append(&mut v6); v4 },
+            AddressPreference::Ipv6 => { v6.append(&mut v4); v6 },
+            AddressPreference::HappyEyeballs => {
+                let mut ordered = Vec::with_capacity(v6.len() + v4.len());
+                let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+                loop {
+                    match (v6.next(), v4.next()) {
+                        (None, None) => break ordered,
+                        (a, b) => ordered.extend(a.into_iter().chain(b)),
+                    }
+                }
+            },
+        };
+
+        ordered.append(&mut other);
+        ordered
+    }
+
+    /// Addresses to be raced, out of addresses already in order.
+    ///
+    /// This is the best address of each family for happy eyeballs, the
+    /// second of which is dialed [`RACE_DELAY`] after the first, and nothing
+    /// otherwise, in which case the addresses are tried in order.
+    pub fn race(&self, ordered: &[Multiaddr]) -> Vec<Multiaddr> {
+        if *self != AddressPreference::HappyEyeballs {
+            return Vec::new()
+        }
+
+        let v6 = ordered.iter().find(|addr| is_ipv6(addr) == Some(true));
+        let v4 = ordered.iter().find(|addr| is_ipv6(addr) == Some(false));
+
+        match (v6, v4) {
+            (Some(v6), Some(v4)) => vec![v6.clone(), v4.clone()],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Name of protocol as used in its textual form, e.g. `ip4`
+fn name(protocol: &Protocol) -> String {
+    protocol.to_string().split('/').nth(1).unwrap_or_default().to_owned()
+}
+
+/// Transport that refuses to dial addresses not covered by an allowlist.
+///
+/// Refused dials fail with [`TransportError::MultiaddrNotSupported`] before
+/// any connection is attempted and are reported as unreachable addresses.
+#[derive(Debug, Clone)]
+pub struct FilteredTransport<T> {
+    inner: T,
+    allowlist: ProtocolAllowlist,
+}
+
+impl<T> FilteredTransport<T> {
+    pub fn new(inner: T, allowlist: ProtocolAllowlist) -> Self {
+        FilteredTransport { inner, allowlist }
+    }
+}
+
+impl<T: Transport> Transport for FilteredTransport<T> {
+    type Output = T::Output;
+    type Error = T::Error;
+    type Listener = T::Listener;
+    type ListenerUpgrade = T::ListenerUpgrade;
+    type Dial = T::Dial;
+
+    fn listen_on(self, addr: Multiaddr) -> Result<Self::Listener, TransportError<Self::Error>> {
+        self.inner.listen_on(addr)
+    }
+
+    fn dial(self, addr: Multiaddr) -> Result<Self::Dial, TransportError<Self::Error>> {
+        if self.allowlist.allows(&addr) {
+            self.inner.dial(addr)
+        } else {
+            debug!("Refusing to dial {}: protocol not allowed", addr);
+            Err(TransportError::MultiaddrNotSupported(addr))
+        }
+    }
+}
+
+/// Security upgrade recording the name of the protocol negotiated for each
+/// authenticated connection in a [`SecurityTable`].
+///
+/// Only the protocol is known, as the cipher suite negotiated within it is
+/// not reported by libp2p. Entries are removed by the behaviour once the
+/// connection is closed, see [`crate::behaviour::BehaviourConfig::with_security_table`].
+#[derive(Debug, Clone)]
+pub struct RecordSecurity<U> {
+    inner: U,
+    table: SecurityTable,
+}
+
+impl<U> RecordSecurity<U> {
+    pub fn new(inner: U, table: SecurityTable) -> Self {
+        RecordSecurity { inner, table }
+    }
+}
+
+impl<U: UpgradeInfo> UpgradeInfo for RecordSecurity<U> {
+    type Info = U::Info;
+    type InfoIter = U::InfoIter;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.inner.protocol_info()
+    }
+}
+
+/// Record the protocol of the connection once authenticated
+fn record<F, D, E>(upgrade: F, protocol: String, table: SecurityTable) -> BoxFuture<'static, Result<(PeerId, D), E>>
+where
+    F: Future<Output = Result<(PeerId, D), E>> + Send + 'static,
+{
+    upgrade.map_ok(move |(peer, output)| {
+        table.lock().unwrap().insert(peer.clone(), protocol);
+        (peer, output)
+    }).boxed()
+}
+
+impl<C, D, U> InboundUpgrade<C> for RecordSecurity<U>
+where
+    U: InboundUpgrade<C, Output = (PeerId, D)>,
+    U::Future: Send + 'static,
+{
+    type Output = (PeerId, D);
+    type Error = U::Error;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, socket: C, info: Self::Info) -> Self::Future {
+        let protocol = String::from_utf8_lossy(info.protocol_name()).into_owned();
+        record(self.inner.upgrade_inbound(socket, info), protocol, self.table)
+    }
+}
+
+impl<C, D, U> OutboundUpgrade<C> for RecordSecurity<U>
+where
+    U: OutboundUpgrade<C, Output = (PeerId, D)>,
+    U::Future: Send + 'static,
+{
+    type Output = (PeerId, D);
+    type Error = U::Error;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, socket: C, info: Self::Info) -> Self::Future {
+        let protocol = String::from_utf8_lossy(info.protocol_name()).into_owned();
+        record(self.inner.upgrade_outbound(socket, info), protocol, self.table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FilteredTransport, ProtocolAllowlist, RecordSecurity, SecurityTable, is_protocol_name};
+    use futures::{future::{self, BoxFuture}, prelude::*};
+    use libp2p::{
+        PeerId,
+        Transport,
+        core::{
+            InboundUpgrade,
+            OutboundUpgrade,
+            UpgradeInfo,
+            multiaddr::multiaddr,
+            transport::{ListenerEvent, TransportError, memory::MemoryTransport},
+            upgrade,
+        },
+    };
+    use rand::{thread_rng, Rng};
+
+    /// Security upgrade supporting the given protocols, that authenticates
+    /// the remote as the given peer without further ado
+    #[derive(Clone)]
+    struct Insecure(Vec<&'static [u8]>, PeerId);
+
+    impl UpgradeInfo for Insecure {
+        type Info = &'static [u8];
+        type InfoIter = std::vec::IntoIter<Self::Info>;
+
+        fn protocol_info(&self) -> Self::InfoIter {
+            self.0.clone().into_iter()
+        }
+    }
+
+    impl<C: Send + 'static> InboundUpgrade<C> for Insecure {
+        type Output = (PeerId, C);
+        type Error = std::io::Error;
+        type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+        fn upgrade_inbound(self, socket: C, _: Self::Info) -> Self::Future {
+            future::ok((self.1, socket)).boxed()
+        }
+    }
+
+    impl<C: Send + 'static> OutboundUpgrade<C> for Insecure {
+        type Output = (PeerId, C);
+        type Error = std::io::Error;
+        type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+        fn upgrade_outbound(self, socket: C, _: Self::Info) -> Self::Future {
+            future::ok((self.1, socket)).boxed()
+        }
+    }
+
+    #[test]
+    fn refuse_disallowed_dial() {
+        let transport = FilteredTransport::new(MemoryTransport, ProtocolAllowlist::new(vec!["memory"]));
+
+        let addr = multiaddr![Memory(thread_rng().gen::<u64>())];
+        let _listener = MemoryTransport.listen_on(addr.clone()).unwrap();
+
+        assert!(transport.clone().dial(addr).is_ok());
+
+        match transport.dial("/dns4/example.com/tcp/4001".parse().unwrap()) {
+            Err(TransportError::MultiaddrNotSupported(_)) => (),
+            _ => panic!("Disallowed address was dialed"),
+        }
+    }
+
+    #[test]
+    fn known_protocol_names() {
+        let addr: libp2p::Multiaddr = "/ip4/127.0.0.1/tcp/4001/p2p/QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ".parse().unwrap();
+        assert!(addr.iter().all(|p| is_protocol_name(&super::name(&p))));
+
+        assert!(!is_protocol_name("ipv4"));
+        assert!(!is_protocol_name("/tcp"));
+    }
+
+    #[test]
+    fn record_negotiated_security() {
+        let (dialer, listener) = (PeerId::random(), PeerId::random());
+        let (dialer_table, listener_table) = (SecurityTable::default(), SecurityTable::default());
+
+        let addr = multiaddr![Memory(thread_rng().gen::<u64>())];
+        let mut listening = MemoryTransport.listen_on(addr.clone()).unwrap();
+
+        // The listener only supports the protocol the dialer prefers least
+        let security = RecordSecurity::new(Insecure(vec![b"/plain/2.0.0"], dialer.clone()), listener_table.clone());
+        let accepted = async_std::task::spawn(async move {
+            let conn = loop {
+                if let ListenerEvent::Upgrade { upgrade, .. } = listening.next().await.unwrap().unwrap() {
+                    break upgrade.await.unwrap()
+                }
+            };
+            upgrade::apply_inbound(conn, security).await.unwrap();
+        });
+
+        let security = RecordSecurity::new(Insecure(vec![b"/plain/1.0.0", b"/plain/2.0.0"], listener.clone()), dialer_table.clone());
+        async_std::task::block_on(async move {
+            let conn = MemoryTransport.dial(addr).unwrap().await.unwrap();
+            upgrade::apply_outbound(conn, security, upgrade::Version::V1).await.unwrap();
+        });
+
+        async_std::task::block_on(accepted);
+
+        assert_eq!(dialer_table.lock().unwrap().get(&listener).map(String::as_str), Some("/plain/2.0.0"));
+        assert_eq!(listener_table.lock().unwrap().get(&dialer).map(String::as_str), Some("/plain/2.0.0"));
+    }
+}
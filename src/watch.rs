@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use libp2p::PeerId;
+
+use crate::behaviour::PeerSnapshot;
+use crate::status::Payload;
+
+
+/// Clear screen and move cursor to the top left corner
+pub const CLEAR: &str = "\x1b[2J\x1b[H";
+
+/// Resolve display name of peer, falling back to its advertised name and then its id
+fn name_of(peer: &PeerSnapshot, names: &HashMap<PeerId, String>) -> String {
+    names.get(&peer.id).cloned()
+        .or_else(|| peer.name.clone())
+        .unwrap_or_else(|| peer.id.to_base58())
+}
+
+/// Order peers by display name, then by id, so output does not depend on
+/// the order peers were discovered in
+pub fn sort_peers(peers: &mut [PeerSnapshot], names: &HashMap<PeerId, String>) {
+    peers.sort_by_cached_key(|peer| (name_of(peer, names), peer.id.to_base58()));
+}
+
+fn state(peer: &PeerSnapshot) -> &'static str {
+    if peer.online { "online" } else { "offline" }
+}
+
+fn age(d: Option<Duration>) -> String {
+    d.map_or("-".to_owned(), |d| format!("{}s", d.as_secs()))
+}
+
+/// Short form of status, similar to an abbreviated git hash
+fn short(payload: Option<Payload>) -> String {
+    payload.map_or("-".to_owned(), |p| p.iter().take(4).map(|b| format!("{:02x}", b)).collect())
+}
+
+fn rtt(d: Option<Duration>) -> String {
+    d.map_or("-".to_owned(), |d| format!("{}ms", d.as_millis()))
+}
+
+/// Render peer snapshot as aligned table
+pub fn render_table(peers: &[PeerSnapshot], names: &HashMap<PeerId, String>) -> String {
+    let rows: Vec<[String; 5]> = peers.iter()
+        .map(|peer| [
+            name_of(peer, names),
+            state(peer).to_owned(),
+            age(peer.last_seen),
+            short(peer.status),
+            rtt(peer.rtt),
+        ])
+        .collect();
+
+    let width = rows.iter()
+        .map(|row| row[0].len())
+        .chain(Some("NAME".len()))
+        .max().unwrap();
+
+    let mut table = format!("{:<w$}  {:<7}  {:>9}  {:<8}  {:>6}\n",
+        "NAME", "STATE", "LAST SEEN", "STATUS", "RTT", w = width);
+
+    for row in rows.iter() {
+        table += &format!("{:<w$}  {:<7}  {:>9}  {:<8}  {:>6}\n",
+            row[0], row[1], row[2], row[3], row[4], w = width);
+    }
+
+    table
+}
+
+/// Render single peer as plain line, used when not attached to a terminal
+pub fn render_line(peer: &PeerSnapshot, names: &HashMap<PeerId, String>) -> String {
+    format!("{} {} last_seen={} status={} rtt={}",
+        name_of(peer, names), state(peer), age(peer.last_seen), short(peer.status), rtt(peer.rtt))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_rendering() {
+        let alice = PeerId::random();
+        let bob = PeerId::random();
+
+        let mut names = HashMap::new();
+        names.insert(alice.clone(), "alice".to_owned());
+        names.insert(bob.clone(), "bob".to_owned());
+
+        let mut status = [0u8; 20];
+        status[..4].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+
+        let peers = vec![
+            PeerSnapshot {
+                id: alice,
+                online: true,
+                last_seen: Some(Duration::from_secs(3)),
+                status: Some(status),
+                rtt: Some(Duration::from_millis(12)),
+                name: None,
+            },
+            PeerSnapshot {
+                id: bob,
+                online: false,
+                last_seen: None,
+                status: None,
+                rtt: None,
+                name: None,
+            },
+        ];
+
+        assert_eq!(render_table(&peers, &names),
+"NAME   STATE    LAST SEEN  STATUS       RTT
+alice  online          3s  deadbeef    12ms
+bob    offline          -  -              -
+");
+    }
+}